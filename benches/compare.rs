@@ -0,0 +1,115 @@
+//! The same producer/consumer workload over this crate's queues and the
+//! popular alternatives: `std::sync::mpsc`, crossbeam-channel and rtrb.
+//! Run with `cargo bench --features compare-bench --bench compare`.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use ringbuf::mpsc_lockfree::MPSCRingBuffer;
+use ringbuf::spsc_lockfree::SPSCRingBuffer;
+use std::sync::mpsc;
+use std::thread;
+
+/// Messages sent per iteration.
+const MESSAGES: u64 = 1 << 18;
+
+/// Sends `MESSAGES` values from one thread to another. `push` returns the
+/// value back while the queue is full, `pop` returns `None` while it is
+/// empty.
+fn transfer<P, C>(
+    (mut tx, mut rx): (P, C),
+    push: impl Fn(&mut P, u64) -> Option<u64> + Send,
+    pop: impl Fn(&mut C) -> Option<u64> + Send,
+) where
+    P: Send,
+    C: Send,
+{
+    thread::scope(|scope| {
+        scope.spawn(move || {
+            for i in 0..MESSAGES {
+                let mut value = i;
+                while let Some(rejected) = push(&mut tx, value) {
+                    value = rejected;
+                    // The consumer may share the core on small machines.
+                    thread::yield_now();
+                }
+            }
+        });
+        scope.spawn(move || {
+            for _ in 0..MESSAGES {
+                loop {
+                    match pop(&mut rx) {
+                        Some(value) => {
+                            black_box(value);
+                            break;
+                        }
+                        None => thread::yield_now(),
+                    }
+                }
+            }
+        });
+    });
+}
+
+fn compare(c: &mut Criterion) {
+    let mut group = c.benchmark_group("compare");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(MESSAGES));
+    for capacity in [64, 1024] {
+        group.bench_with_input(BenchmarkId::new("ringbuf spsc", capacity), &capacity, |b, &capacity| {
+            b.iter(|| {
+                transfer(
+                    SPSCRingBuffer::new_split(capacity),
+                    |tx, v| tx.push(v).err().map(|e| e.into_inner()),
+                    |rx| rx.pop().ok(),
+                )
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("ringbuf mpsc", capacity), &capacity, |b, &capacity| {
+            b.iter(|| {
+                let ring = MPSCRingBuffer::new(capacity);
+                transfer(
+                    (ring.clone(), ring),
+                    |tx, v| tx.try_push(v).err().map(|e| e.into_inner()),
+                    |rx| rx.pop(),
+                )
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("std sync_channel", capacity), &capacity, |b, &capacity| {
+            b.iter(|| {
+                transfer(
+                    mpsc::sync_channel(capacity),
+                    |tx, v| match tx.try_send(v) {
+                        Ok(()) => None,
+                        Err(mpsc::TrySendError::Full(v)) => Some(v),
+                        Err(mpsc::TrySendError::Disconnected(_)) => unreachable!(),
+                    },
+                    |rx| rx.try_recv().ok(),
+                )
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("crossbeam bounded", capacity), &capacity, |b, &capacity| {
+            b.iter(|| {
+                transfer(
+                    crossbeam_channel::bounded(capacity),
+                    |tx, v| tx.try_send(v).err().map(|e| e.into_inner()),
+                    |rx| rx.try_recv().ok(),
+                )
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("rtrb", capacity), &capacity, |b, &capacity| {
+            b.iter(|| {
+                transfer(
+                    rtrb::RingBuffer::new(capacity),
+                    |tx, v| match tx.push(v) {
+                        Ok(()) => None,
+                        Err(rtrb::PushError::Full(v)) => Some(v),
+                    },
+                    |rx| rx.pop().ok(),
+                )
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, compare);
+criterion_main!(benches);
@@ -0,0 +1,71 @@
+//! Throughput of the MPSC channel with 1, 2, 4 and 8 producers against one
+//! consumer, for several capacities. Also prints how many pushes failed on
+//! a full queue per message sent, the share of the producers' work wasted
+//! to contention.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use ringbuf::mpsc_lockfree::channel;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+
+/// Messages sent per iteration, split across the producers.
+const MESSAGES: u64 = 1 << 18;
+
+/// Runs `producers` producers and one consumer over a channel of
+/// `capacity`, returns the number of failed pushes.
+fn run(producers: u64, capacity: usize) -> u64 {
+    let (tx, mut rx) = channel(capacity);
+    let failed = AtomicU64::new(0);
+    thread::scope(|scope| {
+        for _ in 0..producers {
+            let (tx, failed) = (tx.clone(), &failed);
+            scope.spawn(move || {
+                let mut misses = 0;
+                for i in 0..MESSAGES / producers {
+                    let mut value = i;
+                    while let Err(e) = tx.try_push(value) {
+                        value = e.into_inner();
+                        misses += 1;
+                        // The consumer may share the core on small machines.
+                        thread::yield_now();
+                    }
+                }
+                failed.fetch_add(misses, Ordering::Relaxed);
+            });
+        }
+        drop(tx);
+        scope.spawn(move || {
+            for value in rx.iter() {
+                black_box(value);
+            }
+        });
+    });
+    failed.into_inner()
+}
+
+fn mpsc_contention(c: &mut Criterion) {
+    let mut group = c.benchmark_group("mpsc_contention");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(MESSAGES));
+    for producers in [1, 2, 4, 8] {
+        for capacity in [64, 1024, 16384] {
+            let (mut failed, mut runs) = (0, 0);
+            let id = BenchmarkId::new(format!("{producers} producer(s)"), capacity);
+            group.bench_with_input(id, &capacity, |b, &capacity| {
+                b.iter(|| {
+                    failed += run(producers, capacity);
+                    runs += 1;
+                })
+            });
+            // No runs if skipped by a filter.
+            if runs > 0 {
+                let rate = failed as f64 / (runs * MESSAGES) as f64;
+                println!("{producers} producer(s), capacity {capacity}: {rate:.3} failed pushes per message");
+            }
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, mpsc_contention);
+criterion_main!(benches);
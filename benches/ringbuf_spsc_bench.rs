@@ -1,23 +1,23 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use ringbuf::spsc_bounded::SPSCRingBuffer;
+use ringbuf::spsc_array::ArrayRingBuffer;
 
 fn create(n: u64) {
     for _ in 0..n {
-        let rb : SPSCRingBuffer = SPSCRingBuffer::new(10);
+        let rb : ArrayRingBuffer = ArrayRingBuffer::with_capacity(10);
         assert_eq!(rb.capacity(), 10);
         assert_eq!(rb.size(), 0);
-        assert_eq!(rb.free(), rb.capacity() - rb.size() -1);
+        assert_eq!(rb.free(), rb.capacity() - rb.size());
     }
 }
 
 fn push(n: u64) {
     for _ in 0..n {
-        let mut rb : SPSCRingBuffer = SPSCRingBuffer::new(8);
+        let mut rb : ArrayRingBuffer = ArrayRingBuffer::with_capacity(8);
         for i in 0..7 {
-            assert!(rb.push(i));
+            assert!(rb.push(i).is_ok());
         }
         assert_eq!(rb.size(), 7);
-        assert_eq!(rb.free(), rb.capacity() - rb.size() -1);
+        assert_eq!(rb.free(), rb.capacity() - rb.size());
     }
 }
 
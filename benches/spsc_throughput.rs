@@ -0,0 +1,59 @@
+//! Producer/consumer throughput of the lock-free SPSC ring, in messages per
+//! second, for several capacities and numbers of concurrent thread pairs.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use ringbuf::spsc_lockfree::SPSCRingBuffer;
+use std::thread;
+
+/// Messages sent by each producer per iteration.
+const MESSAGES: u64 = 1 << 20;
+
+/// Runs `pairs` producer/consumer thread pairs, each over its own ring.
+fn run_pairs(pairs: usize, capacity: usize, batched: bool) {
+    thread::scope(|scope| {
+        for _ in 0..pairs {
+            let (mut tx, mut rx) = SPSCRingBuffer::new_split(capacity);
+            scope.spawn(move || {
+                if batched {
+                    tx.set_flush_every(capacity / 4);
+                }
+                for i in 0..MESSAGES {
+                    let mut value = i;
+                    loop {
+                        let pushed = if batched { tx.push_batched(value) } else { tx.push(value) };
+                        match pushed {
+                            Ok(_) => break,
+                            Err(e) => value = e.into_inner(),
+                        }
+                        // The consumer may share the core on small machines.
+                        thread::yield_now();
+                    }
+                }
+            });
+            scope.spawn(move || {
+                for _ in 0..MESSAGES {
+                    black_box(rx.pop_blocking().unwrap());
+                }
+            });
+        }
+    });
+}
+
+fn spsc_throughput(c: &mut Criterion) {
+    let mut group = c.benchmark_group("spsc_throughput");
+    group.sample_size(10);
+    for pairs in [1, 2] {
+        group.throughput(Throughput::Elements(MESSAGES * pairs as u64));
+        for capacity in [64, 1024, 16384] {
+            for batched in [false, true] {
+                let name = if batched { "push_batched" } else { "push" };
+                let id = BenchmarkId::new(format!("{name}/{pairs} pair(s)"), capacity);
+                group.bench_with_input(id, &capacity, |b, &capacity| b.iter(|| run_pairs(pairs, capacity, batched)));
+            }
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, spsc_throughput);
+criterion_main!(benches);
@@ -0,0 +1,299 @@
+//! A Disruptor-style broadcast ring: every `Consumer` sees every value, and
+//! the single `Producer` is gated on the slowest one, so a slot is only
+//! reused once all the consumers are done with it. For fan-out of a stream,
+//! such as market data, to several independent readers.
+//! Each consumer publishes its own read counter on its own cache line; the
+//! producer only scans them when the ring looks full from its last scan.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::cell::{Cell, UnsafeCell};
+use core::mem::MaybeUninit;
+
+use crate::error::{PopError, PushError};
+use crate::spsc_lockfree::CachePadded;
+use crate::sync::{Arc, AtomicBool, AtomicUsize, Ordering, SlotTracker};
+
+/// Read counter of one consumer.
+struct Cursor {
+    read: AtomicUsize,
+    /// Cleared when the consumer is dropped, so it no longer gates the
+    /// producer.
+    active: AtomicBool,
+}
+
+struct Shared<T> {
+    /// The slots before `write` are initialized, all of them once `filled`:
+    /// a value stays in place until the producer needs its slot again.
+    buffer: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    /// `capacity - 1`, the capacity is always a power of two.
+    mask: usize,
+    write: CachePadded<AtomicUsize>,
+    /// Set once every slot was written, `write` may wrap around after that.
+    filled: AtomicBool,
+    cursors: Box<[CachePadded<Cursor>]>,
+    /// Consumers not dropped yet.
+    active: AtomicUsize,
+    /// Set when the `Producer` is dropped.
+    producer_dropped: AtomicBool,
+    tracker: SlotTracker,
+}
+
+unsafe impl<T: Send + Sync> Sync for Shared<T> {}
+
+/// Creates a ring with `capacity` rounded up to the next power of two, and
+/// returns its producer and its `consumers` consumers.
+pub fn channel<T>(capacity: usize, consumers: usize) -> (Producer<T>, Vec<Consumer<T>>) {
+    let capacity = capacity.next_power_of_two();
+    let shared = Arc::new(Shared {
+        buffer: (0..capacity).map(|_| UnsafeCell::new(MaybeUninit::uninit())).collect(),
+        mask: capacity - 1,
+        write: CachePadded::new(AtomicUsize::new(0)),
+        filled: AtomicBool::new(false),
+        cursors: (0..consumers)
+            .map(|_| CachePadded::new(Cursor { read: AtomicUsize::new(0), active: AtomicBool::new(true) }))
+            .collect(),
+        active: AtomicUsize::new(consumers),
+        producer_dropped: AtomicBool::new(false),
+        tracker: SlotTracker::new(capacity),
+    });
+    let consumers = (0..consumers)
+        .map(|index| Consumer { shared: shared.clone(), index, cached_write: Cell::new(0) })
+        .collect();
+    (Producer { shared, write: 0, filled: false, cached_lag: 0 }, consumers)
+}
+
+/// The pushing half of a broadcast `channel`.
+pub struct Producer<T> {
+    shared: Arc<Shared<T>>,
+    /// Same as `shared.write`, only this producer stores it.
+    write: usize,
+    /// Same as `shared.filled`.
+    filled: bool,
+    /// Lag of the slowest consumer at the last scan of the cursors.
+    cached_lag: usize,
+}
+
+impl<T> Producer<T> {
+    /// Pushes a value for all the consumers. Fails with `Full` while the
+    /// slowest consumer is `capacity` values behind, or with `Disconnected`
+    /// once all the consumers are dropped, handing the value back.
+    pub fn push(&mut self, value: T) -> Result<(), PushError<T>> {
+        if self.is_disconnected() {
+            return Err(PushError::Disconnected(value));
+        }
+        if self.cached_lag == self.capacity() {
+            self.cached_lag = self.slowest_lag();
+            if self.cached_lag == self.capacity() {
+                return Err(PushError::Full(value));
+            }
+        }
+
+        let slot = self.write & self.shared.mask;
+        self.shared.tracker.write(slot);
+        unsafe {
+            let cell = &mut *self.shared.buffer[slot].get();
+            // Every consumer is past the value of the previous lap.
+            if self.filled {
+                cell.assume_init_drop();
+            }
+            cell.write(value);
+        }
+        if slot == self.shared.mask && !self.filled {
+            self.filled = true;
+            self.shared.filled.store(true, Ordering::Relaxed);
+        }
+        self.write = self.write.wrapping_add(1);
+        self.cached_lag += 1;
+        self.shared.write.store(self.write, Ordering::Release);
+        Ok(())
+    }
+
+    /// Number of values the slowest active consumer has yet to read.
+    fn slowest_lag(&self) -> usize {
+        self.shared
+            .cursors
+            .iter()
+            .filter(|c| c.active.load(Ordering::Acquire))
+            .map(|c| self.write.wrapping_sub(c.read.load(Ordering::Acquire)))
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared.mask + 1
+    }
+
+    /// Returns true if all the consumers were dropped.
+    pub fn is_disconnected(&self) -> bool {
+        self.shared.active.load(Ordering::Acquire) == 0
+    }
+}
+
+impl<T> Drop for Producer<T> {
+    fn drop(&mut self) {
+        self.shared.producer_dropped.store(true, Ordering::Release);
+    }
+}
+
+/// A reading half of a broadcast `channel`, sees every value pushed.
+pub struct Consumer<T> {
+    shared: Arc<Shared<T>>,
+    index: usize,
+    /// Last `write` observed by this consumer.
+    cached_write: Cell<usize>,
+}
+
+impl<T: Clone> Consumer<T> {
+    /// Returns a clone of the next value. Values pushed before the
+    /// `Producer` was dropped are still delivered, `Disconnected` is only
+    /// returned once they are all read.
+    pub fn pop(&mut self) -> Result<T, PopError> {
+        self.read_with(T::clone)
+    }
+}
+
+impl<T> Consumer<T> {
+    /// Calls `f` on the next value in place, without cloning it, and moves
+    /// past it.
+    pub fn read_with<R>(&mut self, f: impl FnOnce(&T) -> R) -> Result<R, PopError> {
+        let cursor = &self.shared.cursors[self.index];
+        let read = cursor.read.load(Ordering::Relaxed);
+        if read == self.cached_write.get() && !self.refresh(read) {
+            if !self.shared.producer_dropped.load(Ordering::Acquire) {
+                return Err(PopError::Empty);
+            }
+            // The producer may have pushed right before dropping.
+            if !self.refresh(read) {
+                return Err(PopError::Disconnected);
+            }
+        }
+        let slot = read & self.shared.mask;
+        self.shared.tracker.read(slot);
+        // Published by the producer, which cannot reuse the slot before
+        // this consumer moves past it.
+        let result = f(unsafe { (*self.shared.buffer[slot].get()).assume_init_ref() });
+        cursor.read.store(read.wrapping_add(1), Ordering::Release);
+        Ok(result)
+    }
+
+    /// Reloads `write`, returns true if there is a value at `read`.
+    fn refresh(&self, read: usize) -> bool {
+        self.cached_write.set(self.shared.write.load(Ordering::Acquire));
+        self.cached_write.get() != read
+    }
+
+    /// Number of values pushed and not read by this consumer yet.
+    pub fn len(&self) -> usize {
+        let read = self.shared.cursors[self.index].read.load(Ordering::Relaxed);
+        self.shared.write.load(Ordering::Acquire).wrapping_sub(read)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Drop for Consumer<T> {
+    fn drop(&mut self) {
+        self.shared.cursors[self.index].active.store(false, Ordering::Release);
+        self.shared.active.fetch_sub(1, Ordering::Release);
+    }
+}
+
+impl<T> Drop for Shared<T> {
+    /// Drops the values still in place.
+    fn drop(&mut self) {
+        let stored = if self.filled.load(Ordering::Relaxed) { self.mask + 1 } else { self.write.load(Ordering::Relaxed) };
+        for slot in &mut self.buffer[..stored] {
+            unsafe { slot.get_mut().assume_init_drop() };
+        }
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn every_consumer_sees_every_value() {
+        let (mut tx, consumers) = channel(4, 3);
+        let readers: Vec<_> = consumers
+            .into_iter()
+            .map(|mut rx| {
+                thread::spawn(move || {
+                    let mut seen = Vec::new();
+                    loop {
+                        match rx.pop() {
+                            Ok(value) => seen.push(value),
+                            Err(PopError::Empty) => thread::yield_now(),
+                            Err(PopError::Disconnected) => return seen,
+                        }
+                    }
+                })
+            })
+            .collect();
+        for i in 0..1000 {
+            let mut value = i.to_string();
+            while let Err(e) = tx.push(value) {
+                value = e.into_inner();
+                thread::yield_now();
+            }
+        }
+        drop(tx);
+        let expected: Vec<_> = (0..1000).map(|i| i.to_string()).collect();
+        for reader in readers {
+            assert_eq!(reader.join().unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn gated_on_the_slowest_consumer() {
+        let (mut tx, mut consumers) = channel(2, 2);
+        let mut slow = consumers.pop().unwrap();
+        let mut fast = consumers.pop().unwrap();
+        assert!(tx.push(1).is_ok());
+        assert!(tx.push(2).is_ok());
+        assert_eq!(fast.pop(), Ok(1));
+        assert_eq!(fast.pop(), Ok(2));
+        assert_eq!(tx.push(3), Err(PushError::Full(3)));
+        assert_eq!(slow.read_with(|v| v * 10), Ok(10));
+        assert!(tx.push(3).is_ok());
+        // A dropped consumer no longer gates the producer.
+        drop(slow);
+        assert!(tx.push(4).is_ok());
+        assert_eq!(fast.len(), 2);
+        drop(fast);
+        assert_eq!(tx.push(5), Err(PushError::Disconnected(5)));
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+    use loom::thread;
+
+    #[test]
+    fn slot_reused_after_all_reads() {
+        loom::model(|| {
+            let (mut tx, mut consumers) = channel(1, 2);
+            let mut slow = consumers.pop().unwrap();
+            let mut fast = consumers.pop().unwrap();
+            let reader = thread::spawn(move || loop {
+                match slow.pop() {
+                    Ok(value) => return value,
+                    Err(_) => thread::yield_now(),
+                }
+            });
+            tx.push(String::from("1")).unwrap();
+            assert_eq!(fast.pop().unwrap(), "1");
+            // Only overwrites "1" once the slow consumer has its copy.
+            while tx.push(String::from("2")).is_err() {
+                thread::yield_now();
+            }
+            assert_eq!(reader.join().unwrap(), "1");
+            assert_eq!(fast.pop().unwrap(), "2");
+        });
+    }
+}
@@ -0,0 +1,208 @@
+//! A conflating queue: each value carries a key, and a newer value replaces
+//! the one of the same key still waiting to be popped, e.g. the latest
+//! price per symbol, so a slow consumer skips the stale updates instead of
+//! falling behind. Keys are indexes below the number of keys the queue is
+//! created with; map symbols to them once, up front.
+//! The queue is a key index, holding the latest value of each key in a
+//! `SeqLockCell` and whether it waits to be popped, plus a ring of the
+//! waiting keys in the order they became pending. Each key is in the ring
+//! at most once, so a push never finds it full (but may wait for another
+//! consumer to finish popping the slot it pushes to).
+
+use alloc::boxed::Box;
+
+use crate::mpmc_lockfree::MPMCRingBuffer;
+use crate::seqlock::SeqLockCell;
+use crate::sync::{Arc, AtomicBool, Ordering};
+
+struct Entry<T> {
+    latest: SeqLockCell<Option<T>>,
+    /// Set while the key is in the ring.
+    pending: AtomicBool,
+}
+
+pub struct ConflatingQueue<T> {
+    /// The key index.
+    entries: Box<[Entry<T>]>,
+    /// The pending keys, oldest first.
+    ring: MPMCRingBuffer<usize>,
+}
+
+impl<T: Copy> ConflatingQueue<T> {
+    /// Creates a queue for the keys `0..keys`.
+    pub fn new(keys: usize) -> Arc<Self> {
+        let entries = (0..keys)
+            .map(|_| Entry { latest: SeqLockCell::new(None), pending: AtomicBool::new(false) })
+            .collect();
+        Arc::new(ConflatingQueue { entries, ring: MPMCRingBuffer::with_capacity(keys) })
+    }
+
+    /// Returns the number of keys.
+    pub fn keys(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Publishes the latest value of `key`. Returns true if it replaced a
+    /// value still waiting to be popped, false if the key is queued anew.
+    /// Panics if `key` is not below `keys()`.
+    pub fn push(&self, key: usize, value: T) -> bool {
+        let entry = &self.entries[key];
+        entry.latest.store(Some(value));
+        // A consumer that clears `pending` after this sees the value above.
+        if entry.pending.swap(true, Ordering::AcqRel) {
+            return true;
+        }
+        self.ring.push_reserved(key);
+        false
+    }
+
+    /// Pops the key that became pending first, with its latest value.
+    /// A value pushed while it is being popped may be popped again with the
+    /// key's next pop: the latest value of a key is delivered at least once.
+    pub fn pop(&self) -> Option<(usize, T)> {
+        let key = self.ring.pop()?;
+        let entry = &self.entries[key];
+        // Cleared before reading the value: a push from now on queues the
+        // key again rather than being lost.
+        entry.pending.swap(false, Ordering::AcqRel);
+        let value = entry.latest.load().expect("a pending key has a value");
+        Some((key, value))
+    }
+
+    /// Returns the latest value pushed for `key`, popped or not.
+    pub fn latest(&self, key: usize) -> Option<T> {
+        self.entries[key].latest.load()
+    }
+
+    /// Returns true if `key` has a value waiting to be popped.
+    pub fn is_pending(&self, key: usize) -> bool {
+        self.entries[key].pending.load(Ordering::Acquire)
+    }
+
+    /// Returns the number of keys waiting to be popped.
+    pub fn len(&self) -> usize {
+        self.ring.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ring.is_empty()
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn newer_value_replaces_pending_one() {
+        let queue = ConflatingQueue::new(3);
+        assert!(!queue.push(2, 10.0));
+        assert!(!queue.push(0, 1.0));
+        assert!(queue.push(2, 10.5));
+        assert_eq!(queue.len(), 2);
+        assert!(queue.is_pending(2));
+        assert_eq!(queue.pop(), Some((2, 10.5)));
+        assert!(!queue.is_pending(2));
+        assert_eq!(queue.latest(2), Some(10.5));
+        assert!(!queue.push(2, 11.0));
+        assert_eq!(queue.pop(), Some((0, 1.0)));
+        assert_eq!(queue.pop(), Some((2, 11.0)));
+        assert_eq!(queue.pop(), None);
+        assert_eq!(queue.latest(1), None);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri reports the optimistic copy of a seqlock as a data race")]
+    fn last_value_of_each_key_delivered() {
+        let queue = ConflatingQueue::new(4);
+        let producers: Vec<_> = (0..2)
+            .map(|p| {
+                let queue = queue.clone();
+                thread::spawn(move || {
+                    for i in 1..=1000 {
+                        queue.push(p * 2 + i % 2, i);
+                    }
+                })
+            })
+            .collect();
+        let mut last = [0; 4];
+        let mut popped = 0;
+        while producers.iter().any(|p| !p.is_finished()) || !queue.is_empty() {
+            match queue.pop() {
+                Some((key, value)) => {
+                    assert!(value >= last[key]);
+                    last[key] = value;
+                    popped += 1;
+                }
+                None => thread::yield_now(),
+            }
+        }
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        assert_eq!(last, [1000, 999, 1000, 999]);
+        assert!(popped <= 2000);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri reports the optimistic copy of a seqlock as a data race")]
+    fn concurrent_consumers() {
+        // Consumers vacate their slots of the ring out of order: a push may
+        // land on the slot of a pop that is still in progress.
+        let queue = ConflatingQueue::new(2);
+        let producer = {
+            let queue = queue.clone();
+            thread::spawn(move || {
+                for i in 1..=2000 {
+                    queue.push(i % 2, i);
+                }
+            })
+        };
+        let consumers: Vec<_> = (0..2)
+            .map(|_| {
+                let queue = queue.clone();
+                thread::spawn(move || {
+                    let mut last = [0; 2];
+                    for _ in 0..1000 {
+                        if let Some((key, value)) = queue.pop() {
+                            assert!(value >= last[key]);
+                            last[key] = value;
+                        }
+                        thread::yield_now();
+                    }
+                })
+            })
+            .collect();
+        producer.join().unwrap();
+        for consumer in consumers {
+            consumer.join().unwrap();
+        }
+        while queue.pop().is_some() {}
+        assert_eq!(queue.latest(0), Some(2000));
+        assert_eq!(queue.latest(1), Some(1999));
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+    use loom::thread;
+
+    #[test]
+    fn latest_value_not_lost() {
+        loom::model(|| {
+            let queue = ConflatingQueue::new(1);
+            queue.push(0, 1);
+            let producer = {
+                let queue = queue.clone();
+                thread::spawn(move || queue.push(0, 2))
+            };
+            let first = queue.pop().unwrap().1;
+            producer.join().unwrap();
+            // Either the pop saw 2, or 2 is still pending.
+            let last = queue.pop().map_or(first, |(_, value)| value);
+            assert_eq!(last, 2);
+        });
+    }
+}
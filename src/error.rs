@@ -0,0 +1,160 @@
+//! Errors shared by all the ring buffers. A rejected value is always handed
+//! back, so the caller can retry without cloning it.
+//! `Display` and `Error` are written out rather than derived, so that
+//! builds without `std` need no error crate.
+
+use core::fmt;
+
+/// Error of a push, holding the value that was not queued.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PushError<T> {
+    Full(T),
+    Disconnected(T),
+}
+
+impl<T> PushError<T> {
+    /// Returns the value that was not queued.
+    pub fn into_inner(self) -> T {
+        match self {
+            PushError::Full(value) | PushError::Disconnected(value) => value,
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        matches!(self, PushError::Full(_))
+    }
+
+    pub fn is_disconnected(&self) -> bool {
+        matches!(self, PushError::Disconnected(_))
+    }
+}
+
+/// Error of a pop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PopError {
+    Empty,
+    Disconnected,
+}
+
+/// Error of the operations that can only fail once the other side of the
+/// ring buffer is dropped, such as the slice and chunk ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Disconnected;
+
+/// Error of `Producer::write_chunk`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkError {
+    TooFewSlots(usize),
+    Disconnected,
+}
+
+/// Error of `MessageProducer::push_msg`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageError {
+    TooLong(usize),
+    Full,
+    Disconnected,
+}
+
+/// Error of `SharedRing::init` and `SharedRing::attach`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SharedMemoryError {
+    TooShort(usize),
+    Misaligned,
+    NotInitialized,
+    RecordSize,
+    Capacity,
+}
+
+const FULL: &str = "The ring buffer is full";
+const DISCONNECTED: &str = "The other side of the ring buffer was dropped";
+
+impl<T> fmt::Display for PushError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PushError::Full(_) => f.write_str(FULL),
+            PushError::Disconnected(_) => f.write_str(DISCONNECTED),
+        }
+    }
+}
+
+impl fmt::Display for PopError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PopError::Empty => f.write_str("The ring buffer is empty"),
+            PopError::Disconnected => f.write_str(DISCONNECTED),
+        }
+    }
+}
+
+impl fmt::Display for Disconnected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(DISCONNECTED)
+    }
+}
+
+impl fmt::Display for ChunkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChunkError::TooFewSlots(n) => write!(f, "Not enough slots for the chunk, only {n} available"),
+            ChunkError::Disconnected => f.write_str(DISCONNECTED),
+        }
+    }
+}
+
+impl fmt::Display for MessageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MessageError::TooLong(n) => write!(f, "The message is longer than the {n} bytes a message can hold"),
+            MessageError::Full => f.write_str(FULL),
+            MessageError::Disconnected => f.write_str(DISCONNECTED),
+        }
+    }
+}
+
+impl fmt::Display for SharedMemoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SharedMemoryError::TooShort(n) => write!(f, "The memory is too short for the ring, {n} bytes are needed"),
+            SharedMemoryError::Misaligned => f.write_str("The memory does not start on a cache line"),
+            SharedMemoryError::NotInitialized => f.write_str("No ring was initialized in the memory"),
+            SharedMemoryError::RecordSize => f.write_str("The ring holds records of another size"),
+            SharedMemoryError::Capacity => f.write_str("The ring header holds an invalid capacity"),
+        }
+    }
+}
+
+impl<T: fmt::Debug> core::error::Error for PushError<T> {}
+impl core::error::Error for PopError {}
+impl core::error::Error for Disconnected {}
+impl core::error::Error for ChunkError {}
+impl core::error::Error for MessageError {}
+impl core::error::Error for SharedMemoryError {}
+
+impl From<Disconnected> for PopError {
+    fn from(_: Disconnected) -> Self {
+        PopError::Disconnected
+    }
+}
+
+impl From<Disconnected> for ChunkError {
+    fn from(_: Disconnected) -> Self {
+        ChunkError::Disconnected
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_error_returns_the_value() {
+        let e = PushError::Full(String::from("v"));
+        assert!(e.is_full());
+        assert_eq!(e.to_string(), "The ring buffer is full");
+        assert_eq!(e.into_inner(), "v");
+        assert_eq!(PushError::Disconnected(3).into_inner(), 3);
+        assert_eq!(PopError::from(Disconnected), PopError::Disconnected);
+        assert_eq!(ChunkError::TooFewSlots(2).to_string(), "Not enough slots for the chunk, only 2 available");
+    }
+}
@@ -0,0 +1,141 @@
+//! C ABI for the lock-free SPSC ring of `u64`, so that a C/C++ plugin can
+//! produce into a ring consumed from Rust (or the other way around):
+//!
+//! ```c
+//! typedef struct RingbufSpsc RingbufSpsc;
+//! RingbufSpsc *ringbuf_spsc_new(size_t capacity);
+//! bool ringbuf_spsc_push(const RingbufSpsc *ring, uint64_t value);
+//! bool ringbuf_spsc_pop(const RingbufSpsc *ring, uint64_t *out);
+//! void ringbuf_spsc_free(RingbufSpsc *ring);
+//! ```
+//!
+//! A Rust consumer keeps its own reference through `RingbufSpsc::ring`
+//! and hands `Box::into_raw` of the handle to the plugin. As for any SPSC
+//! ring, only one thread may push and only one may pop at a time.
+
+use alloc::boxed::Box;
+
+use crate::index::RingIndex;
+use crate::spsc_lockfree::SPSCRingBuffer;
+use crate::sync::Arc;
+
+/// Handle to a ring shared with C code, always used behind a pointer.
+#[repr(C)]
+pub struct RingbufSpsc {
+    ring: Arc<SPSCRingBuffer<u64>>,
+}
+
+impl RingbufSpsc {
+    /// Creates a handle to a new ring of `capacity` rounded up to the next
+    /// power of two.
+    pub fn new(capacity: usize) -> Box<Self> {
+        Box::new(RingbufSpsc { ring: Arc::new(SPSCRingBuffer::new(capacity)) })
+    }
+
+    /// Returns the ring, which outlives the handle if cloned.
+    pub fn ring(&self) -> &Arc<SPSCRingBuffer<u64>> {
+        &self.ring
+    }
+}
+
+/// Creates a ring of `capacity` rounded up to the next power of two, or
+/// returns null if that capacity is too large.
+#[no_mangle]
+pub extern "C" fn ringbuf_spsc_new(capacity: usize) -> *mut RingbufSpsc {
+    match capacity.checked_next_power_of_two() {
+        Some(capacity) if capacity <= <usize as RingIndex>::MAX_CAPACITY => Box::into_raw(RingbufSpsc::new(capacity)),
+        _ => core::ptr::null_mut(),
+    }
+}
+
+/// Pushes `value`, returns false if the ring is full.
+/// # Safety
+/// `ring` must be a live handle, and the only producer of the ring.
+#[no_mangle]
+pub unsafe extern "C" fn ringbuf_spsc_push(ring: *const RingbufSpsc, value: u64) -> bool {
+    (*ring).ring.push(value).is_ok()
+}
+
+/// Pops the oldest value into `out`, returns false if the ring is empty.
+/// # Safety
+/// `ring` must be a live handle, and the only consumer of the ring. `out`
+/// must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn ringbuf_spsc_pop(ring: *const RingbufSpsc, out: *mut u64) -> bool {
+    match (*ring).ring.pop() {
+        Some((_, value)) => {
+            out.write(value);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Frees the handle. The ring itself lives on while Rust holds it.
+/// # Safety
+/// `ring` must come from `ringbuf_spsc_new` or `Box::into_raw`, and is not
+/// usable afterwards. Null is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn ringbuf_spsc_free(ring: *mut RingbufSpsc) {
+    if !ring.is_null() {
+        drop(Box::from_raw(ring));
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+
+    struct Plugin(*mut RingbufSpsc);
+
+    unsafe impl Send for Plugin {}
+
+    impl Plugin {
+        // Moves the whole `Plugin` into the closure, not just the pointer.
+        fn into_raw(self) -> *mut RingbufSpsc {
+            self.0
+        }
+    }
+
+    #[test]
+    fn push_and_pop() {
+        let ring = ringbuf_spsc_new(3);
+        let mut out = 0;
+        unsafe {
+            assert!(!ringbuf_spsc_pop(ring, &mut out));
+            for i in 0..4 {
+                assert!(ringbuf_spsc_push(ring, i));
+            }
+            assert!(!ringbuf_spsc_push(ring, 4));
+            assert!(ringbuf_spsc_pop(ring, &mut out));
+            assert_eq!(out, 0);
+            ringbuf_spsc_free(ring);
+        }
+        assert!(ringbuf_spsc_new(usize::MAX).is_null());
+    }
+
+    #[test]
+    fn consumed_from_rust() {
+        let handle = RingbufSpsc::new(8);
+        let ring = handle.ring().clone();
+        let plugin = Plugin(Box::into_raw(handle));
+        // Stands in for the C plugin.
+        let producer = std::thread::spawn(move || {
+            let raw = plugin.into_raw();
+            for i in 0..100 {
+                while !unsafe { ringbuf_spsc_push(raw, i) } {
+                    std::thread::yield_now();
+                }
+            }
+            unsafe { ringbuf_spsc_free(raw) };
+        });
+        let mut next = 0;
+        while next < 100 {
+            if let Some((_, v)) = ring.pop() {
+                assert_eq!(v, next);
+                next += 1;
+            }
+        }
+        producer.join().unwrap();
+    }
+}
@@ -0,0 +1,162 @@
+//! A flight recorder: the last entries logged by a service, kept in an
+//! MPSC ring with `force_push` so that recording never waits and the
+//! oldest entries make room for the new ones, and dumped to stderr or a
+//! file when the process panics, for post-mortem debugging.
+//! Any thread can record into the `global` recorder, or into its own.
+
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::panic;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::mpsc_lockfree::MPSCRingBuffer;
+use crate::sync::Arc;
+
+/// Entries kept by the `global` recorder.
+pub const GLOBAL_CAPACITY: usize = 1024;
+
+/// A recorded entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// Time since the recorder was created.
+    pub elapsed: Duration,
+    pub message: String,
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{:>12.6}s] {}", self.elapsed.as_secs_f64(), self.message)
+    }
+}
+
+/// Where the panic hook dumps the entries.
+#[derive(Debug, Clone)]
+pub enum DumpTarget {
+    Stderr,
+    /// Appended to, so that successive panics do not overwrite each other.
+    File(PathBuf),
+}
+
+/// A handle to a flight recorder, clones share its entries.
+#[derive(Clone)]
+pub struct FlightRecorder {
+    ring: Arc<MPSCRingBuffer<Entry>>,
+    start: Instant,
+}
+
+impl FlightRecorder {
+    /// Creates a recorder keeping the last `capacity` entries, rounded up
+    /// as by `mpsc_lockfree::MPSCRingBuffer::new`.
+    pub fn new(capacity: usize) -> Self {
+        FlightRecorder { ring: MPSCRingBuffer::new(capacity), start: Instant::now() }
+    }
+
+    /// Returns the process-wide recorder of `GLOBAL_CAPACITY` entries,
+    /// created on first use.
+    pub fn global() -> &'static FlightRecorder {
+        static GLOBAL: OnceLock<FlightRecorder> = OnceLock::new();
+        GLOBAL.get_or_init(|| FlightRecorder::new(GLOBAL_CAPACITY))
+    }
+
+    /// Records an entry, evicting the oldest one if the recorder is full.
+    /// Never waits, safe to call from any number of threads at once.
+    pub fn record(&self, message: impl Into<String>) {
+        let entry = Entry { elapsed: self.start.elapsed(), message: message.into() };
+        self.ring.force_push(entry);
+    }
+
+    /// Records a drained entry again, keeping its time.
+    pub(crate) fn restore(&self, entry: Entry) {
+        self.ring.force_push(entry);
+    }
+
+    /// Takes the retained entries, oldest first.
+    pub fn drain(&self) -> Vec<Entry> {
+        core::iter::from_fn(|| self.ring.pop()).collect()
+    }
+
+    /// Returns the number of entries evicted so far to make room.
+    pub fn dropped(&self) -> usize {
+        self.ring.dropped()
+    }
+
+    /// Takes the retained entries and writes them to `out`, one per line,
+    /// after a line counting them and the evicted ones.
+    pub fn dump<W: Write + ?Sized>(&self, out: &mut W) -> io::Result<()> {
+        let entries = self.drain();
+        writeln!(out, "flight recorder: last {} entries ({} evicted)", entries.len(), self.dropped())?;
+        for entry in entries {
+            writeln!(out, "{entry}")?;
+        }
+        out.flush()
+    }
+
+    /// Chains a panic hook dumping the entries to `target` after the
+    /// current hook ran. Dump errors are ignored, the process is going
+    /// down already.
+    pub fn install_panic_hook(&self, target: DumpTarget) {
+        let recorder = self.clone();
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            previous(info);
+            let _ = match &target {
+                DumpTarget::Stderr => recorder.dump(&mut io::stderr().lock()),
+                DumpTarget::File(path) => {
+                    OpenOptions::new().create(true).append(true).open(path).and_then(|mut file| recorder.dump(&mut file))
+                }
+            };
+        }));
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn keeps_the_last_entries() {
+        let recorder = FlightRecorder::new(4);
+        for i in 0..10 {
+            recorder.record(format!("request {i}"));
+        }
+        assert_eq!(recorder.dropped(), 6);
+        let mut out = Vec::new();
+        recorder.dump(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(lines[0], "flight recorder: last 4 entries (6 evicted)");
+        assert!(lines[1].ends_with("] request 6"));
+        assert!(lines[4].ends_with("] request 9"));
+        assert!(recorder.drain().is_empty());
+    }
+
+    #[test]
+    fn dumped_on_panic() {
+        let path = std::env::temp_dir().join(format!("ringbuf-flight-{}", std::process::id()));
+        let recorder = FlightRecorder::new(8);
+        recorder.install_panic_hook(DumpTarget::File(path.clone()));
+        let worker = {
+            let recorder = recorder.clone();
+            thread::spawn(move || {
+                recorder.record("loaded config");
+                recorder.record("about to fail");
+                panic!("boom");
+            })
+        };
+        assert!(worker.join().is_err());
+        // Back to the default hook, for the other tests.
+        let _ = panic::take_hook();
+        let dump = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        // Another test panicking meanwhile may have dumped some of them.
+        assert!(dump.contains("] loaded config"));
+        assert!(dump.contains("] about to fail"));
+    }
+}
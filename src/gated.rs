@@ -0,0 +1,282 @@
+//! A bounded multi-producer single-consumer channel for pipelines that
+//! must never drop data: a producer takes a permit from a semaphore-style
+//! counter of the vacant slots before pushing, waiting for one if there is
+//! none, and the consumer gives the permit back once the slot is vacant
+//! again. A push with a permit always finds its slot vacant, so there are
+//! no failed pushes to retry, and at most `capacity` values are ever held.
+
+use crate::error::{Disconnected, PopError, PushError};
+use crate::mpmc_lockfree::MPMCRingBuffer;
+use crate::sync::{Arc, AtomicBool, AtomicUsize, Ordering};
+use crate::wait::{SpinThenYield, WaitStrategy};
+
+struct Shared<T> {
+    queue: MPMCRingBuffer<T>,
+    /// Vacant slots not promised to a producer yet.
+    permits: AtomicUsize,
+    /// Live `Sender`s.
+    senders: AtomicUsize,
+    /// Set when the `Receiver` is dropped.
+    receiver_dropped: AtomicBool,
+}
+
+/// Creates a channel with `capacity` rounded up to the next power of two,
+/// and at least 2, and returns its sending and receiving handles. Clone
+/// the `Sender` for each producer.
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    channel_with(capacity, SpinThenYield::default(), SpinThenYield::default())
+}
+
+/// Same as `channel`, with the strategies the senders wait for a permit
+/// with and the receiver waits for a value with.
+pub fn channel_with<T, WS, WR>(capacity: usize, sender_wait: WS, receiver_wait: WR) -> (Sender<T, WS>, Receiver<T, WR>)
+where
+    WS: WaitStrategy + Clone,
+    WR: WaitStrategy,
+{
+    let queue = MPMCRingBuffer::with_capacity(capacity);
+    let shared = Arc::new(Shared {
+        permits: AtomicUsize::new(queue.capacity()),
+        queue,
+        senders: AtomicUsize::new(1),
+        receiver_dropped: AtomicBool::new(false),
+    });
+    (Sender { shared: shared.clone(), wait: sender_wait }, Receiver { shared, wait: receiver_wait })
+}
+
+/// A producer handle of a gated `channel`.
+pub struct Sender<T, W = SpinThenYield> {
+    shared: Arc<Shared<T>>,
+    wait: W,
+}
+
+impl<T, W: WaitStrategy> Sender<T, W> {
+    /// Pushes a value, waiting for a permit while all the slots are taken.
+    /// Fails only with `Disconnected`, handing the value back.
+    pub fn send(&self, value: T) -> Result<(), PushError<T>> {
+        let mut step = 0;
+        while !self.try_acquire() {
+            if self.is_disconnected() {
+                return Err(PushError::Disconnected(value));
+            }
+            self.wait.wait(step, None);
+            step = step.saturating_add(1);
+        }
+        self.push_with_permit(value)
+    }
+
+    /// Pushes a value if a permit is available, or hands it back in
+    /// `PushError::Full`.
+    pub fn try_send(&self, value: T) -> Result<(), PushError<T>> {
+        if !self.try_acquire() {
+            return Err(PushError::Full(value));
+        }
+        self.push_with_permit(value)
+    }
+
+    /// Takes a permit if there is one left.
+    fn try_acquire(&self) -> bool {
+        self.shared
+            .permits
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |permits| permits.checked_sub(1))
+            .is_ok()
+    }
+
+    fn push_with_permit(&self, value: T) -> Result<(), PushError<T>> {
+        if self.is_disconnected() {
+            self.shared.permits.fetch_add(1, Ordering::Relaxed);
+            return Err(PushError::Disconnected(value));
+        }
+        // At most `capacity` permits are out, and the receiver only gives
+        // one back once the pop of its slot is complete: the slot of this
+        // push is vacant, even if this thread may not see it yet.
+        self.shared.queue.push_reserved(value);
+        Ok(())
+    }
+
+    /// Returns the number of permits left, the pushes that would not wait.
+    pub fn available(&self) -> usize {
+        self.shared.permits.load(Ordering::Relaxed)
+    }
+
+    /// Returns true if the `Receiver` was dropped.
+    pub fn is_disconnected(&self) -> bool {
+        self.shared.receiver_dropped.load(Ordering::Acquire)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared.queue.capacity()
+    }
+}
+
+impl<T, W: Clone> Clone for Sender<T, W> {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::Relaxed);
+        Sender { shared: self.shared.clone(), wait: self.wait.clone() }
+    }
+}
+
+impl<T, W> Drop for Sender<T, W> {
+    fn drop(&mut self) {
+        self.shared.senders.fetch_sub(1, Ordering::Release);
+    }
+}
+
+/// The receiving handle of a gated `channel`, there is only one.
+pub struct Receiver<T, W = SpinThenYield> {
+    shared: Arc<Shared<T>>,
+    wait: W,
+}
+
+impl<T, W: WaitStrategy> Receiver<T, W> {
+    /// Pops the oldest value and gives its permit back. Values pushed
+    /// before the last `Sender` was dropped are still delivered,
+    /// `Disconnected` is only returned once the queue is drained.
+    pub fn pop(&mut self) -> Result<T, PopError> {
+        let value = match self.shared.queue.pop() {
+            Some(value) => value,
+            None if !self.is_disconnected() => return Err(PopError::Empty),
+            // A sender may have pushed right before dropping.
+            None => self.shared.queue.pop().ok_or(PopError::Disconnected)?,
+        };
+        self.shared.permits.fetch_add(1, Ordering::Release);
+        Ok(value)
+    }
+
+    /// Pops the oldest value, waiting for one to be pushed.
+    pub fn recv(&mut self) -> Result<T, Disconnected> {
+        let mut step = 0;
+        loop {
+            match self.pop() {
+                Ok(value) => return Ok(value),
+                Err(PopError::Disconnected) => return Err(Disconnected),
+                Err(PopError::Empty) => {
+                    self.wait.wait(step, None);
+                    step = step.saturating_add(1);
+                }
+            }
+        }
+    }
+
+    /// Returns true if all the `Sender`s were dropped.
+    pub fn is_disconnected(&self) -> bool {
+        self.shared.senders.load(Ordering::Acquire) == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared.queue.capacity()
+    }
+
+    /// See `mpmc_lockfree::MPMCRingBuffer::len`.
+    pub fn len(&self) -> usize {
+        self.shared.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shared.queue.is_empty()
+    }
+}
+
+impl<T, W> Drop for Receiver<T, W> {
+    fn drop(&mut self) {
+        self.shared.receiver_dropped.store(true, Ordering::Release);
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn permits() {
+        let (tx, mut rx) = channel(2);
+        assert_eq!(tx.available(), 2);
+        assert!(tx.send(1).is_ok());
+        assert!(tx.try_send(2).is_ok());
+        assert_eq!(tx.try_send(3), Err(PushError::Full(3)));
+        assert_eq!(rx.pop(), Ok(1));
+        assert_eq!(tx.available(), 1);
+        drop(rx);
+        assert_eq!(tx.send(3), Err(PushError::Disconnected(3)));
+        assert_eq!(tx.try_send(3), Err(PushError::Disconnected(3)));
+        assert_eq!(tx.available(), 1);
+    }
+
+    #[test]
+    fn blocking_producers_lose_nothing() {
+        let (tx, mut rx) = channel(4);
+        let senders: Vec<_> = (0..4)
+            .map(|p| {
+                let tx = tx.clone();
+                thread::spawn(move || {
+                    for i in 0..200 {
+                        tx.send(p * 200 + i).unwrap();
+                    }
+                })
+            })
+            .collect();
+        drop(tx);
+        let mut received = Vec::new();
+        while let Ok(value) = rx.recv() {
+            received.push(value);
+        }
+        for sender in senders {
+            sender.join().unwrap();
+        }
+        received.sort();
+        assert_eq!(received, (0..800).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn a_permit_never_fails_a_push() {
+        // A permit handed back for one slot can be taken by a producer whose
+        // claim lands on another slot, one whose pop it has not seen yet.
+        let (tx, mut rx) = channel(2);
+        let senders: Vec<_> = (0..4)
+            .map(|_| {
+                let tx = tx.clone();
+                thread::spawn(move || {
+                    for i in 0..1_000 {
+                        tx.send(i).unwrap();
+                    }
+                })
+            })
+            .collect();
+        drop(tx);
+        let mut received = 0;
+        while rx.recv().is_ok() {
+            received += 1;
+        }
+        for sender in senders {
+            sender.join().unwrap();
+        }
+        assert_eq!(received, 4_000);
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+    use loom::thread;
+
+    #[test]
+    fn permit_guarantees_a_vacant_slot() {
+        loom::model(|| {
+            let (tx, mut rx) = channel(2);
+            tx.send(0).unwrap();
+            tx.send(1).unwrap();
+            let sender = thread::spawn(move || tx.try_send(2));
+            let popped = rx.pop().unwrap();
+            // With a permit taken, the push itself cannot fail.
+            match sender.join().unwrap() {
+                Ok(()) => {
+                    assert_eq!(rx.pop(), Ok(1));
+                    assert_eq!(rx.pop(), Ok(2));
+                }
+                Err(e) => assert!(e.is_full()),
+            }
+            assert_eq!(popped, 0);
+        });
+    }
+}
@@ -0,0 +1,129 @@
+//! Width of the indices of the lock-free SPSC ring. Narrower indices use
+//! narrower atomics, for small targets whose native word is 16 or 32 bits,
+//! or which only have atomic instructions for those widths.
+//! Indices are free-running counters wrapping at the width of the type, so
+//! the capacity of a ring is limited to half the values the type can hold.
+
+use crate::sync::{AtomicU16, AtomicU32, AtomicUsize, Ordering};
+
+/// An unsigned integer type usable as the index of a ring. The ring keeps
+/// doing its arithmetic on `usize`, only the shared atomics are narrower.
+/// # Safety
+/// `load` must return the last value stored (by `store` or by a successful
+/// `compare_exchange`) with the requested ordering: the ring relies on it
+/// to never read a slot that was not written.
+pub unsafe trait RingIndex {
+    /// The atomic holding an index.
+    type Atomic: Send + Sync;
+    /// Largest capacity the counters can tell apart from an empty ring.
+    const MAX_CAPACITY: usize;
+    /// The largest index, counters wrap to 0 after it.
+    const MAX: usize;
+
+    fn new_atomic(value: usize) -> Self::Atomic;
+    fn load(atomic: &Self::Atomic, order: Ordering) -> usize;
+    fn store(atomic: &Self::Atomic, value: usize, order: Ordering);
+    fn compare_exchange(
+        atomic: &Self::Atomic,
+        current: usize,
+        new: usize,
+        success: Ordering,
+        failure: Ordering,
+    ) -> Result<usize, usize>;
+
+    /// Advances the counter `index` by `n`.
+    #[inline]
+    fn add(index: usize, n: usize) -> usize {
+        index.wrapping_add(n) & Self::MAX
+    }
+
+    /// Number of increments from the counter `from` to the counter `to`.
+    #[inline]
+    fn distance(from: usize, to: usize) -> usize {
+        to.wrapping_sub(from) & Self::MAX
+    }
+}
+
+macro_rules! ring_index {
+    ($int:ty, $atomic:ty) => {
+        unsafe impl RingIndex for $int {
+            type Atomic = $atomic;
+            const MAX_CAPACITY: usize = 1 << (<$int>::BITS - 1);
+            const MAX: usize = <$int>::MAX as usize;
+
+            fn new_atomic(value: usize) -> $atomic {
+                <$atomic>::new(value as $int)
+            }
+
+            #[inline]
+            fn load(atomic: &$atomic, order: Ordering) -> usize {
+                atomic.load(order) as usize
+            }
+
+            #[inline]
+            fn store(atomic: &$atomic, value: usize, order: Ordering) {
+                atomic.store(value as $int, order)
+            }
+
+            #[inline]
+            fn compare_exchange(
+                atomic: &$atomic,
+                current: usize,
+                new: usize,
+                success: Ordering,
+                failure: Ordering,
+            ) -> Result<usize, usize> {
+                atomic
+                    .compare_exchange(current as $int, new as $int, success, failure)
+                    .map(|v| v as usize)
+                    .map_err(|v| v as usize)
+            }
+        }
+    };
+}
+
+ring_index!(u16, AtomicU16);
+ring_index!(u32, AtomicU32);
+ring_index!(usize, AtomicUsize);
+
+/// Proofs for `cargo kani`, over every counter value: the counters of a
+/// ring `n` values apart always report `n`, so a full ring is never taken
+/// for an empty one, and the masked slots stay in the buffer.
+#[cfg(kani)]
+mod verification {
+    use super::*;
+
+    fn counters<I: RingIndex>() {
+        let shift: u32 = kani::any();
+        kani::assume(shift <= I::MAX_CAPACITY.trailing_zeros());
+        let capacity = 1usize << shift;
+        let mask = capacity - 1;
+        let read: usize = kani::any();
+        kani::assume(read <= I::MAX);
+        let n: usize = kani::any();
+        kani::assume(n <= capacity);
+        let write = I::add(read, n);
+        assert!(write <= I::MAX);
+        assert_eq!(I::distance(read, write), n);
+        assert!(read & mask < capacity);
+        assert!(write & mask < capacity);
+        // Wrapping at `I::MAX` keeps the slots in step, as the capacity
+        // divides the number of counter values.
+        assert_eq!(write & mask, (read & mask).wrapping_add(n) & mask);
+    }
+
+    #[kani::proof]
+    fn u16_counters() {
+        counters::<u16>();
+    }
+
+    #[kani::proof]
+    fn u32_counters() {
+        counters::<u32>();
+    }
+
+    #[kani::proof]
+    fn usize_counters() {
+        counters::<usize>();
+    }
+}
@@ -1,3 +1,105 @@
-pub mod spsc_bounded;
-pub mod spsc_lockfree_bounded;
-pub mod mpsc_lockfree_bounded;
+//! Ring buffers. Without the default `std` feature the crate is `no_std`;
+//! `alloc` alone builds the lock-free SPSC ring and the modules over it,
+//! `mpmc` and `mpsc` (both default) add the multi-producer rings, and
+//! `async`, `metrics` and the other opt-in features only pull in their
+//! dependencies when asked. With no feature at all only the rings over
+//! memory the caller provides are left, see `volatile` and `shared`.
+//!
+//! Each variant has its module and a distinct name, re-exported here:
+//! `spsc_array::ArrayRingBuffer` (single-threaded, `std` only),
+//! `spsc_lockfree::SPSCRingBuffer`, `mpsc_lockfree::MPSCRingBuffer` and
+//! `mpmc_lockfree::MPMCRingBuffer`. The traits of `ring` let generic code
+//! take any of them. `use ringbuf::prelude::*` brings them in with the
+//! traits, the errors and `WaitStrategy`.
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod spsc_array;
+pub mod error;
+#[cfg(feature = "alloc")]
+pub mod spsc_lockfree;
+#[cfg(feature = "mpsc")]
+pub mod mpsc_lockfree;
+#[cfg(feature = "mpmc")]
+pub mod mpmc_lockfree;
+#[cfg(feature = "mpmc")]
+pub mod spmc_lockfree;
+#[cfg(feature = "alloc")]
+pub mod broadcast;
+pub mod seqlock;
+#[cfg(feature = "alloc")]
+pub mod select;
+#[cfg(feature = "mpmc")]
+pub mod sharded;
+#[cfg(feature = "mpmc")]
+pub mod gated;
+#[cfg(feature = "mpmc")]
+pub mod priority;
+#[cfg(feature = "mpmc")]
+pub mod conflate;
+#[cfg(feature = "alloc")]
+pub mod queue_pair;
+pub mod volatile;
+pub mod shared;
+#[cfg(all(feature = "std", feature = "mpsc"))]
+pub mod flight;
+#[cfg(all(feature = "std", feature = "mpsc"))]
+pub mod logger;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "lz4")]
+pub mod spill;
+pub mod wait;
+pub mod index;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "stats")]
+pub mod stats;
+#[cfg(all(feature = "shm", unix))]
+pub mod shm;
+#[cfg(all(feature = "persist", unix))]
+pub mod persist;
+#[cfg(all(any(feature = "shm", feature = "persist"), unix))]
+mod mmap;
+pub mod prelude;
+#[cfg(feature = "alloc")]
+pub mod ring;
+mod sync;
+#[cfg(feature = "async")]
+mod waker;
+
+pub use error::{ChunkError, Disconnected, MessageError, PopError, PushError, SharedMemoryError};
+#[cfg(feature = "std")]
+pub use spsc_array::ArrayRingBuffer;
+#[cfg(feature = "alloc")]
+pub use spsc_lockfree::SPSCRingBuffer;
+#[cfg(feature = "mpsc")]
+pub use mpsc_lockfree::MPSCRingBuffer;
+#[cfg(feature = "mpmc")]
+pub use mpmc_lockfree::MPMCRingBuffer;
+#[cfg(feature = "alloc")]
+pub use ring::{Ring, RingConsumer, RingProducer};
+
+// The module paths before the renaming, so that existing imports still
+// build.
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub use spsc_array as spsc_bounded;
+#[cfg(feature = "alloc")]
+#[doc(hidden)]
+pub use spsc_lockfree as spsc_lockfree_bounded;
+#[cfg(feature = "mpsc")]
+#[doc(hidden)]
+pub use mpsc_lockfree as mpsc_lockfree_bounded;
+#[cfg(feature = "mpmc")]
+#[doc(hidden)]
+pub use mpmc_lockfree as mpmc_lockfree_bounded;
+#[cfg(feature = "mpmc")]
+#[doc(hidden)]
+pub use spmc_lockfree as spmc_lockfree_bounded;
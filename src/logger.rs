@@ -0,0 +1,94 @@
+//! A `log::Log` appender keeping the recent log lines in a `FlightRecorder`:
+//! logging formats the record and force-pushes it, so it never waits and
+//! the oldest lines make room for the new ones. `snapshot` returns the
+//! recent lines on demand, e.g. for a debug endpoint, and the recorder's
+//! panic hook can dump them when the process panics.
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+
+use crate::flight::FlightRecorder;
+
+pub struct RingLogger {
+    recorder: FlightRecorder,
+    level: LevelFilter,
+}
+
+impl RingLogger {
+    /// Creates a logger keeping the last `capacity` lines at `level` or
+    /// more severe, see `FlightRecorder::new`.
+    pub fn new(capacity: usize, level: LevelFilter) -> Self {
+        RingLogger { recorder: FlightRecorder::new(capacity), level }
+    }
+
+    /// Makes this the logger of the `log` macros, for the rest of the
+    /// process. Fails if a logger was set already.
+    pub fn install(self) -> Result<&'static RingLogger, SetLoggerError> {
+        let level = self.level;
+        let logger: &'static RingLogger = Box::leak(Box::new(self));
+        log::set_logger(logger)?;
+        log::set_max_level(level);
+        Ok(logger)
+    }
+
+    /// Returns the recent lines, oldest first. They are taken out of the
+    /// ring and put back: lines logged meanwhile may end up before them,
+    /// or evict some of them.
+    pub fn snapshot(&self) -> Vec<String> {
+        let entries = self.recorder.drain();
+        let lines = entries.iter().map(ToString::to_string).collect();
+        for entry in entries {
+            self.recorder.restore(entry);
+        }
+        lines
+    }
+
+    /// Returns the recorder holding the lines, e.g. to install its panic
+    /// hook.
+    pub fn recorder(&self) -> &FlightRecorder {
+        &self.recorder
+    }
+}
+
+impl Log for RingLogger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if self.enabled(record.metadata()) {
+            self.recorder.record(format!("{:<5} {}: {}", record.level(), record.target(), record.args()));
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use log::Level;
+
+    fn log(logger: &RingLogger, level: Level, message: &str) {
+        logger.log(&Record::builder().level(level).target("app").args(format_args!("{message}")).build());
+    }
+
+    #[test]
+    fn keeps_recent_lines() {
+        let logger = RingLogger::new(2, LevelFilter::Info);
+        log(&logger, Level::Debug, "filtered out");
+        log(&logger, Level::Info, "one");
+        log(&logger, Level::Warn, "two");
+        log(&logger, Level::Error, "three");
+        let lines = logger.snapshot();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with("] WARN  app: two"));
+        assert!(lines[1].ends_with("] ERROR app: three"));
+        // Still there for the next snapshot.
+        assert_eq!(logger.snapshot(), lines);
+        assert_eq!(logger.recorder().dropped(), 1);
+    }
+}
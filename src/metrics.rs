@@ -0,0 +1,165 @@
+//! Latency instrumentation of the rings, enabled by the `metrics` feature.
+//!
+//! `LatencyHistogram` buckets values like an HDR histogram: exact below 16,
+//! then 16 linear buckets per power of two, so any recorded value is known
+//! within 1/16 (about 6%) whatever its magnitude, in a fixed 8 KiB.
+
+use core::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+/// log2 of the number of buckets per power of two.
+const SUB_BITS: u32 = 4;
+const SUB_BUCKETS: usize = 1 << SUB_BITS;
+const BUCKETS: usize = (64 - SUB_BITS as usize + 1) * SUB_BUCKETS;
+
+/// Lock-free histogram of latencies in nanoseconds. Recording is a couple
+/// of relaxed atomic increments, so it can be shared by the producer and
+/// the consumer and read concurrently; a read racing with records may miss
+/// the latest ones.
+pub struct LatencyHistogram {
+    buckets: Box<[AtomicU64]>,
+    count: AtomicU64,
+    max: AtomicU64,
+}
+
+impl LatencyHistogram {
+    pub fn new() -> Self {
+        LatencyHistogram {
+            buckets: (0..BUCKETS).map(|_| AtomicU64::new(0)).collect(),
+            count: AtomicU64::new(0),
+            max: AtomicU64::new(0),
+        }
+    }
+
+    /// Records a latency of `nanos`.
+    pub fn record(&self, nanos: u64) {
+        self.buckets[bucket(nanos)].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.max.fetch_max(nanos, Ordering::Relaxed);
+    }
+
+    /// Records the time elapsed since `start`.
+    pub fn record_since(&self, start: Instant) {
+        self.record(u64::try_from(start.elapsed().as_nanos()).unwrap_or(u64::MAX));
+    }
+
+    /// Returns the number of recorded latencies.
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Returns the largest recorded latency, exact.
+    pub fn max(&self) -> u64 {
+        self.max.load(Ordering::Relaxed)
+    }
+
+    /// Returns the latency that `quantile` (in `0.0..=1.0`) of the records
+    /// are at or below, rounded up to the end of its bucket. 0 if nothing
+    /// was recorded.
+    pub fn value_at_quantile(&self, quantile: f64) -> u64 {
+        let counts: Vec<u64> = self.buckets.iter().map(|b| b.load(Ordering::Relaxed)).collect();
+        let total: u64 = counts.iter().sum();
+        if total == 0 {
+            return 0;
+        }
+        let rank = ((quantile.clamp(0.0, 1.0) * total as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (i, &count) in counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return highest_in_bucket(i).min(self.max());
+            }
+        }
+        self.max()
+    }
+
+    pub fn p50(&self) -> u64 {
+        self.value_at_quantile(0.5)
+    }
+
+    pub fn p99(&self) -> u64 {
+        self.value_at_quantile(0.99)
+    }
+
+    pub fn p999(&self) -> u64 {
+        self.value_at_quantile(0.999)
+    }
+
+    /// Forgets all the records, e.g. between two phases of a load test.
+    pub fn reset(&self) {
+        for bucket in self.buckets.iter() {
+            bucket.store(0, Ordering::Relaxed);
+        }
+        self.count.store(0, Ordering::Relaxed);
+        self.max.store(0, Ordering::Relaxed);
+    }
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for LatencyHistogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LatencyHistogram")
+            .field("count", &self.count())
+            .field("p50", &self.p50())
+            .field("p99", &self.p99())
+            .field("p999", &self.p999())
+            .field("max", &self.max())
+            .finish()
+    }
+}
+
+/// Index of the bucket of `value`.
+fn bucket(value: u64) -> usize {
+    if value < SUB_BUCKETS as u64 {
+        return value as usize;
+    }
+    let exp = 63 - value.leading_zeros();
+    let sub = (value >> (exp - SUB_BITS)) as usize & (SUB_BUCKETS - 1);
+    (exp - SUB_BITS + 1) as usize * SUB_BUCKETS + sub
+}
+
+/// Largest value falling in the bucket `index`.
+fn highest_in_bucket(index: usize) -> u64 {
+    if index < SUB_BUCKETS {
+        return index as u64;
+    }
+    let shift = (index / SUB_BUCKETS) as u32 - 1;
+    let lowest = ((SUB_BUCKETS + index % SUB_BUCKETS) as u64) << shift;
+    lowest + ((1 << shift) - 1)
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets() {
+        for value in [0, 15, 16, 17, 31, 32, 1000, 123_456_789, u64::MAX] {
+            let high = highest_in_bucket(bucket(value));
+            assert!(high >= value && high - value <= value / 16, "{value} -> {high}");
+        }
+        assert_eq!(bucket(u64::MAX), BUCKETS - 1);
+    }
+
+    #[test]
+    fn quantiles() {
+        let histogram = LatencyHistogram::new();
+        assert_eq!(histogram.p99(), 0);
+        for nanos in 1..=1000 {
+            histogram.record(nanos);
+        }
+        assert_eq!(histogram.count(), 1000);
+        assert_eq!(histogram.max(), 1000);
+        assert!((500..=531).contains(&histogram.p50()));
+        assert!((990..=1000).contains(&histogram.p99()));
+        assert_eq!(histogram.p999(), 1000);
+        histogram.reset();
+        assert_eq!(histogram.count(), 0);
+    }
+}
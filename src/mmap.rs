@@ -0,0 +1,43 @@
+//! Shared mappings of whole files, for the rings that live in one.
+
+use core::ptr::{self, NonNull};
+use std::fs::File;
+use std::io;
+use std::os::fd::AsRawFd;
+
+/// A `MAP_SHARED` mapping of the first `len` bytes of a file, unmapped
+/// when dropped.
+pub(crate) struct Mapping {
+    ptr: NonNull<u8>,
+    len: usize,
+}
+
+impl Mapping {
+    pub(crate) fn new(file: &File, len: usize) -> io::Result<Self> {
+        let prot = libc::PROT_READ | libc::PROT_WRITE;
+        let ptr = unsafe { libc::mmap(ptr::null_mut(), len, prot, libc::MAP_SHARED, file.as_raw_fd(), 0) };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Mapping { ptr: NonNull::new(ptr as *mut u8).expect("mmap returned null"), len })
+    }
+
+    pub(crate) fn as_ptr(&self) -> *mut u8 {
+        self.ptr.as_ptr()
+    }
+
+    /// Writes the modified pages back to the file, and waits for it.
+    #[cfg(feature = "persist")]
+    pub(crate) fn sync(&self) -> io::Result<()> {
+        if unsafe { libc::msync(self.ptr.as_ptr() as *mut libc::c_void, self.len, libc::MS_SYNC) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ptr.as_ptr() as *mut libc::c_void, self.len) };
+    }
+}
@@ -0,0 +1,406 @@
+//! A bounded multi-producer multi-consumer queue, after Dmitry Vyukov's
+//! bounded MPMC queue: every slot carries a sequence number telling whether
+//! it is vacant for the push at a given position or holds the value for the
+//! pop at that position, so producers only race on a CAS of `write` and
+//! consumers on a CAS of `read`. Share it through the returned `Arc`, e.g.
+//! as the job queue of a worker pool.
+//!
+//! Fairness: the queue is lock-free but not wait-free. A producer may lose
+//! the CAS of `write` to the others any number of times, each loss meaning
+//! another value was pushed, and the values of concurrent pushes are queued
+//! in the order the CAS succeeded, not the order of the calls. To keep many
+//! producers from livelocking on the cache line of `write`, a producer
+//! losing the race backs off (spin, then yield, then park, see
+//! `wait::Backoff`) before its next attempt; the same goes for consumers
+//! racing on `read`.
+
+use alloc::boxed::Box;
+#[cfg(feature = "mpsc")]
+use alloc::vec::Vec;
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+
+use crate::error::PushError;
+use crate::spsc_lockfree::CachePadded;
+use crate::sync::{Arc, AtomicUsize, Ordering, SlotTracker};
+use crate::wait::Backoff;
+
+struct Slot<T> {
+    /// `pos` when vacant for the push at `pos`, `pos + 1` once it holds the
+    /// value for the pop at `pos`.
+    sequence: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+pub struct MPMCRingBuffer<T> {
+    buffer: Box<[Slot<T>]>,
+    /// `capacity - 1`, the capacity is always a power of two.
+    mask: usize,
+    /// Position of the next push, `write & mask` is its slot.
+    write: CachePadded<AtomicUsize>,
+    /// Position of the next pop.
+    read: CachePadded<AtomicUsize>,
+    tracker: SlotTracker,
+}
+
+#[deprecated(note = "renamed to `MPMCRingBuffer`")]
+pub type RingBuffer<T> = MPMCRingBuffer<T>;
+
+unsafe impl<T: Send> Sync for MPMCRingBuffer<T> {}
+
+impl<T> MPMCRingBuffer<T> {
+    /// Creates a ring buffer with `capacity` rounded up to the next power of
+    /// two, and at least 2: with a single slot the sequence of a filled slot
+    /// would read as vacant for the next push.
+    pub fn new(capacity: usize) -> Arc<Self> {
+        Arc::new(Self::with_capacity(capacity))
+    }
+
+    /// Same as `new`, for the rings built on this one.
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        let capacity = capacity.next_power_of_two().max(2);
+        let buffer = (0..capacity)
+            .map(|i| Slot { sequence: AtomicUsize::new(i), value: UnsafeCell::new(MaybeUninit::uninit()) })
+            .collect();
+        MPMCRingBuffer {
+            buffer,
+            mask: capacity - 1,
+            write: CachePadded::new(AtomicUsize::new(0)),
+            read: CachePadded::new(AtomicUsize::new(0)),
+            tracker: SlotTracker::new(capacity),
+        }
+    }
+
+    /// Returns the maximum number of values the ring buffer can hold.
+    pub fn capacity(&self) -> usize {
+        self.mask + 1
+    }
+
+    /// Returns the number of values pushed and not popped yet, counting the
+    /// pushes and pops still in progress. A snapshot, as for the SPSC ring.
+    pub fn len(&self) -> usize {
+        let read = self.read.load(Ordering::Acquire);
+        let write = self.write.load(Ordering::Acquire);
+        write.wrapping_sub(read).min(self.capacity())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Pushes a value, or hands it back in `PushError::Full`. Safe to call
+    /// from any number of threads at once.
+    pub fn try_push(&self, item: T) -> Result<(), PushError<T>> {
+        let mut pos = self.write.load(Ordering::Relaxed);
+        let mut backoff = Backoff::default();
+        let slot = loop {
+            let slot = &self.buffer[pos & self.mask];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            match (sequence.wrapping_sub(pos) as isize).signum() {
+                // Vacant for this position: claim it against the other producers.
+                0 => match self.write.compare_exchange_weak(pos, pos.wrapping_add(1), Ordering::Relaxed, Ordering::Relaxed) {
+                    Ok(_) => break slot,
+                    Err(current) => pos = current,
+                },
+                // Still holds the value pushed one lap ago.
+                -1 => return Err(PushError::Full(item)),
+                // Another producer claimed it, catch up.
+                _ => pos = self.write.load(Ordering::Relaxed),
+            }
+            backoff.snooze();
+        };
+
+        self.tracker.write(pos & self.mask);
+        unsafe {
+            (*slot.value.get()).write(item);
+        }
+        slot.sequence.store(pos.wrapping_add(1), Ordering::Release);
+        Ok(())
+    }
+
+    /// `try_push` for callers that know a slot is free, such as the holders
+    /// of a permit in `gated`: the slot of the push may still be under the
+    /// pop of the previous lap, by a consumer that claimed it and has not
+    /// marked it vacant yet, or marked it without this thread seeing it
+    /// yet. `try_push` reports that as full; this waits for the pop instead.
+    pub(crate) fn push_reserved(&self, item: T) {
+        let mut item = item;
+        let mut backoff = Backoff::default();
+        while let Err(rejected) = self.try_push(item) {
+            item = rejected.into_inner();
+            backoff.snooze();
+        }
+    }
+
+    /// `try_push` for rings with a single producer: the slot is claimed
+    /// with a plain store of `write` instead of a CAS.
+    /// # Safety
+    /// No other push may run concurrently.
+    pub(crate) unsafe fn push_single(&self, item: T) -> Result<(), PushError<T>> {
+        let pos = self.write.load(Ordering::Relaxed);
+        let slot = &self.buffer[pos & self.mask];
+        // The slot of the next push is either vacant for it or still holds
+        // the value pushed one lap ago.
+        if slot.sequence.load(Ordering::Acquire) != pos {
+            return Err(PushError::Full(item));
+        }
+        self.write.store(pos.wrapping_add(1), Ordering::Relaxed);
+        self.tracker.write(pos & self.mask);
+        (*slot.value.get()).write(item);
+        slot.sequence.store(pos.wrapping_add(1), Ordering::Release);
+        Ok(())
+    }
+
+    /// Pops the oldest value, `None` if the queue is empty. Safe to call
+    /// from any number of threads at once.
+    pub fn pop(&self) -> Option<T> {
+        let mut pos = self.read.load(Ordering::Relaxed);
+        let mut backoff = Backoff::default();
+        let slot = loop {
+            let slot = &self.buffer[pos & self.mask];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            match (sequence.wrapping_sub(pos.wrapping_add(1)) as isize).signum() {
+                // Holds the value for this position: claim it against the
+                // other consumers.
+                0 => match self.read.compare_exchange_weak(pos, pos.wrapping_add(1), Ordering::Relaxed, Ordering::Relaxed) {
+                    Ok(_) => break slot,
+                    Err(current) => pos = current,
+                },
+                // Not written yet: empty.
+                -1 => return None,
+                _ => pos = self.read.load(Ordering::Relaxed),
+            }
+            backoff.snooze();
+        };
+
+        self.tracker.read(pos & self.mask);
+        let item = unsafe { (*slot.value.get()).assume_init_read() };
+        // Vacant for the push one lap later.
+        slot.sequence.store(pos.wrapping_add(self.capacity()), Ordering::Release);
+        Some(item)
+    }
+
+    /// Pops the oldest value only if the queue is full, i.e. its slot is
+    /// the one of the next push. `None` otherwise, including while another
+    /// pop of that value is in progress: its slot is about to be vacant.
+    #[cfg(feature = "mpsc")]
+    pub(crate) fn pop_if_full(&self) -> Option<T> {
+        let pos = self.read.load(Ordering::Relaxed);
+        // No push can be claimed past `pos + capacity` before the slot of
+        // `pos` is vacant, so the queue stays full until the CAS below.
+        if self.write.load(Ordering::Relaxed).wrapping_sub(pos) != self.capacity() {
+            return None;
+        }
+        let slot = &self.buffer[pos & self.mask];
+        // Claimed by a push that has not written it yet.
+        if slot.sequence.load(Ordering::Acquire) != pos.wrapping_add(1) {
+            return None;
+        }
+        self.read.compare_exchange(pos, pos.wrapping_add(1), Ordering::Relaxed, Ordering::Relaxed).ok()?;
+        self.tracker.read(pos & self.mask);
+        let item = unsafe { (*slot.value.get()).assume_init_read() };
+        slot.sequence.store(pos.wrapping_add(self.capacity()), Ordering::Release);
+        Some(item)
+    }
+}
+
+impl<T: Copy> MPMCRingBuffer<T> {
+    /// Pushes as many values from `values` as there are vacant slots in a
+    /// row, claimed with a single CAS of `write` instead of one per value.
+    /// Returns how many values were pushed.
+    pub fn try_push_slice(&self, values: &[T]) -> usize {
+        let (pos, n) = self.claim(values.len());
+        for (i, value) in values[..n].iter().enumerate() {
+            // Claimed above.
+            unsafe { self.publish(pos.wrapping_add(i), *value) };
+        }
+        n
+    }
+}
+
+impl<T> MPMCRingBuffer<T> {
+    /// Moves as many values from the front of `values` as there are vacant
+    /// slots in a row, as `try_push_slice` does. Returns how many were
+    /// pushed.
+    #[cfg(feature = "mpsc")]
+    pub(crate) fn push_from(&self, values: &mut Vec<T>) -> usize {
+        let (pos, n) = self.claim(values.len());
+        for (i, value) in values.drain(..n).enumerate() {
+            // Claimed above.
+            unsafe { self.publish(pos.wrapping_add(i), value) };
+        }
+        n
+    }
+
+    /// Claims up to `max` vacant slots in a row with a single CAS of
+    /// `write`. Returns the position of the first one and how many were
+    /// claimed, each of them must then be written by `publish`.
+    fn claim(&self, max: usize) -> (usize, usize) {
+        let mut pos = self.write.load(Ordering::Relaxed);
+        let mut backoff = Backoff::default();
+        loop {
+            // A slot vacant for its position can only be claimed by moving
+            // `write`: the run stays vacant if the CAS succeeds.
+            let n = (0..max.min(self.capacity()))
+                .take_while(|&i| {
+                    let pos = pos.wrapping_add(i);
+                    self.buffer[pos & self.mask].sequence.load(Ordering::Acquire) == pos
+                })
+                .count();
+            if n == 0 {
+                let current = self.write.load(Ordering::Relaxed);
+                // Full, or nothing to push.
+                if current == pos {
+                    return (pos, 0);
+                }
+                pos = current;
+            } else {
+                match self.write.compare_exchange_weak(pos, pos.wrapping_add(n), Ordering::Relaxed, Ordering::Relaxed) {
+                    Ok(_) => return (pos, n),
+                    Err(current) => pos = current,
+                }
+            }
+            backoff.snooze();
+        }
+    }
+
+    /// Writes the value of position `pos` and hands it to the consumers.
+    /// # Safety
+    /// `pos` must have been claimed by `claim` and not published yet.
+    unsafe fn publish(&self, pos: usize, item: T) {
+        let slot = &self.buffer[pos & self.mask];
+        self.tracker.write(pos & self.mask);
+        (*slot.value.get()).write(item);
+        slot.sequence.store(pos.wrapping_add(1), Ordering::Release);
+    }
+}
+
+impl<T> Drop for MPMCRingBuffer<T> {
+    /// Runs the destructors of the values still queued.
+    fn drop(&mut self) {
+        let mut pos = self.read.load(Ordering::Relaxed);
+        let write = self.write.load(Ordering::Relaxed);
+        while pos != write {
+            unsafe { self.buffer[pos & self.mask].value.get_mut().assume_init_drop() };
+            pos = pos.wrapping_add(1);
+        }
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+    use std::thread;
+
+    #[test]
+    fn push_and_pop() {
+        let ring = MPMCRingBuffer::new(3);
+        assert_eq!(ring.capacity(), 4);
+        for i in 0..4 {
+            assert!(ring.try_push(i.to_string()).is_ok());
+        }
+        assert_eq!(ring.try_push("4".to_string()), Err(PushError::Full("4".to_string())));
+        assert_eq!(ring.len(), 4);
+        assert_eq!(ring.pop().as_deref(), Some("0"));
+        assert!(ring.try_push("4".to_string()).is_ok());
+        // The values left are dropped with the ring.
+    }
+
+    #[test]
+    fn push_slice() {
+        let ring = MPMCRingBuffer::new(4);
+        assert_eq!(ring.try_push_slice(&[0, 1, 2]), 3);
+        assert_eq!(ring.try_push_slice(&[3, 4, 5]), 1);
+        assert_eq!(ring.try_push_slice(&[4]), 0);
+        assert_eq!(ring.pop(), Some(0));
+        assert_eq!(ring.pop(), Some(1));
+        // Wraps around.
+        assert_eq!(ring.try_push_slice(&[4, 5, 6]), 2);
+        assert_eq!((0..4).map(|_| ring.pop().unwrap()).collect::<Vec<_>>(), [2, 3, 4, 5]);
+        assert_eq!(ring.try_push_slice(&[]), 0);
+        assert!(ring.is_empty());
+    }
+
+    #[test]
+    fn worker_pool() {
+        let ring = MPMCRingBuffer::new(8);
+        let done = Arc::new(AtomicBool::new(false));
+        let workers: Vec<_> = (0..3)
+            .map(|_| {
+                let (ring, done) = (ring.clone(), done.clone());
+                thread::spawn(move || {
+                    let mut jobs = Vec::new();
+                    loop {
+                        match ring.pop() {
+                            Some(job) => jobs.push(job),
+                            None if done.load(Ordering::Acquire) && ring.is_empty() => return jobs,
+                            None => thread::yield_now(),
+                        }
+                    }
+                })
+            })
+            .collect();
+        for job in 0..1000 {
+            let mut job = job;
+            while let Err(e) = ring.try_push(job) {
+                job = e.into_inner();
+                thread::yield_now();
+            }
+        }
+        done.store(true, Ordering::Release);
+        let mut jobs: Vec<_> = workers.into_iter().flat_map(|w| w.join().unwrap()).collect();
+        jobs.sort();
+        assert_eq!(jobs, (0..1000).collect::<Vec<_>>());
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+    use loom::thread;
+
+    #[test]
+    fn two_consumers() {
+        loom::model(|| {
+            let ring = MPMCRingBuffer::new(2);
+            ring.try_push(0).unwrap();
+            ring.try_push(1).unwrap();
+            let consumer = {
+                let ring = ring.clone();
+                thread::spawn(move || ring.pop())
+            };
+            let producer = {
+                let ring = ring.clone();
+                thread::spawn(move || while ring.try_push(2).is_err() {
+                    thread::yield_now();
+                })
+            };
+            let mine = ring.pop();
+            let theirs = consumer.join().unwrap();
+            producer.join().unwrap();
+            let mut popped = [mine.unwrap(), theirs.unwrap(), ring.pop().unwrap()];
+            popped.sort();
+            assert_eq!(popped, [0, 1, 2]);
+        });
+    }
+
+    #[test]
+    fn slice_and_single_push() {
+        loom::model(|| {
+            let ring = MPMCRingBuffer::new(4);
+            let producer = {
+                let ring = ring.clone();
+                thread::spawn(move || ring.try_push(0).unwrap())
+            };
+            assert_eq!(ring.try_push_slice(&[1, 2]), 2);
+            producer.join().unwrap();
+            let mut popped: Vec<_> = (0..3).map(|_| ring.pop().unwrap()).collect();
+            assert!(ring.pop().is_none());
+            // The run of the slice is contiguous.
+            assert!(popped == [0, 1, 2] || popped == [1, 2, 0]);
+            popped.sort();
+            assert_eq!(popped, [0, 1, 2]);
+        });
+    }
+}
@@ -0,0 +1,1041 @@
+//! A bounded multi-producer single-consumer queue: the MPMC queue of
+//! `mpmc_lockfree`, plus the disconnection tracking and wakeups of
+//! the `channel` handles.
+
+#[cfg(feature = "std")]
+use core::time::Duration;
+
+use alloc::vec::Vec;
+
+use crate::error::{Disconnected, PopError, PushError};
+use crate::mpmc_lockfree;
+use crate::sync::{Arc, AtomicBool, AtomicUsize, Ordering};
+use crate::wait::{self, Backoff, Instant, SpinThenYield, WaitStrategy};
+#[cfg(feature = "async")]
+use crate::waker::{WakerList, WakerSlot};
+
+#[cfg(feature = "async")]
+mod future;
+#[cfg(feature = "async")]
+pub use future::{RecvFuture, SendFuture};
+
+pub struct MPSCRingBuffer<T> {
+  queue: mpmc_lockfree::MPMCRingBuffer<T>,
+  /// Live `Sender`s of a `channel`.
+  senders: AtomicUsize,
+  /// Set when the `Receiver` of a `channel` is dropped.
+  receiver_dropped: AtomicBool,
+  /// Values evicted by `force_push`.
+  dropped: AtomicUsize,
+  /// Task waiting in `Receiver::recv_async` for a value.
+  #[cfg(feature = "async")]
+  receiver_waker: WakerSlot,
+  /// Tasks waiting in `Sender::send_async` for a vacant slot.
+  #[cfg(feature = "async")]
+  sender_wakers: WakerList,
+}
+
+#[deprecated(note = "renamed to `MPSCRingBuffer`")]
+pub type RingBuffer<T> = MPSCRingBuffer<T>;
+
+impl<T: Copy> MPSCRingBuffer<T> {
+  /// See `mpmc_lockfree::MPMCRingBuffer::try_push_slice`.
+  pub fn try_push_slice(&self, values: &[T]) -> usize {
+    let n = self.queue.try_push_slice(values);
+    #[cfg(feature = "async")]
+    if n > 0 {
+      self.receiver_waker.wake();
+    }
+    n
+  }
+}
+
+impl<T> MPSCRingBuffer<T> {
+  /// Creates a ring buffer with `capacity` rounded up to the next power of
+  /// two, and at least 2 (see `mpmc_lockfree::MPMCRingBuffer::new`).
+  pub fn new(capacity: usize) -> Arc<Self> {
+    Arc::new(Self {
+      queue: mpmc_lockfree::MPMCRingBuffer::with_capacity(capacity),
+      senders: AtomicUsize::new(0),
+      receiver_dropped: AtomicBool::new(false),
+      dropped: AtomicUsize::new(0),
+      #[cfg(feature = "async")]
+      receiver_waker: WakerSlot::default(),
+      #[cfg(feature = "async")]
+      sender_wakers: WakerList::default(),
+    })
+  }
+
+  /// Returns the maximum number of values the ring buffer can hold.
+  pub fn capacity(&self) -> usize {
+    self.queue.capacity()
+  }
+
+  /// Returns the number of queued values. Approximate while pushes or pops
+  /// are in progress: a claimed slot counts before its value is written, so
+  /// a pop may still find the queue empty. Never above `capacity`.
+  pub fn len(&self) -> usize {
+    self.queue.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.queue.is_empty()
+  }
+
+  /// Returns true if a push would likely fail with `Full`, same caveats as
+  /// `len`: a producer racing the others may still find a vacant slot.
+  pub fn is_full(&self) -> bool {
+    self.len() == self.capacity()
+  }
+
+  /// Pushes a value, or hands it back in `PushError::Full`. Safe to call
+  /// from any number of threads at once.
+  pub fn try_push(&self, item: T) -> Result<(), PushError<T>> {
+    self.queue.try_push(item)?;
+    #[cfg(feature = "async")]
+    self.receiver_waker.wake();
+    Ok(())
+  }
+
+  /// Pushes a value, evicting the oldest one while the queue is full, for
+  /// producers that must never wait (log or metric shipping). Returns the
+  /// evicted value, also counted in `dropped`. Safe to call from any number
+  /// of threads at once, and concurrently with `pop`.
+  pub fn force_push(&self, item: T) -> Option<T> {
+    let mut item = item;
+    let mut evicted = None;
+    let mut backoff = Backoff::default();
+    loop {
+      match self.try_push(item) {
+        Err(PushError::Full(rejected)) => item = rejected,
+        _ => return evicted,
+      }
+      // A push also fails while the oldest value is being popped by the
+      // consumer; that frees its slot, so only a full queue is evicted from.
+      match self.queue.pop_if_full() {
+        Some(oldest) => {
+          self.dropped.fetch_add(1, Ordering::Relaxed);
+          // Only the latest eviction is handed back, the others are dropped.
+          evicted = Some(oldest);
+        }
+        None => backoff.snooze(),
+      }
+    }
+  }
+
+  /// Returns the number of values evicted by `force_push` so far.
+  pub fn dropped(&self) -> usize {
+    self.dropped.load(Ordering::Relaxed)
+  }
+
+  /// Moves a run of values from the front of `values` in one claim, see
+  /// `StagedSender::flush`.
+  fn push_from(&self, values: &mut Vec<T>) -> usize {
+    let n = self.queue.push_from(values);
+    #[cfg(feature = "async")]
+    if n > 0 {
+      self.receiver_waker.wake();
+    }
+    n
+  }
+
+  pub fn pop(&self) -> Option<T> {
+    let item = self.queue.pop()?;
+    #[cfg(feature = "async")]
+    self.sender_wakers.wake_one();
+    Some(item)
+  }
+}
+
+/// Creates a queue of `capacity` (see `MPSCRingBuffer::new`) and returns its
+/// sending and receiving handles. Clone the `Sender` for each producer.
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+  channel_with(capacity, SpinThenYield::default(), SpinThenYield::default())
+}
+
+/// Same as `channel`, with the wait strategies of the blocking sends and
+/// receives, e.g. `wait::Hybrid` to park the waiting threads during long
+/// waits. Clones of the `Sender` share its strategy.
+pub fn channel_with<T, WS, WR>(capacity: usize, sender_wait: WS, receiver_wait: WR) -> (Sender<T, WS>, Receiver<T, WR>)
+where
+  WS: WaitStrategy + Clone,
+  WR: WaitStrategy,
+{
+  let ring = MPSCRingBuffer::new(capacity);
+  ring.senders.store(1, Ordering::Relaxed);
+  (Sender { ring: ring.clone(), wait: sender_wait }, Receiver { ring, wait: receiver_wait })
+}
+
+/// A producer handle of a `channel`. The `Receiver` sees the channel
+/// disconnected once all of them are dropped.
+/// `W` is the strategy used to wait for a vacant slot in `send`.
+pub struct Sender<T, W = SpinThenYield> {
+  ring: Arc<MPSCRingBuffer<T>>,
+  wait: W,
+}
+
+impl<T, W: WaitStrategy> Sender<T, W> {
+  /// See `MPSCRingBuffer::try_push`. Fails with `Disconnected` once the
+  /// `Receiver` is dropped, even if the queue is also full: `Full` is
+  /// transient and worth a retry, `Disconnected` is permanent.
+  pub fn try_push(&self, item: T) -> Result<(), PushError<T>> {
+    if self.is_disconnected() {
+      return Err(PushError::Disconnected(item));
+    }
+    self.ring.try_push(item)
+  }
+
+  /// Pushes a value, evicting and dropping the oldest one if the queue is
+  /// full (see `MPSCRingBuffer::force_push`). Fails only with `Disconnected`.
+  pub fn force_push(&self, item: T) -> Result<(), PushError<T>> {
+    if self.is_disconnected() {
+      return Err(PushError::Disconnected(item));
+    }
+    self.ring.force_push(item);
+    Ok(())
+  }
+
+  /// Pushes a value, waiting for a vacant slot if the queue is full.
+  /// Fails only with `Disconnected`.
+  pub fn send(&self, item: T) -> Result<(), PushError<T>> {
+    self.send_until(item, None)
+  }
+
+  /// Pushes a value, waiting up to `timeout` for a vacant slot.
+  /// On timeout the value is handed back in `PushError::Full`.
+  #[cfg(feature = "std")]
+  pub fn send_timeout(&self, item: T, timeout: Duration) -> Result<(), PushError<T>> {
+    self.send_until(item, Some(Instant::now() + timeout))
+  }
+
+  fn send_until(&self, mut item: T, deadline: Option<Instant>) -> Result<(), PushError<T>> {
+    let mut step = 0;
+    loop {
+      match self.try_push(item) {
+        Err(PushError::Full(rejected)) if !wait::expired(deadline) => {
+          item = rejected;
+          self.wait.wait(step, deadline);
+          step = step.saturating_add(1);
+        }
+        result => return result,
+      }
+    }
+  }
+
+  /// Returns true if the `Receiver` was dropped.
+  pub fn is_disconnected(&self) -> bool {
+    self.ring.receiver_dropped.load(Ordering::Acquire)
+  }
+
+  pub fn capacity(&self) -> usize {
+    self.ring.capacity()
+  }
+
+  /// See `MPSCRingBuffer::len`.
+  pub fn len(&self) -> usize {
+    self.ring.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.ring.is_empty()
+  }
+
+  /// See `MPSCRingBuffer::is_full`.
+  pub fn is_full(&self) -> bool {
+    self.ring.is_full()
+  }
+
+  /// See `MPSCRingBuffer::dropped`.
+  pub fn dropped(&self) -> usize {
+    self.ring.dropped()
+  }
+}
+
+impl<T: Copy, W: WaitStrategy> Sender<T, W> {
+  /// Pushes as many values from `values` as there are vacant slots in a
+  /// row, claiming them at once (see `MPSCRingBuffer::try_push_slice`).
+  /// Returns how many values were pushed.
+  pub fn try_push_slice(&self, values: &[T]) -> Result<usize, Disconnected> {
+    if self.is_disconnected() {
+      return Err(Disconnected);
+    }
+    Ok(self.ring.try_push_slice(values))
+  }
+}
+
+impl<T, W> Sender<T, W> {
+  /// Turns this sender into one that stages up to `batch` values locally
+  /// and publishes them in one claimed run of slots, for very hot
+  /// producers: one CAS on the shared `write` per batch instead of one per
+  /// value, at the cost of the latency of the staged values.
+  pub fn staged(self, batch: usize) -> StagedSender<T, W> {
+    let batch = batch.max(1);
+    StagedSender { sender: self, staged: Vec::with_capacity(batch), batch }
+  }
+
+  /// Turns this sender into one that hands every value it fails to queue,
+  /// on a full queue or a dropped `Receiver`, to `sink` instead of back
+  /// to the caller, e.g. for the audit trail of a payment pipeline.
+  pub fn with_overflow<S: OverflowSink<T>>(self, sink: S) -> OverflowSender<T, W, S> {
+    OverflowSender { sender: self, sink }
+  }
+}
+
+/// Receives the values an `OverflowSender` could not queue: a closure, or
+/// the `Sender` of a secondary channel.
+pub trait OverflowSink<T> {
+  fn overflow(&self, item: T);
+}
+
+impl<T, F: Fn(T)> OverflowSink<T> for F {
+  fn overflow(&self, item: T) {
+    self(item)
+  }
+}
+
+/// Pushes to the secondary channel, and drops the value if it is full or
+/// disconnected too.
+impl<T, W: WaitStrategy> OverflowSink<T> for Sender<T, W> {
+  fn overflow(&self, item: T) {
+    let _ = self.try_push(item);
+  }
+}
+
+/// A `Sender` with a dead-letter sink, made by `Sender::with_overflow`.
+/// Its pushes return true if the value was queued, false if it went to
+/// the sink.
+pub struct OverflowSender<T, W, S> {
+  sender: Sender<T, W>,
+  sink: S,
+}
+
+impl<T, W: WaitStrategy, S: OverflowSink<T>> OverflowSender<T, W, S> {
+  /// See `Sender::try_push`.
+  pub fn try_push(&self, item: T) -> bool {
+    self.divert(self.sender.try_push(item))
+  }
+
+  /// See `Sender::send_timeout`.
+  #[cfg(feature = "std")]
+  pub fn send_timeout(&self, item: T, timeout: Duration) -> bool {
+    self.divert(self.sender.send_timeout(item, timeout))
+  }
+
+  fn divert(&self, result: Result<(), PushError<T>>) -> bool {
+    match result {
+      Ok(()) => true,
+      Err(e) => {
+        self.sink.overflow(e.into_inner());
+        false
+      }
+    }
+  }
+
+  pub fn sender(&self) -> &Sender<T, W> {
+    &self.sender
+  }
+}
+
+impl<T, W: Clone, S: Clone> Clone for OverflowSender<T, W, S> {
+  fn clone(&self) -> Self {
+    OverflowSender { sender: self.sender.clone(), sink: self.sink.clone() }
+  }
+}
+
+/// A `Sender` staging its values, made by `Sender::staged`. The staged
+/// values are only seen by the `Receiver` once flushed, by `flush` or
+/// once `batch` of them are staged. Dropping it flushes what fits in the
+/// queue and drops the rest.
+pub struct StagedSender<T, W = SpinThenYield> {
+  sender: Sender<T, W>,
+  staged: Vec<T>,
+  batch: usize,
+}
+
+impl<T, W: WaitStrategy> StagedSender<T, W> {
+  /// Stages a value, flushing the batch once it is complete. Fails with
+  /// `Full` if the batch is complete and the queue has no room for any of
+  /// it, or with `Disconnected` once the `Receiver` is dropped.
+  pub fn push(&mut self, item: T) -> Result<(), PushError<T>> {
+    if self.sender.is_disconnected() {
+      return Err(PushError::Disconnected(item));
+    }
+    if self.staged.len() == self.batch && self.flush() == self.batch {
+      return Err(PushError::Full(item));
+    }
+    self.staged.push(item);
+    if self.staged.len() == self.batch {
+      self.flush();
+    }
+    Ok(())
+  }
+}
+
+impl<T, W> StagedSender<T, W> {
+  /// Publishes the staged values, as many as there are vacant slots in a
+  /// row, in order. Returns the number of values still staged.
+  pub fn flush(&mut self) -> usize {
+    if !self.staged.is_empty() {
+      self.sender.ring.push_from(&mut self.staged);
+    }
+    self.staged.len()
+  }
+
+  /// Number of values staged and not published yet.
+  pub fn pending(&self) -> usize {
+    self.staged.len()
+  }
+
+  pub fn sender(&self) -> &Sender<T, W> {
+    &self.sender
+  }
+}
+
+impl<T, W> Drop for StagedSender<T, W> {
+  fn drop(&mut self) {
+    self.flush();
+  }
+}
+
+impl<T, W: Clone> Clone for Sender<T, W> {
+  fn clone(&self) -> Self {
+    self.ring.senders.fetch_add(1, Ordering::Relaxed);
+    Sender { ring: self.ring.clone(), wait: self.wait.clone() }
+  }
+}
+
+impl<T, W> Drop for Sender<T, W> {
+  fn drop(&mut self) {
+    // Release: the pushes of every sender happen before the `Receiver`
+    // sees the count reach 0.
+    if self.ring.senders.fetch_sub(1, Ordering::Release) == 1 {
+      #[cfg(feature = "async")]
+      self.ring.receiver_waker.wake();
+    }
+  }
+}
+
+/// The consumer handle of a `channel`, there is only one.
+/// `W` is the strategy used to wait for a value in `recv`.
+pub struct Receiver<T, W = SpinThenYield> {
+  ring: Arc<MPSCRingBuffer<T>>,
+  wait: W,
+}
+
+impl<T, W: WaitStrategy> Receiver<T, W> {
+  /// Pops the oldest value. Values pushed before the last `Sender` was
+  /// dropped are still delivered, `Disconnected` is only returned once the
+  /// queue is drained.
+  pub fn pop(&mut self) -> Result<T, PopError> {
+    if let Some(item) = self.ring.pop() {
+      return Ok(item);
+    }
+    if !self.is_disconnected() {
+      return Err(PopError::Empty);
+    }
+    // A sender may have pushed right before dropping.
+    self.ring.pop().ok_or(PopError::Disconnected)
+  }
+
+  /// Pops the oldest value, waiting for one if the queue is empty. Fails
+  /// once all the `Sender`s are dropped and the queue is drained.
+  pub fn recv(&mut self) -> Result<T, Disconnected> {
+    self.recv_until(None).map_err(|_| Disconnected)
+  }
+
+  /// Returns an iterator receiving values with `recv` until all the
+  /// `Sender`s are dropped: `for value in rx.iter()`.
+  pub fn iter(&mut self) -> Iter<'_, T, W> {
+    Iter { rx: self }
+  }
+
+  /// Returns an iterator popping the values already queued, it ends as
+  /// soon as the queue is observed empty.
+  pub fn try_iter(&mut self) -> TryIter<'_, T, W> {
+    TryIter { rx: self }
+  }
+
+  /// Pops the oldest value, waiting up to `timeout` for one. Fails with
+  /// `Empty` on timeout.
+  #[cfg(feature = "std")]
+  pub fn recv_timeout(&mut self, timeout: Duration) -> Result<T, PopError> {
+    self.recv_until(Some(Instant::now() + timeout))
+  }
+
+  /// Same as `recv_timeout`, waiting until `deadline`.
+  #[cfg(feature = "std")]
+  pub fn recv_deadline(&mut self, deadline: Instant) -> Result<T, PopError> {
+    self.recv_until(Some(deadline))
+  }
+
+  fn recv_until(&mut self, deadline: Option<Instant>) -> Result<T, PopError> {
+    let mut step = 0;
+    loop {
+      match self.pop() {
+        Err(PopError::Empty) if !wait::expired(deadline) => {
+          self.wait.wait(step, deadline);
+          step = step.saturating_add(1);
+        }
+        result => return result,
+      }
+    }
+  }
+
+  /// Returns true if all the `Sender`s were dropped.
+  pub fn is_disconnected(&self) -> bool {
+    self.ring.senders.load(Ordering::Acquire) == 0
+  }
+
+  pub fn capacity(&self) -> usize {
+    self.ring.capacity()
+  }
+
+  /// See `MPSCRingBuffer::len`.
+  pub fn len(&self) -> usize {
+    self.ring.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.ring.is_empty()
+  }
+
+  /// Values lost to `Sender::force_push`, see `MPSCRingBuffer::dropped`.
+  pub fn dropped(&self) -> usize {
+    self.ring.dropped()
+  }
+}
+
+impl<'a, T, W: WaitStrategy> IntoIterator for &'a mut Receiver<T, W> {
+  type Item = T;
+  type IntoIter = Iter<'a, T, W>;
+
+  fn into_iter(self) -> Iter<'a, T, W> {
+    self.iter()
+  }
+}
+
+/// Iterator returned by `Receiver::iter`.
+pub struct Iter<'a, T, W> {
+  rx: &'a mut Receiver<T, W>,
+}
+
+impl<T, W: WaitStrategy> Iterator for Iter<'_, T, W> {
+  type Item = T;
+
+  fn next(&mut self) -> Option<T> {
+    self.rx.recv().ok()
+  }
+}
+
+/// Iterator returned by `Receiver::try_iter`.
+pub struct TryIter<'a, T, W> {
+  rx: &'a mut Receiver<T, W>,
+}
+
+impl<T, W: WaitStrategy> Iterator for TryIter<'_, T, W> {
+  type Item = T;
+
+  fn next(&mut self) -> Option<T> {
+    self.rx.pop().ok()
+  }
+}
+
+impl<T, W> Drop for Receiver<T, W> {
+  fn drop(&mut self) {
+    self.ring.receiver_dropped.store(true, Ordering::Release);
+    #[cfg(feature = "async")]
+    self.ring.sender_wakers.wake_all();
+  }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+  use super::*;
+  use std::sync::atomic::AtomicUsize;
+  use std::thread;
+
+  static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+  struct Counted(#[allow(dead_code)] u64);
+
+  impl Drop for Counted {
+    fn drop(&mut self) {
+      DROPS.fetch_add(1, Ordering::SeqCst);
+    }
+  }
+
+  #[test]
+  fn capacity_rounds_up() {
+    let buffer = MPSCRingBuffer::new(3);
+    assert_eq!(buffer.capacity(), 4);
+    for i in 0..4 {
+      assert!(buffer.try_push(i).is_ok());
+    }
+    assert_eq!(buffer.try_push(4), Err(PushError::Full(4)));
+    assert!(buffer.is_full());
+    assert_eq!(buffer.pop(), Some(0));
+    assert_eq!(buffer.len(), 3);
+    assert!(!buffer.is_full() && !buffer.is_empty());
+    assert_eq!(MPSCRingBuffer::<u8>::new(1).capacity(), 2);
+  }
+
+  #[test]
+  fn drop_queued_values() {
+    let buffer = MPSCRingBuffer::new(4);
+    for i in 0..3 {
+      assert!(buffer.try_push(Counted(i)).is_ok());
+    }
+    drop(buffer.pop());
+    assert_eq!(DROPS.load(Ordering::SeqCst), 1);
+    assert!(buffer.try_push(Counted(3)).is_ok());
+    assert_eq!(DROPS.load(Ordering::SeqCst), 1);
+    drop(buffer);
+    assert_eq!(DROPS.load(Ordering::SeqCst), 4);
+  }
+
+  #[test]
+  fn non_zeroable_values() {
+    // Slots are `MaybeUninit`: a type without a valid all-zero value is
+    // only ever read once written, across several laps.
+    let buffer = MPSCRingBuffer::new(2);
+    for i in 1..=5u32 {
+      assert!(buffer.try_push((core::num::NonZeroU32::new(i).unwrap(), Box::new(i))).is_ok());
+      let (n, boxed) = buffer.pop().unwrap();
+      assert_eq!((n.get(), *boxed), (i, i));
+    }
+    assert!(buffer.pop().is_none());
+  }
+
+  #[test]
+  fn force_push() {
+    let buffer = MPSCRingBuffer::new(2);
+    assert_eq!(buffer.force_push(0), None);
+    assert_eq!(buffer.force_push(1), None);
+    assert_eq!(buffer.force_push(2), Some(0));
+    assert_eq!(buffer.force_push(3), Some(1));
+    assert_eq!(buffer.dropped(), 2);
+    assert_eq!(buffer.pop(), Some(2));
+    assert_eq!(buffer.force_push(4), None);
+    assert_eq!(buffer.pop(), Some(3));
+    assert_eq!(buffer.pop(), Some(4));
+  }
+
+  #[test]
+  fn lossy_senders_never_wait() {
+    let (tx, mut rx) = channel(4);
+    let senders: Vec<_> = (0..3)
+      .map(|p| {
+        let tx = tx.clone();
+        thread::spawn(move || {
+          for i in 0..1000 {
+            tx.force_push(p * 1000 + i).unwrap();
+          }
+        })
+      })
+      .collect();
+    drop(tx);
+    let mut received = 0;
+    while rx.recv().is_ok() {
+      received += 1;
+      thread::yield_now();
+    }
+    for sender in senders {
+      sender.join().unwrap();
+    }
+    assert_eq!(received + rx.dropped(), 3000);
+  }
+
+  #[test]
+  fn bursty_slice_senders() {
+    let (tx, mut rx) = channel(8);
+    let senders: Vec<_> = (0..3)
+      .map(|p| {
+        let tx = tx.clone();
+        thread::spawn(move || {
+          let burst: Vec<u32> = (0..100).map(|i| p * 100 + i).collect();
+          let mut rest = &burst[..];
+          while !rest.is_empty() {
+            rest = &rest[tx.try_push_slice(rest).unwrap()..];
+            thread::yield_now();
+          }
+        })
+      })
+      .collect();
+    drop(tx);
+    let mut received: Vec<_> = rx.iter().collect();
+    for sender in senders {
+      sender.join().unwrap();
+    }
+    received.sort();
+    assert_eq!(received, (0..300).collect::<Vec<_>>());
+    assert_eq!(rx.try_iter().count(), 0);
+  }
+
+  #[test]
+  fn staged_sender() {
+    let (tx, mut rx) = channel(4);
+    let mut tx = tx.staged(3);
+    assert!(tx.push(0).is_ok() && tx.push(1).is_ok());
+    assert_eq!(tx.pending(), 2);
+    assert_eq!(rx.pop(), Err(PopError::Empty));
+    // Completes the batch.
+    assert!(tx.push(2).is_ok());
+    assert_eq!(tx.pending(), 0);
+    for i in 3..6 {
+      assert!(tx.push(i).is_ok());
+    }
+    // One slot was vacant: 4 and 5 stay staged.
+    assert_eq!(tx.pending(), 2);
+    assert!(tx.push(6).is_ok());
+    assert_eq!(tx.push(7), Err(PushError::Full(7)));
+    assert_eq!(rx.try_iter().collect::<Vec<_>>(), [0, 1, 2, 3]);
+    drop(tx);
+    assert_eq!(rx.iter().collect::<Vec<_>>(), [4, 5, 6]);
+  }
+
+  #[test]
+  fn concurrent_producers() {
+    // Miri runs the threads a few thousand times slower.
+    const N: usize = if cfg!(miri) { 100 } else { 1000 };
+    let buffer = MPSCRingBuffer::new(8);
+    let producers: Vec<_> = (0..4)
+      .map(|p| {
+        let buffer = buffer.clone();
+        thread::spawn(move || {
+          for i in 0..N {
+            let mut value = p * N + i;
+            while let Err(e) = buffer.try_push(value) {
+              value = e.into_inner();
+              thread::yield_now();
+            }
+          }
+        })
+      })
+      .collect();
+    let mut seen = vec![false; 4 * N];
+    let mut last = [None; 4];
+    for _ in 0..4 * N {
+      let value = loop {
+        match buffer.pop() {
+          Some(value) => break value,
+          None => thread::yield_now(),
+        }
+      };
+      assert!(!seen[value], "{value} popped twice");
+      seen[value] = true;
+      // Values of one producer come out in order.
+      assert!(last[value / N] < Some(value));
+      last[value / N] = Some(value);
+    }
+    for producer in producers {
+      producer.join().unwrap();
+    }
+    assert_eq!(buffer.pop(), None);
+  }
+
+  #[test]
+  fn channel_senders() {
+    let (tx, mut rx) = channel(4);
+    let handles: Vec<_> = (0..2)
+      .map(|i| {
+        let tx = tx.clone();
+        thread::spawn(move || tx.try_push(i).unwrap())
+      })
+      .collect();
+    for handle in handles {
+      handle.join().unwrap();
+    }
+    assert!(tx.try_push(2).is_ok());
+    let mut values = [rx.pop().unwrap(), rx.pop().unwrap()];
+    values.sort();
+    assert_eq!(values, [0, 1]);
+    assert_eq!(rx.pop(), Ok(2));
+    assert_eq!(rx.pop(), Err(PopError::Empty));
+  }
+
+  #[test]
+  fn disconnect() {
+    let (tx, mut rx) = channel(4);
+    let tx2 = tx.clone();
+    assert!(tx.try_push(1).is_ok());
+    drop(tx);
+    assert!(!rx.is_disconnected());
+    assert!(tx2.try_push(2).is_ok());
+    drop(tx2);
+    assert_eq!(rx.pop(), Ok(1));
+    assert_eq!(rx.pop(), Ok(2));
+    assert_eq!(rx.pop(), Err(PopError::Disconnected));
+
+    let (tx, rx) = channel(4);
+    drop(rx);
+    assert!(tx.is_disconnected());
+    assert_eq!(tx.try_push(1), Err(PushError::Disconnected(1)));
+
+    // A full queue reports `Full` until the receiver goes away.
+    let (tx, rx) = channel(2);
+    assert!(tx.try_push(1).is_ok() && tx.try_push(2).is_ok());
+    assert_eq!(tx.try_push(3), Err(PushError::Full(3)));
+    drop(rx);
+    assert_eq!(tx.try_push(3), Err(PushError::Disconnected(3)));
+  }
+
+  #[test]
+  fn recv() {
+    let (tx, mut rx) = channel(2);
+    let producer = thread::spawn(move || {
+      for i in 0..100 {
+        assert!(tx.send(i).is_ok());
+      }
+    });
+    for i in 0..100 {
+      assert_eq!(rx.recv(), Ok(i));
+    }
+    producer.join().unwrap();
+    assert_eq!(rx.recv(), Err(Disconnected));
+  }
+
+  #[test]
+  fn iterators() {
+    let (tx, mut rx) = channel(4);
+    for i in 0..3 {
+      assert!(tx.try_push(i).is_ok());
+    }
+    assert_eq!(rx.try_iter().collect::<Vec<_>>(), [0, 1, 2]);
+    assert_eq!(rx.try_iter().next(), None);
+    let producer = thread::spawn(move || {
+      for i in 0..10 {
+        assert!(tx.send(i).is_ok());
+      }
+    });
+    // Ends once the sender is dropped.
+    assert_eq!((&mut rx).into_iter().sum::<i32>(), 45);
+    producer.join().unwrap();
+  }
+
+  #[cfg(feature = "std")]
+  #[test]
+  fn send_timeout() {
+    use std::time::Duration;
+
+    let (tx, rx) = channel(2);
+    assert!(tx.send(1).is_ok());
+    assert!(tx.send(2).is_ok());
+    assert_eq!(tx.send_timeout(3, Duration::from_millis(10)), Err(PushError::Full(3)));
+    drop(rx);
+    assert_eq!(tx.send(3), Err(PushError::Disconnected(3)));
+  }
+
+  #[test]
+  fn overflow_sinks() {
+    let (tx, rx) = channel(2);
+    let (dead_tx, mut dead_rx) = channel(4);
+    let tx = tx.with_overflow(dead_tx);
+    assert!(tx.try_push(1) && tx.try_push(2));
+    assert!(!tx.try_push(3));
+    drop(rx);
+    assert!(!tx.try_push(4));
+    assert_eq!(dead_rx.try_iter().collect::<Vec<_>>(), [3, 4]);
+
+    let audit = std::sync::Mutex::new(Vec::new());
+    let (tx, _rx) = channel(2);
+    let tx = tx.with_overflow(|item| audit.lock().unwrap().push(item));
+    for i in 0..4 {
+      tx.try_push(i);
+    }
+    assert_eq!(*audit.lock().unwrap(), [2, 3]);
+  }
+
+  #[cfg(feature = "std")]
+  #[test]
+  fn recv_timeout() {
+    use std::time::{Duration, Instant};
+
+    let (tx, mut rx) = channel(2);
+    let start = Instant::now();
+    assert_eq!(rx.recv_timeout(Duration::from_millis(10)), Err(PopError::Empty));
+    assert!(start.elapsed() >= Duration::from_millis(10));
+    assert!(tx.try_push(1).is_ok());
+    assert_eq!(rx.recv_deadline(Instant::now() + Duration::from_secs(10)), Ok(1));
+    drop(tx);
+    assert_eq!(rx.recv_timeout(Duration::from_secs(10)), Err(PopError::Disconnected));
+  }
+
+  #[test]
+  fn test_ring_buffer() {
+    let buffer = MPSCRingBuffer::new(3);
+    let buffer_clone = Arc::clone(&buffer);
+
+    let producer = thread::spawn(move || {
+      for i in 0..100 {
+        if buffer_clone.try_push(i).is_ok() {
+          println!("Produced: {}", i);
+        }
+      }
+    });
+
+    let consumer = thread::spawn(move || {
+      for _ in 0..100 {
+        while let Some(item) = buffer.pop() {
+          println!("Consumed: {}", item);
+        }
+      }
+    });
+
+    producer.join().unwrap();
+    consumer.join().unwrap();
+  }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+  use super::*;
+  use loom::thread;
+
+  #[test]
+  fn push_and_pop() {
+    loom::model(|| {
+      let buffer = MPSCRingBuffer::new(2);
+      let producer = {
+        let buffer = buffer.clone();
+        thread::spawn(move || {
+          for i in 0..2 {
+            while buffer.try_push(i).is_err() {
+              thread::yield_now();
+            }
+          }
+        })
+      };
+      for i in 0..2 {
+        loop {
+          match buffer.pop() {
+            Some(item) => {
+              assert_eq!(item, i);
+              break;
+            }
+            None => thread::yield_now(),
+          }
+        }
+      }
+      producer.join().unwrap();
+    });
+  }
+
+  #[test]
+  fn force_push_racing_a_pop() {
+    loom::model(|| {
+      let buffer = MPSCRingBuffer::new(2);
+      buffer.try_push(0).unwrap();
+      buffer.try_push(1).unwrap();
+      let consumer = {
+        let buffer = buffer.clone();
+        thread::spawn(move || buffer.pop())
+      };
+      let evicted = buffer.force_push(2);
+      let popped = consumer.join().unwrap();
+      // Once 0 is popped there is room for 2: 1 must not be evicted.
+      match (popped, evicted) {
+        (Some(0), None) => assert_eq!((buffer.pop(), buffer.pop()), (Some(1), Some(2))),
+        (Some(1), Some(0)) => assert_eq!(buffer.pop(), Some(2)),
+        other => panic!("unexpected {other:?}"),
+      }
+    });
+  }
+
+  #[test]
+  fn two_producers() {
+    loom::model(|| {
+      let buffer = MPSCRingBuffer::new(2);
+      let producers: Vec<_> = (0..2)
+        .map(|i| {
+          let buffer = buffer.clone();
+          thread::spawn(move || buffer.try_push(i).unwrap())
+        })
+        .collect();
+      let mut popped = Vec::new();
+      while popped.len() < 2 {
+        match buffer.pop() {
+          Some(item) => popped.push(item),
+          None => thread::yield_now(),
+        }
+      }
+      popped.sort();
+      assert_eq!(popped, [0, 1]);
+      for producer in producers {
+        producer.join().unwrap();
+      }
+    });
+  }
+
+  #[test]
+  fn claims_race_a_wrapping_pop() {
+    loom::model(|| {
+      // One vacant slot left: the pop frees the other one, whose next
+      // position is a lap later.
+      let buffer = MPSCRingBuffer::new(2);
+      buffer.try_push(0).unwrap();
+      let producers: Vec<_> = (1..3)
+        .map(|i| {
+          let buffer = buffer.clone();
+          thread::spawn(move || buffer.try_push(i))
+        })
+        .collect();
+      assert_eq!(buffer.pop(), Some(0));
+      let mut values: Vec<_> = producers.into_iter().filter_map(|p| p.join().unwrap().err()).map(PushError::into_inner).collect();
+      // Only a claim made before the pop can find the queue full.
+      assert!(values.len() <= 1);
+      while let Some(item) = buffer.pop() {
+        values.push(item);
+      }
+      values.sort();
+      assert_eq!(values, [1, 2]);
+    });
+  }
+
+  #[test]
+  fn order_of_each_producer() {
+    loom::model(|| {
+      let buffer = MPSCRingBuffer::new(4);
+      let first = {
+        let buffer = buffer.clone();
+        thread::spawn(move || {
+          buffer.try_push(0).unwrap();
+          buffer.try_push(1).unwrap();
+        })
+      };
+      let second = {
+        let buffer = buffer.clone();
+        thread::spawn(move || buffer.try_push(10).unwrap())
+      };
+      let mut popped = Vec::new();
+      while popped.len() < 3 {
+        match buffer.pop() {
+          Some(item) => popped.push(item),
+          None => thread::yield_now(),
+        }
+      }
+      popped.retain(|&i| i < 10);
+      assert_eq!(popped, [0, 1]);
+      first.join().unwrap();
+      second.join().unwrap();
+    });
+  }
+
+  #[test]
+  fn values_before_disconnect() {
+    loom::model(|| {
+      let (tx, mut rx) = channel(2);
+      let producer = thread::spawn(move || tx.try_push(1).unwrap());
+      let mut popped = None;
+      loop {
+        match rx.pop() {
+          Ok(item) => popped = Some(item),
+          Err(PopError::Empty) => thread::yield_now(),
+          Err(PopError::Disconnected) => break,
+        }
+      }
+      // The value pushed before the drop is never lost.
+      assert_eq!(popped, Some(1));
+      producer.join().unwrap();
+    });
+  }
+}
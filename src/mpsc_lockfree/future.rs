@@ -0,0 +1,178 @@
+//! Futures bridging the MPSC channel to async tasks. A receiving task that
+//! finds the queue empty registers its waker in the ring and is woken by
+//! the next push; sending tasks that find it full queue up their wakers and
+//! each pop wakes the oldest one, so many producers can wait at once
+//! without polling.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use super::{Receiver, Sender};
+use crate::error::{PopError, PushError};
+use crate::wait::WaitStrategy;
+
+impl<T, W: WaitStrategy> Sender<T, W> {
+  /// Pushes a value, waiting asynchronously for a vacant slot.
+  /// Fails only with `Disconnected`.
+  pub fn send_async(&self, item: T) -> SendFuture<'_, T, W> {
+    SendFuture { sender: self, item: Some(item), key: None }
+  }
+}
+
+impl<T, W: WaitStrategy> Receiver<T, W> {
+  /// Polls for the oldest value. Fails only with `Disconnected`.
+  pub fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Result<T, PopError>> {
+    match self.pop() {
+      Err(PopError::Empty) => {}
+      result => return Poll::Ready(result),
+    }
+    self.ring.receiver_waker.register(cx.waker());
+    // A sender may have pushed before the waker was registered.
+    match self.pop() {
+      Err(PopError::Empty) => Poll::Pending,
+      result => Poll::Ready(result),
+    }
+  }
+
+  /// Pops the oldest value, waiting asynchronously for one to be pushed.
+  /// Fails only with `Disconnected`.
+  pub fn recv_async(&mut self) -> RecvFuture<'_, T, W> {
+    RecvFuture { receiver: self }
+  }
+}
+
+/// Future returned by `Sender::send_async`.
+pub struct SendFuture<'a, T, W> {
+  sender: &'a Sender<T, W>,
+  item: Option<T>,
+  /// Entry in the waiting senders, once registered.
+  key: Option<usize>,
+}
+
+// The value is never pinned, it is moved into the ring.
+impl<T, W> Unpin for SendFuture<'_, T, W> {}
+
+impl<T, W: WaitStrategy> SendFuture<'_, T, W> {
+  fn complete(&mut self, result: Result<(), PushError<T>>) -> Poll<Result<(), PushError<T>>> {
+    if let Some(key) = self.key.take() {
+      self.sender.ring.sender_wakers.remove(key);
+    }
+    Poll::Ready(result)
+  }
+}
+
+impl<T, W: WaitStrategy> Future for SendFuture<'_, T, W> {
+  type Output = Result<(), PushError<T>>;
+
+  fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+    let this = &mut *self;
+    let item = this.item.take().expect("SendFuture polled after completion");
+    let item = match this.sender.try_push(item) {
+      Err(PushError::Full(item)) => item,
+      result => return this.complete(result),
+    };
+    this.sender.ring.sender_wakers.register(&mut this.key, cx.waker());
+    // The receiver may have popped before the waker was registered.
+    match this.sender.try_push(item) {
+      Err(PushError::Full(item)) => {
+        this.item = Some(item);
+        Poll::Pending
+      }
+      result => this.complete(result),
+    }
+  }
+}
+
+impl<T, W> Drop for SendFuture<'_, T, W> {
+  fn drop(&mut self) {
+    if let Some(key) = self.key {
+      self.sender.ring.sender_wakers.cancel(key);
+    }
+  }
+}
+
+/// Future returned by `Receiver::recv_async`.
+pub struct RecvFuture<'a, T, W> {
+  receiver: &'a mut Receiver<T, W>,
+}
+
+impl<T, W: WaitStrategy> Future for RecvFuture<'_, T, W> {
+  type Output = Result<T, PopError>;
+
+  fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+    self.receiver.poll_recv(cx)
+  }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+  use super::*;
+  use crate::error::Disconnected;
+  use crate::mpsc_lockfree::channel;
+  use futures::executor::block_on;
+  use std::sync::atomic::{AtomicUsize, Ordering};
+  use std::sync::Arc;
+  use std::task::{Wake, Waker};
+  use std::thread;
+
+  #[test]
+  fn many_senders_one_receiver() {
+    let (tx, mut rx) = channel(2);
+    let senders: Vec<_> = (0..4)
+      .map(|p| {
+        let tx = tx.clone();
+        thread::spawn(move || {
+          block_on(async {
+            for i in 0..100 {
+              tx.send_async(p * 100 + i).await.unwrap();
+            }
+          })
+        })
+      })
+      .collect();
+    drop(tx);
+    let received = block_on(async {
+      let mut received = Vec::new();
+      while let Ok(value) = rx.recv_async().await {
+        received.push(value);
+      }
+      received
+    });
+    for sender in senders {
+      sender.join().unwrap();
+    }
+    assert_eq!(received.len(), 400);
+    assert_eq!(rx.recv(), Err(Disconnected));
+  }
+
+  #[test]
+  fn dropped_send_passes_the_wakeup_on() {
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+      fn wake(self: Arc<Self>) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+      }
+    }
+
+    let (tx, mut rx) = channel(2);
+    assert!(tx.try_push(0).is_ok());
+    assert!(tx.try_push(1).is_ok());
+    let noop = futures::task::noop_waker();
+    let count = Arc::new(CountingWaker(AtomicUsize::new(0)));
+    let waker = Waker::from(count.clone());
+    let mut first = tx.send_async(2);
+    let mut second = tx.send_async(3);
+    assert!(Pin::new(&mut first).poll(&mut Context::from_waker(&noop)).is_pending());
+    assert!(Pin::new(&mut second).poll(&mut Context::from_waker(&waker)).is_pending());
+    // Wakes `first`, which gives up: `second` must get the slot.
+    assert_eq!(rx.pop(), Ok(0));
+    assert_eq!(count.0.load(Ordering::SeqCst), 0);
+    drop(first);
+    assert_eq!(count.0.load(Ordering::SeqCst), 1);
+    assert_eq!(Pin::new(&mut second).poll(&mut Context::from_waker(&waker)), Poll::Ready(Ok(())));
+    drop(rx);
+    assert_eq!(block_on(tx.send_async(4)), Err(PushError::Disconnected(4)));
+  }
+}
@@ -0,0 +1,307 @@
+//! A persistent ring of byte records in a memory-mapped file: a tiny
+//! bounded write-ahead log. Each record is its length and a CRC-32,
+//! followed by its bytes; the checksum also covers the record's offset in
+//! the log, so that a stale record left by an earlier lap never passes for
+//! a new one. `append` and `pop` only change the mapping, `commit` writes
+//! the records back to the file and then, in a second step, the `read` and
+//! `write` offsets of the header.
+//! `open` recovers the ring after a crash: it keeps the longest run of
+//! valid records from the committed `read` offset, which includes the
+//! records appended but not committed yet if they reached the file whole,
+//! and drops the torn one after them, if any.
+
+use alloc::vec::Vec;
+use core::mem;
+use std::fs::OpenOptions;
+use std::io;
+use std::path::Path;
+
+use crate::error::MessageError;
+use crate::mmap::Mapping;
+
+/// Identifies a persistent ring, and the version of its layout.
+const MAGIC: u64 = u64::from_le_bytes(*b"RINGWAL1");
+/// Length of the header of a record: its length, then its checksum.
+const RECORD_HEADER: usize = 8;
+
+/// Start of the file, followed by the records.
+#[repr(C)]
+struct Header {
+    magic: u64,
+    /// Bytes of records the ring can hold.
+    capacity: u64,
+    /// Committed offset of the oldest record, free-running.
+    read: u64,
+    /// Committed offset past the newest record, free-running.
+    write: u64,
+}
+
+/// Lookup table of the CRC-32 (IEEE) polynomial.
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { 0xEDB8_8320 ^ (crc >> 1) } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Checksum of the record of `bytes` at `offset`.
+fn checksum(offset: u64, bytes: &[u8]) -> u32 {
+    let header = offset.to_le_bytes().into_iter().chain((bytes.len() as u32).to_le_bytes());
+    let crc = header.chain(bytes.iter().copied()).fold(!0u32, |crc, byte| {
+        CRC_TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    });
+    !crc
+}
+
+/// A ring of byte records in a file, see the module documentation.
+pub struct PersistentRingBuffer {
+    map: Mapping,
+    capacity: u64,
+    /// `read` of the header: the space up to it may be reused.
+    committed_read: u64,
+    /// Offset of the oldest record.
+    read: u64,
+    /// Offset past the newest record.
+    write: u64,
+    /// Records between `read` and `write`.
+    len: usize,
+}
+
+// The mapping is only accessed through `&mut self`.
+unsafe impl Send for PersistentRingBuffer {}
+
+impl PersistentRingBuffer {
+    /// Creates (or truncates) the file at `path`, holding an empty ring of
+    /// `capacity` bytes of records, and at least 16.
+    pub fn create(path: impl AsRef<Path>, capacity: usize) -> io::Result<Self> {
+        let capacity = capacity.max(16) as u64;
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path)?;
+        file.set_len(mem::size_of::<Header>() as u64 + capacity)?;
+        let map = Mapping::new(&file, mem::size_of::<Header>() + capacity as usize)?;
+        let ring = PersistentRingBuffer { map, capacity, committed_read: 0, read: 0, write: 0, len: 0 };
+        // Fresh mapping, owned by this ring.
+        unsafe { ring.header().write(Header { magic: MAGIC, capacity, read: 0, write: 0 }) };
+        ring.map.sync()?;
+        Ok(ring)
+    }
+
+    /// Maps the ring at `path` and recovers its valid records, see the
+    /// module documentation. Fails with `InvalidData` if the file is not a
+    /// persistent ring.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let len = file.metadata()?.len();
+        if len < mem::size_of::<Header>() as u64 {
+            return Err(invalid_data("file too short for a ring header"));
+        }
+        let map = Mapping::new(&file, len as usize)?;
+        // The header is within the mapping, checked above.
+        let header = unsafe { map.as_ptr().cast::<Header>().read() };
+        if header.magic != MAGIC {
+            return Err(invalid_data("not a persistent ring"));
+        }
+        if header.capacity < 16 || mem::size_of::<Header>() as u64 + header.capacity > len {
+            return Err(invalid_data("capacity does not match the file"));
+        }
+        let (read, capacity) = (header.read, header.capacity);
+        let mut ring = PersistentRingBuffer { map, capacity, committed_read: read, read, write: read, len: 0 };
+        while let Some(next) = ring.valid_record_end(ring.write) {
+            ring.write = next;
+            ring.len += 1;
+        }
+        Ok(ring)
+    }
+
+    /// Returns the offset past the record at `offset` if it is whole, with
+    /// a matching checksum, and within the ring.
+    fn valid_record_end(&self, offset: u64) -> Option<u64> {
+        let limit = self.committed_read + self.capacity;
+        if offset + RECORD_HEADER as u64 > limit {
+            return None;
+        }
+        let mut head = [0; RECORD_HEADER];
+        self.copy_out(offset, &mut head);
+        let len = u32::from_le_bytes(head[..4].try_into().unwrap()) as u64;
+        let end = offset + RECORD_HEADER as u64 + len;
+        if end > limit {
+            return None;
+        }
+        let mut bytes = vec![0; len as usize];
+        self.copy_out(offset + RECORD_HEADER as u64, &mut bytes);
+        (u32::from_le_bytes(head[4..].try_into().unwrap()) == checksum(offset, &bytes)).then_some(end)
+    }
+
+    /// Appends a copy of `record`, durable once committed. Fails with
+    /// `MessageError::Full` until enough space is popped and committed,
+    /// and `TooLong` if there never will be.
+    pub fn append(&mut self, record: &[u8]) -> Result<(), MessageError> {
+        let max = self.capacity as usize - RECORD_HEADER;
+        if record.len() > max {
+            return Err(MessageError::TooLong(max));
+        }
+        let end = self.write + (RECORD_HEADER + record.len()) as u64;
+        // Space popped since the last commit still holds records that a
+        // recovery would start from.
+        if end - self.committed_read > self.capacity {
+            return Err(MessageError::Full);
+        }
+        let mut head = [0; RECORD_HEADER];
+        head[..4].copy_from_slice(&(record.len() as u32).to_le_bytes());
+        head[4..].copy_from_slice(&checksum(self.write, record).to_le_bytes());
+        self.copy_in(self.write, &head);
+        self.copy_in(self.write + RECORD_HEADER as u64, record);
+        self.write = end;
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Pops the oldest record. It is still in the file, and recovered by
+    /// `open`, until the next commit.
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        if self.len == 0 {
+            return None;
+        }
+        let mut head = [0; RECORD_HEADER];
+        self.copy_out(self.read, &mut head);
+        let mut record = vec![0; u32::from_le_bytes(head[..4].try_into().unwrap()) as usize];
+        self.copy_out(self.read + RECORD_HEADER as u64, &mut record);
+        self.read += (RECORD_HEADER + record.len()) as u64;
+        self.len -= 1;
+        Some(record)
+    }
+
+    /// Makes the appends and pops so far durable: writes the records back
+    /// to the file, then the offsets, waiting for each.
+    pub fn commit(&mut self) -> io::Result<()> {
+        self.map.sync()?;
+        // Owned by this ring, see `create`.
+        unsafe {
+            (*self.header()).read = self.read;
+            (*self.header()).write = self.write;
+        }
+        self.map.sync()?;
+        self.committed_read = self.read;
+        Ok(())
+    }
+
+    /// Returns the number of records.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the bytes of records the ring can hold, headers included.
+    pub fn capacity(&self) -> usize {
+        self.capacity as usize
+    }
+
+    fn header(&self) -> *mut Header {
+        self.map.as_ptr().cast()
+    }
+
+    /// Returns the two ranges of the data area holding `len` bytes from the
+    /// free-running `offset`, the second one non-empty when they wrap.
+    fn ranges(&self, offset: u64, len: usize) -> (usize, usize, usize) {
+        let start = (offset % self.capacity) as usize;
+        let first = len.min(self.capacity as usize - start);
+        (mem::size_of::<Header>() + start, first, len - first)
+    }
+
+    fn copy_in(&mut self, offset: u64, bytes: &[u8]) {
+        let (start, first, second) = self.ranges(offset, bytes.len());
+        let data = self.map.as_ptr();
+        // Both ranges are within the data area, owned by this ring.
+        unsafe {
+            data.add(start).copy_from_nonoverlapping(bytes.as_ptr(), first);
+            data.add(mem::size_of::<Header>()).copy_from_nonoverlapping(bytes[first..].as_ptr(), second);
+        }
+    }
+
+    fn copy_out(&self, offset: u64, out: &mut [u8]) {
+        let (start, first, second) = self.ranges(offset, out.len());
+        let data = self.map.as_ptr();
+        // See `copy_in`.
+        unsafe {
+            out.as_mut_ptr().copy_from_nonoverlapping(data.add(start), first);
+            out[first..].as_mut_ptr().copy_from_nonoverlapping(data.add(mem::size_of::<Header>()), second);
+        }
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::FileExt;
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ringbuf-wal-{name}-{}", std::process::id()))
+    }
+
+    #[test]
+    fn commits_survive_reopening() {
+        let path = temp_path("reopen");
+        let mut ring = PersistentRingBuffer::create(&path, 40).unwrap();
+        assert_eq!(ring.append(&[0; 33]), Err(MessageError::TooLong(32)));
+        for record in [&b"one"[..], b"two", b"three"] {
+            ring.append(record).unwrap();
+        }
+        assert_eq!(ring.append(b"four"), Err(MessageError::Full));
+        assert_eq!(ring.pop().unwrap(), b"one");
+        // Not committed: the space of "one" is not reusable yet.
+        assert_eq!(ring.append(b"four"), Err(MessageError::Full));
+        ring.commit().unwrap();
+        // Wraps around the end of the data area.
+        ring.append(b"four").unwrap();
+        ring.commit().unwrap();
+        drop(ring);
+
+        let mut ring = PersistentRingBuffer::open(&path).unwrap();
+        assert_eq!(ring.len(), 3);
+        let records: Vec<_> = core::iter::from_fn(|| ring.pop()).collect();
+        assert_eq!(records, [&b"two"[..], b"three", b"four"]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn recovery_drops_torn_records() {
+        let path = temp_path("torn");
+        let mut ring = PersistentRingBuffer::create(&path, 64).unwrap();
+        ring.append(b"committed").unwrap();
+        ring.commit().unwrap();
+        // Reached the file, as if the process crashed before the commit.
+        ring.append(b"whole").unwrap();
+        ring.append(b"torn").unwrap();
+        drop(ring);
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        let torn = mem::size_of::<Header>() + (8 + 9) + (8 + 5) + 8;
+        file.write_at(b"x", torn as u64).unwrap();
+
+        let mut ring = PersistentRingBuffer::open(&path).unwrap();
+        assert_eq!(ring.len(), 2);
+        assert_eq!(ring.pop().unwrap(), b"committed");
+        assert_eq!(ring.pop().unwrap(), b"whole");
+        // The torn record's space is reused.
+        ring.append(b"again").unwrap();
+        assert_eq!(ring.pop().unwrap(), b"again");
+        assert!(PersistentRingBuffer::open(temp_path("missing")).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
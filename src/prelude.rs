@@ -0,0 +1,47 @@
+//! The ring buffers, their traits and errors, and `WaitStrategy`, for a glob import:
+//! `use ringbuf::prelude::*`.
+
+#[cfg(feature = "std")]
+pub use crate::spsc_array::ArrayRingBuffer;
+pub use crate::error::{ChunkError, Disconnected, PopError, PushError};
+#[cfg(feature = "mpmc")]
+pub use crate::mpmc_lockfree::MPMCRingBuffer;
+#[cfg(feature = "mpsc")]
+pub use crate::mpsc_lockfree::MPSCRingBuffer;
+#[cfg(feature = "alloc")]
+pub use crate::ring::{Ring, RingConsumer, RingProducer};
+#[cfg(feature = "alloc")]
+pub use crate::spsc_lockfree::SPSCRingBuffer;
+pub use crate::wait::WaitStrategy;
+
+#[cfg(all(test, not(loom), feature = "mpsc"))]
+mod tests {
+    use super::*;
+    use crate::sync::Arc;
+
+    #[test]
+    fn distinct_names() {
+        let (mut tx, mut rx) = SPSCRingBuffer::new_split(2);
+        tx.push(1).unwrap();
+        assert_eq!(rx.pop().unwrap(), 1);
+        let mpsc = MPSCRingBuffer::new(2);
+        mpsc.try_push(2).unwrap();
+        assert_eq!(mpsc.pop(), Some(2));
+        #[cfg(feature = "std")]
+        {
+            let mut array = ArrayRingBuffer::with_capacity(4);
+            array.push(3).unwrap();
+            assert_eq!(array.pop(), Ok(3));
+        }
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn old_paths_still_resolve() {
+        #[cfg(feature = "std")]
+        let _: crate::spsc_bounded::SPSCRingBuffer = ArrayRingBuffer::with_capacity(4);
+        let _: crate::spsc_lockfree_bounded::SPSCRingBuffer<u8> = SPSCRingBuffer::new(2);
+        let _: Arc<crate::mpsc_lockfree_bounded::RingBuffer<u8>> = MPSCRingBuffer::new(2);
+        let _: crate::mpmc_lockfree_bounded::RingBuffer<u8> = MPMCRingBuffer::with_capacity(2);
+    }
+}
@@ -0,0 +1,143 @@
+//! Priority lanes: one multi-producer ring per priority, drained highest
+//! priority first by a single consumer. Strict priority can starve the low
+//! lanes under a sustained high-priority load; a `Scheduler` with a
+//! starvation limit serves a waiting lane once it was passed over that many
+//! times in a row.
+
+use alloc::boxed::Box;
+
+use crate::error::PushError;
+use crate::mpmc_lockfree::MPMCRingBuffer;
+use crate::sync::Arc;
+
+pub struct PriorityRingBuffer<T> {
+    /// Lane 0 has the highest priority.
+    lanes: Box<[MPMCRingBuffer<T>]>,
+}
+
+impl<T> PriorityRingBuffer<T> {
+    /// Creates `lanes` lanes (at least one) of `capacity` each, rounded up
+    /// as by `mpmc_lockfree::MPMCRingBuffer::new`.
+    pub fn new(lanes: usize, capacity: usize) -> Arc<Self> {
+        let lanes = (0..lanes.max(1)).map(|_| MPMCRingBuffer::with_capacity(capacity)).collect();
+        Arc::new(PriorityRingBuffer { lanes })
+    }
+
+    /// Returns the number of lanes.
+    pub fn lanes(&self) -> usize {
+        self.lanes.len()
+    }
+
+    /// Pushes a value to the lane of `priority`, 0 being the highest, or
+    /// hands it back in `PushError::Full` if that lane is full. Safe to
+    /// call from any number of threads at once.
+    /// Panics if `priority` is not below `lanes()`.
+    pub fn try_push(&self, priority: usize, item: T) -> Result<(), PushError<T>> {
+        self.lanes[priority].try_push(item)
+    }
+
+    /// Pops the oldest value of the highest-priority lane holding one, with
+    /// its priority. Strict priority, see `Scheduler` for starvation
+    /// protection.
+    pub fn pop(&self) -> Option<(usize, T)> {
+        self.lanes.iter().enumerate().find_map(|(priority, lane)| Some((priority, lane.pop()?)))
+    }
+
+    /// Returns the number of values queued in the lane of `priority`.
+    pub fn len_of(&self, priority: usize) -> usize {
+        self.lanes[priority].len()
+    }
+
+    /// Returns the number of values queued across all the lanes.
+    pub fn len(&self) -> usize {
+        self.lanes.iter().map(MPMCRingBuffer::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lanes.iter().all(MPMCRingBuffer::is_empty)
+    }
+}
+
+/// The consumer side of a `PriorityRingBuffer`, with optional starvation
+/// protection of the low lanes.
+pub struct Scheduler<T> {
+    ring: Arc<PriorityRingBuffer<T>>,
+    /// Pops after which a passed-over lane is served first, none for
+    /// strict priority.
+    starvation_limit: Option<usize>,
+    /// Pops from higher lanes in a row while each lane held a value.
+    skipped: Box<[usize]>,
+}
+
+impl<T> Scheduler<T> {
+    /// Drains the lanes in strict priority order.
+    pub fn new(ring: Arc<PriorityRingBuffer<T>>) -> Self {
+        let skipped = (0..ring.lanes()).map(|_| 0).collect();
+        Scheduler { ring, starvation_limit: None, skipped }
+    }
+
+    /// Serves a lane holding a value once `limit` values of higher lanes
+    /// were popped in a row ahead of it, so that every lane keeps a share
+    /// of at least one value every `limit + 1` pops.
+    pub fn with_starvation_limit(mut self, limit: usize) -> Self {
+        self.starvation_limit = Some(limit);
+        self
+    }
+
+    /// Pops the next value with its priority, `None` if all the lanes are
+    /// empty.
+    pub fn pop(&mut self) -> Option<(usize, T)> {
+        let lanes = &self.ring.lanes;
+        let starved = self.starvation_limit.and_then(|limit| {
+            // The lowest lanes wait the longest behind the others.
+            (0..lanes.len()).rev().filter(|&i| self.skipped[i] >= limit).find_map(|i| Some((i, lanes[i].pop()?)))
+        });
+        let (priority, item) = starved.or_else(|| self.ring.pop())?;
+        self.skipped[priority] = 0;
+        for (lane, skipped) in lanes.iter().zip(self.skipped.iter_mut()).skip(priority + 1) {
+            if lane.is_empty() {
+                *skipped = 0;
+            } else {
+                *skipped += 1;
+            }
+        }
+        Some((priority, item))
+    }
+
+    pub fn ring(&self) -> &Arc<PriorityRingBuffer<T>> {
+        &self.ring
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highest_lane_first() {
+        let ring = PriorityRingBuffer::new(3, 4);
+        assert!(ring.try_push(2, "low").is_ok());
+        assert!(ring.try_push(0, "high").is_ok());
+        assert!(ring.try_push(1, "mid").is_ok());
+        assert_eq!(ring.len_of(1), 1);
+        let mut scheduler = Scheduler::new(ring.clone());
+        assert_eq!(scheduler.pop(), Some((0, "high")));
+        assert_eq!(scheduler.pop(), Some((1, "mid")));
+        assert_eq!(scheduler.pop(), Some((2, "low")));
+        assert_eq!(scheduler.pop(), None);
+        assert!(ring.is_empty());
+    }
+
+    #[test]
+    fn starvation_limit() {
+        let ring = PriorityRingBuffer::new(2, 16);
+        for i in 0..8 {
+            assert!(ring.try_push(0, i).is_ok());
+        }
+        assert!(ring.try_push(1, 100).is_ok());
+        assert!(ring.try_push(1, 101).is_ok());
+        let mut scheduler = Scheduler::new(ring).with_starvation_limit(3);
+        let order: Vec<_> = core::iter::from_fn(|| scheduler.pop()).map(|(_, v)| v).collect();
+        assert_eq!(order, [0, 1, 2, 100, 3, 4, 5, 101, 6, 7]);
+    }
+}
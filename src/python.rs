@@ -0,0 +1,189 @@
+//! Python bindings of the MPSC channel: Python producer threads, or
+//! asyncio tasks, feed a Rust `Receiver` through a `Sender` object.
+//! Payloads are either raw `bytes` (`send_bytes`) or any picklable object
+//! (`send_object`), pickled on the Python side and unpickled on demand by
+//! `Payload::into_object`.
+//! The blocking sends release the GIL while they wait. An asyncio task
+//! must not block its event loop: it uses the `try_send_*` methods, which
+//! return `False` when the channel is full, or runs the blocking ones in
+//! an executor.
+//!
+//! ```python
+//! sender.send_bytes(b"raw frame")
+//! sender.send_object({"event": "click", "x": 12})
+//! ```
+
+use alloc::vec::Vec;
+
+use pyo3::exceptions::PyBrokenPipeError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use crate::error::PushError;
+use crate::mpsc_lockfree::{self, Receiver, Sender};
+
+/// A value sent from Python.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Payload {
+    /// Sent with `send_bytes`.
+    Bytes(Vec<u8>),
+    /// Sent with `send_object`, as pickled by `pickle.dumps`.
+    Pickled(Vec<u8>),
+}
+
+impl Payload {
+    /// Returns the payload as a Python object: `bytes`, or the unpickled
+    /// object.
+    pub fn into_object(self, py: Python<'_>) -> PyResult<Py<PyAny>> {
+        match self {
+            Payload::Bytes(bytes) => Ok(PyBytes::new(py, &bytes).into_any().unbind()),
+            Payload::Pickled(pickled) => {
+                let pickle = py.import("pickle")?;
+                Ok(pickle.call_method1("loads", (PyBytes::new(py, &pickled),))?.unbind())
+            }
+        }
+    }
+}
+
+/// The `Sender` class, shared by any number of Python threads.
+#[pyclass(name = "Sender", module = "ringbuf", frozen)]
+pub struct PySender {
+    tx: Sender<Payload>,
+}
+
+/// Creates a channel of `capacity` (see `mpsc_lockfree::channel`)
+/// and returns the `Sender` to hand to Python, e.g. with `Py::new`.
+pub fn channel(capacity: usize) -> (PySender, Receiver<Payload>) {
+    let (tx, rx) = mpsc_lockfree::channel(capacity);
+    (PySender { tx }, rx)
+}
+
+impl PySender {
+    /// Sends with the GIL released while waiting for a vacant slot.
+    fn send(&self, py: Python<'_>, payload: Payload) -> PyResult<()> {
+        py.detach(|| self.tx.send(payload)).map_err(disconnected)
+    }
+
+    fn try_send(&self, payload: Payload) -> PyResult<bool> {
+        match self.tx.try_push(payload) {
+            Ok(()) => Ok(true),
+            Err(PushError::Full(_)) => Ok(false),
+            Err(e) => Err(disconnected(e)),
+        }
+    }
+}
+
+fn pickle(obj: &Bound<'_, PyAny>) -> PyResult<Payload> {
+    let pickle = obj.py().import("pickle")?;
+    Ok(Payload::Pickled(pickle.call_method1("dumps", (obj,))?.extract()?))
+}
+
+/// Raised once the Rust `Receiver` is dropped.
+fn disconnected<T>(_: PushError<T>) -> PyErr {
+    PyBrokenPipeError::new_err("the receiver was dropped")
+}
+
+#[pymethods]
+impl PySender {
+    /// Sends a copy of `data`, waiting while the channel is full.
+    fn send_bytes(&self, py: Python<'_>, data: Vec<u8>) -> PyResult<()> {
+        self.send(py, Payload::Bytes(data))
+    }
+
+    /// Pickles and sends `obj`, waiting while the channel is full.
+    fn send_object(&self, obj: &Bound<'_, PyAny>) -> PyResult<()> {
+        self.send(obj.py(), pickle(obj)?)
+    }
+
+    /// Sends a copy of `data`, or returns `False` if the channel is full.
+    fn try_send_bytes(&self, data: Vec<u8>) -> PyResult<bool> {
+        self.try_send(Payload::Bytes(data))
+    }
+
+    /// Pickles and sends `obj`, or returns `False` if the channel is full.
+    fn try_send_object(&self, obj: &Bound<'_, PyAny>) -> PyResult<bool> {
+        self.try_send(pickle(obj)?)
+    }
+
+    fn __len__(&self) -> usize {
+        self.tx.len()
+    }
+}
+
+/// The `ringbuf` Python module, for an embedding application to register
+/// with `pyo3::append_to_inittab!` so scripts can `import ringbuf`.
+#[pymodule]
+pub fn ringbuf(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PySender>()
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use pyo3::types::PyDict;
+
+    #[test]
+    fn python_threads_feed_a_rust_receiver() {
+        Python::initialize();
+        let (tx, mut rx) = channel(4);
+        Python::attach(|py| {
+            let globals = PyDict::new(py);
+            globals.set_item("sender", Py::new(py, tx).unwrap()).unwrap();
+            py.run(
+                c"
+import threading
+def produce(i):
+    sender.send_bytes(bytes([i]))
+    sender.send_object({'thread': i})
+threads = [threading.Thread(target=produce, args=(i,)) for i in range(4)]
+for t in threads: t.start()
+",
+                Some(&globals),
+                None,
+            )
+            .unwrap();
+            // The producers block on the full channel with the GIL
+            // released, so this thread can drain it.
+            let mut received = Vec::new();
+            while received.len() < 8 {
+                match py.detach(|| rx.pop()) {
+                    Ok(payload) => received.push(payload),
+                    Err(_) => py.detach(std::thread::yield_now),
+                }
+            }
+            py.run(c"for t in threads: t.join()", Some(&globals), None).unwrap();
+            let mut threads: Vec<i64> = Vec::new();
+            let mut bytes = Vec::new();
+            for payload in received {
+                match payload {
+                    Payload::Bytes(b) => bytes.extend(b),
+                    pickled => {
+                        let obj = pickled.into_object(py).unwrap();
+                        threads.push(obj.bind(py).get_item("thread").unwrap().extract().unwrap());
+                    }
+                }
+            }
+            bytes.sort();
+            threads.sort();
+            assert_eq!(bytes, [0, 1, 2, 3]);
+            assert_eq!(threads, [0, 1, 2, 3]);
+        });
+    }
+
+    #[test]
+    fn try_send_and_disconnection() {
+        Python::initialize();
+        let (tx, rx) = channel(2);
+        Python::attach(|py| {
+            let sender = Py::new(py, tx).unwrap();
+            let locals = PyDict::new(py);
+            locals.set_item("sender", &sender).unwrap();
+            let sent = py.eval(c"[sender.try_send_bytes(b'a'), sender.try_send_object(1), sender.try_send_bytes(b'c')]", None, Some(&locals));
+            assert_eq!(sent.unwrap().extract::<Vec<bool>>().unwrap(), [true, true, false]);
+            assert_eq!(sender.bind(py).len().unwrap(), 2);
+            drop(rx);
+            let err = py.run(c"sender.send_bytes(b'b')", None, Some(&locals)).unwrap_err();
+            assert!(err.is_instance_of::<PyBrokenPipeError>(py));
+        });
+    }
+}
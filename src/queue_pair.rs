@@ -0,0 +1,170 @@
+//! An io_uring-style queue pair: a submission ring carrying requests from a
+//! `Submitter` to a `Completer` (the driver-like side), and a completion
+//! ring carrying the results back, both lock-free SPSC rings. Each request
+//! gets a correlation id, which its completion carries, so completions can
+//! come back in any order.
+//! The submitter keeps at most as many requests in flight as the
+//! completion ring holds, so a completion never finds it full.
+
+use crate::error::{PopError, PushError};
+use crate::spsc_lockfree::{Consumer, Producer, SPSCRingBuffer};
+
+/// A request and its correlation id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Submission<S> {
+    pub id: u64,
+    pub data: S,
+}
+
+/// The result of the request of the same id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Completion<C> {
+    pub id: u64,
+    pub data: C,
+}
+
+/// The two rings of a queue pair, to split into its `Submitter` and
+/// `Completer`.
+pub struct QueuePair<S, C> {
+    submissions: SPSCRingBuffer<Submission<S>>,
+    completions: SPSCRingBuffer<Completion<C>>,
+}
+
+impl<S, C> QueuePair<S, C> {
+    /// Creates both rings with `capacity` rounded up to the next power of
+    /// two, which also bounds the requests in flight.
+    pub fn new(capacity: usize) -> Self {
+        QueuePair { submissions: SPSCRingBuffer::new(capacity), completions: SPSCRingBuffer::new(capacity) }
+    }
+
+    /// Splits the pair into its two sides, which can be moved to different
+    /// threads. Dropping one side disconnects the other.
+    pub fn split(self) -> (Submitter<S, C>, Completer<S, C>) {
+        let (sq_producer, sq_consumer) = self.submissions.split();
+        let (cq_producer, cq_consumer) = self.completions.split();
+        let submitter = Submitter { submissions: sq_producer, completions: cq_consumer, next_id: 0, in_flight: 0 };
+        (submitter, Completer { submissions: sq_consumer, completions: cq_producer })
+    }
+}
+
+/// The side of a `QueuePair` issuing requests and reaping their completions.
+pub struct Submitter<S, C> {
+    submissions: Producer<Submission<S>>,
+    completions: Consumer<Completion<C>>,
+    next_id: u64,
+    /// Requests submitted and not reaped yet.
+    in_flight: usize,
+}
+
+impl<S, C> Submitter<S, C> {
+    /// Submits a request, returns its correlation id. Hands it back in
+    /// `PushError::Full` while the submission ring is full or as many
+    /// requests are in flight as the completion ring holds: reap some
+    /// completions first.
+    pub fn submit(&mut self, data: S) -> Result<u64, PushError<S>> {
+        if self.in_flight == self.completions.capacity() {
+            return Err(PushError::Full(data));
+        }
+        let id = self.next_id;
+        match self.submissions.push(Submission { id, data }) {
+            Ok(_) => {
+                self.next_id = self.next_id.wrapping_add(1);
+                self.in_flight += 1;
+                Ok(id)
+            }
+            Err(PushError::Full(s)) => Err(PushError::Full(s.data)),
+            Err(PushError::Disconnected(s)) => Err(PushError::Disconnected(s.data)),
+        }
+    }
+
+    /// Reaps the oldest completion. Fails with `Disconnected` once the
+    /// completer is dropped and all its completions are reaped.
+    pub fn reap(&mut self) -> Result<Completion<C>, PopError> {
+        let completion = self.completions.pop()?;
+        self.in_flight -= 1;
+        Ok(completion)
+    }
+
+    /// Returns the number of requests submitted and not reaped yet.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight
+    }
+}
+
+/// The driver-like side of a `QueuePair`, serving requests and posting
+/// their completions.
+pub struct Completer<S, C> {
+    submissions: Consumer<Submission<S>>,
+    completions: Producer<Completion<C>>,
+}
+
+impl<S, C> Completer<S, C> {
+    /// Pops the oldest request. Fails with `Disconnected` once the
+    /// submitter is dropped and all its requests are popped.
+    pub fn pop(&mut self) -> Result<Submission<S>, PopError> {
+        self.submissions.pop()
+    }
+
+    /// Posts the completion of the request `id`. Complete each request
+    /// once: the completion ring then always has room. Fails with
+    /// `Disconnected` once the submitter is dropped.
+    pub fn complete(&mut self, id: u64, data: C) -> Result<(), PushError<C>> {
+        match self.completions.push(Completion { id, data }) {
+            Ok(_) => Ok(()),
+            Err(PushError::Full(c)) => Err(PushError::Full(c.data)),
+            Err(PushError::Disconnected(c)) => Err(PushError::Disconnected(c.data)),
+        }
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn completions_out_of_order() {
+        let (mut submitter, mut completer) = QueuePair::<&str, usize>::new(2).split();
+        let a = submitter.submit("read").unwrap();
+        let b = submitter.submit("write").unwrap();
+        // The completion ring holds 2.
+        assert_eq!(submitter.submit("sync"), Err(PushError::Full("sync")));
+        let first = completer.pop().unwrap();
+        let second = completer.pop().unwrap();
+        assert!(completer.complete(second.id, second.data.len()).is_ok());
+        assert!(completer.complete(first.id, first.data.len()).is_ok());
+        assert_eq!(submitter.reap(), Ok(Completion { id: b, data: 5 }));
+        assert_eq!(submitter.in_flight(), 1);
+        assert!(submitter.submit("sync").is_ok());
+        assert_eq!(submitter.reap(), Ok(Completion { id: a, data: 4 }));
+        drop(completer);
+        assert_eq!(submitter.reap(), Err(PopError::Disconnected));
+    }
+
+    #[test]
+    fn driver_thread() {
+        let (mut submitter, mut completer) = QueuePair::<u64, u64>::new(8).split();
+        let driver = thread::spawn(move || loop {
+            match completer.pop() {
+                Ok(request) => assert!(completer.complete(request.id, request.data * 2).is_ok()),
+                Err(PopError::Empty) => thread::yield_now(),
+                Err(PopError::Disconnected) => break,
+            }
+        });
+        let (mut submitted, mut reaped) = (0, 0);
+        while reaped < 1000 {
+            if submitted < 1000 && submitter.submit(submitted).is_ok() {
+                submitted += 1;
+            }
+            match submitter.reap() {
+                Ok(completion) => {
+                    assert_eq!(completion.data, completion.id * 2);
+                    reaped += 1;
+                }
+                Err(_) => thread::yield_now(),
+            }
+        }
+        drop(submitter);
+        driver.join().unwrap();
+    }
+}
@@ -0,0 +1,318 @@
+//! Traits over the ring variants, so that generic code and tests can swap
+//! the array SPSC, the lock-free SPSC and the MPSC rings:
+//!
+//! ```ignore
+//! fn drain<C: RingConsumer<Order>>(rx: &mut C) -> Vec<Order> {
+//!     core::iter::from_fn(|| rx.try_pop().ok()).collect()
+//! }
+//! ```
+//!
+//! The single-threaded `ArrayRingBuffer` is both its producer and its
+//! consumer; the others implement the traits on their halves.
+//!
+//! The traits are object-safe and also implemented by `&mut R` and
+//! `Box<R>`, so other crates can wrap a handle in a decorator (counting,
+//! encrypting, ...) that implements them by forwarding, and hand a
+//! `Box<dyn RingProducer<T> + Send>` to code generic over the traits. A
+//! boxed `dyn RingConsumer<T>` is also `Selectable`.
+
+use alloc::boxed::Box;
+
+#[cfg(feature = "std")]
+use crate::spsc_array::ArrayRingBuffer;
+use crate::error::{PopError, PushError};
+#[cfg(feature = "mpsc")]
+use crate::mpsc_lockfree;
+use crate::spsc_lockfree;
+use crate::wait::WaitStrategy;
+
+/// What both sides of a ring can tell about it.
+pub trait Ring {
+    /// Returns the most values the ring holds at once.
+    fn capacity(&self) -> usize;
+
+    /// Returns the number of values queued, which the other side may
+    /// change at any time.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// True once the other side is gone. Rings with a single owner are
+    /// never disconnected.
+    fn is_disconnected(&self) -> bool {
+        false
+    }
+}
+
+/// The pushing side of a ring.
+pub trait RingProducer<T>: Ring {
+    /// Pushes a value without waiting, or hands it back in `PushError`.
+    fn try_push(&mut self, value: T) -> Result<(), PushError<T>>;
+}
+
+/// The popping side of a ring.
+pub trait RingConsumer<T>: Ring {
+    /// Pops the oldest value without waiting.
+    fn try_pop(&mut self) -> Result<T, PopError>;
+}
+
+impl<R: Ring + ?Sized> Ring for &mut R {
+    fn capacity(&self) -> usize {
+        (**self).capacity()
+    }
+
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn is_empty(&self) -> bool {
+        (**self).is_empty()
+    }
+
+    fn is_disconnected(&self) -> bool {
+        (**self).is_disconnected()
+    }
+}
+
+impl<T, P: RingProducer<T> + ?Sized> RingProducer<T> for &mut P {
+    fn try_push(&mut self, value: T) -> Result<(), PushError<T>> {
+        (**self).try_push(value)
+    }
+}
+
+impl<T, C: RingConsumer<T> + ?Sized> RingConsumer<T> for &mut C {
+    fn try_pop(&mut self) -> Result<T, PopError> {
+        (**self).try_pop()
+    }
+}
+
+impl<R: Ring + ?Sized> Ring for Box<R> {
+    fn capacity(&self) -> usize {
+        (**self).capacity()
+    }
+
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn is_empty(&self) -> bool {
+        (**self).is_empty()
+    }
+
+    fn is_disconnected(&self) -> bool {
+        (**self).is_disconnected()
+    }
+}
+
+impl<T, P: RingProducer<T> + ?Sized> RingProducer<T> for Box<P> {
+    fn try_push(&mut self, value: T) -> Result<(), PushError<T>> {
+        (**self).try_push(value)
+    }
+}
+
+impl<T, C: RingConsumer<T> + ?Sized> RingConsumer<T> for Box<C> {
+    fn try_pop(&mut self) -> Result<T, PopError> {
+        (**self).try_pop()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Ring for ArrayRingBuffer {
+    fn capacity(&self) -> usize {
+        ArrayRingBuffer::capacity(self)
+    }
+
+    fn len(&self) -> usize {
+        self.size()
+    }
+}
+
+#[cfg(feature = "std")]
+impl RingProducer<u64> for ArrayRingBuffer {
+    fn try_push(&mut self, value: u64) -> Result<(), PushError<u64>> {
+        self.push(value)
+    }
+}
+
+#[cfg(feature = "std")]
+impl RingConsumer<u64> for ArrayRingBuffer {
+    fn try_pop(&mut self) -> Result<u64, PopError> {
+        self.pop()
+    }
+}
+
+impl<T, W: WaitStrategy> Ring for spsc_lockfree::Producer<T, W> {
+    fn capacity(&self) -> usize {
+        spsc_lockfree::Producer::capacity(self)
+    }
+
+    fn len(&self) -> usize {
+        spsc_lockfree::Producer::len(self)
+    }
+
+    fn is_disconnected(&self) -> bool {
+        spsc_lockfree::Producer::is_disconnected(self)
+    }
+}
+
+impl<T, W: WaitStrategy> RingProducer<T> for spsc_lockfree::Producer<T, W> {
+    fn try_push(&mut self, value: T) -> Result<(), PushError<T>> {
+        self.push(value).map(drop)
+    }
+}
+
+impl<T, W: WaitStrategy> Ring for spsc_lockfree::Consumer<T, W> {
+    fn capacity(&self) -> usize {
+        spsc_lockfree::Consumer::capacity(self)
+    }
+
+    fn len(&self) -> usize {
+        spsc_lockfree::Consumer::len(self)
+    }
+
+    fn is_disconnected(&self) -> bool {
+        spsc_lockfree::Consumer::is_disconnected(self)
+    }
+}
+
+impl<T, W: WaitStrategy> RingConsumer<T> for spsc_lockfree::Consumer<T, W> {
+    fn try_pop(&mut self) -> Result<T, PopError> {
+        self.pop()
+    }
+}
+
+#[cfg(feature = "mpsc")]
+impl<T, W: WaitStrategy> Ring for mpsc_lockfree::Sender<T, W> {
+    fn capacity(&self) -> usize {
+        mpsc_lockfree::Sender::capacity(self)
+    }
+
+    fn len(&self) -> usize {
+        mpsc_lockfree::Sender::len(self)
+    }
+
+    fn is_disconnected(&self) -> bool {
+        mpsc_lockfree::Sender::is_disconnected(self)
+    }
+}
+
+#[cfg(feature = "mpsc")]
+impl<T, W: WaitStrategy> RingProducer<T> for mpsc_lockfree::Sender<T, W> {
+    fn try_push(&mut self, value: T) -> Result<(), PushError<T>> {
+        mpsc_lockfree::Sender::try_push(self, value)
+    }
+}
+
+#[cfg(feature = "mpsc")]
+impl<T, W: WaitStrategy> Ring for mpsc_lockfree::Receiver<T, W> {
+    fn capacity(&self) -> usize {
+        mpsc_lockfree::Receiver::capacity(self)
+    }
+
+    fn len(&self) -> usize {
+        mpsc_lockfree::Receiver::len(self)
+    }
+
+    fn is_disconnected(&self) -> bool {
+        mpsc_lockfree::Receiver::is_disconnected(self)
+    }
+}
+
+#[cfg(feature = "mpsc")]
+impl<T, W: WaitStrategy> RingConsumer<T> for mpsc_lockfree::Receiver<T, W> {
+    fn try_pop(&mut self) -> Result<T, PopError> {
+        self.pop()
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use crate::spsc_lockfree::SPSCRingBuffer;
+
+    /// Fills the ring, then drains it, through the traits only.
+    fn fill_and_drain(tx: &mut impl RingProducer<u64>, rx: &mut impl RingConsumer<u64>) {
+        let capacity = tx.capacity();
+        for i in 0..capacity as u64 {
+            tx.try_push(i).unwrap();
+        }
+        assert!(matches!(tx.try_push(99), Err(PushError::Full(99))));
+        assert_eq!(rx.len(), capacity);
+        let drained: Vec<_> = core::iter::from_fn(|| rx.try_pop().ok()).collect();
+        assert_eq!(drained, (0..capacity as u64).collect::<Vec<_>>());
+        assert!(rx.is_empty());
+    }
+
+    #[test]
+    fn spsc_halves() {
+        let (mut tx, mut rx) = SPSCRingBuffer::new_split(4);
+        fill_and_drain(&mut tx, &mut rx);
+    }
+
+    #[cfg(feature = "mpsc")]
+    #[test]
+    fn mpsc_halves() {
+        let (mut tx, mut rx) = mpsc_lockfree::channel(4);
+        fill_and_drain(&mut tx, &mut rx);
+        drop(tx);
+        assert_eq!(RingConsumer::try_pop(&mut rx), Err(PopError::Disconnected));
+    }
+
+    /// A decorator as another crate would write it: counts the pushes and
+    /// forwards to any producer.
+    struct Counting<P> {
+        inner: P,
+        pushed: usize,
+    }
+
+    impl<P: Ring> Ring for Counting<P> {
+        fn capacity(&self) -> usize {
+            self.inner.capacity()
+        }
+
+        fn len(&self) -> usize {
+            self.inner.len()
+        }
+
+        fn is_disconnected(&self) -> bool {
+            self.inner.is_disconnected()
+        }
+    }
+
+    impl<T, P: RingProducer<T>> RingProducer<T> for Counting<P> {
+        fn try_push(&mut self, value: T) -> Result<(), PushError<T>> {
+            self.inner.try_push(value)?;
+            self.pushed += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn boxed_decorators() {
+        let (tx, mut rx) = SPSCRingBuffer::new_split(4);
+        let mut tx: Box<dyn RingProducer<u64> + Send> = Box::new(Counting { inner: tx, pushed: 0 });
+        fill_and_drain(&mut tx, &mut rx);
+        drop(rx);
+        assert!(tx.is_disconnected());
+
+        let (tx, mut rx) = SPSCRingBuffer::new_split(2);
+        let mut counting = Counting { inner: tx, pushed: 0 };
+        fill_and_drain(&mut counting, &mut rx);
+        assert_eq!(counting.pushed, 2);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn array_is_both_sides() {
+        let mut ring = ArrayRingBuffer::with_capacity(3);
+        assert_eq!(Ring::capacity(&ring), 3);
+        for i in 0..3 {
+            ring.try_push(i).unwrap();
+        }
+        assert_eq!(ring.try_push(3), Err(PushError::Full(3)));
+        assert_eq!(ring.try_pop(), Ok(0));
+        assert_eq!(Ring::len(&ring), 2);
+    }
+}
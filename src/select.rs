@@ -0,0 +1,177 @@
+//! Waiting on several rings at once, so a dispatcher thread can serve many
+//! queues:
+//!
+//! ```ignore
+//! let mut select = Select::new();
+//! loop {
+//!     match select.select(&[&orders, &cancels]) {
+//!         0 => handle(orders.pop()),
+//!         _ => handle(cancels.pop()),
+//!     }
+//! }
+//! ```
+//!
+//! The sources are only borrowed for the duration of each call, so the
+//! ready one can then be popped from. Readiness is a hint: a pop may still
+//! find the ring empty, e.g. while an MPSC push is in progress.
+
+#[cfg(feature = "std")]
+use core::time::Duration;
+
+use alloc::boxed::Box;
+
+#[cfg(feature = "mpsc")]
+use crate::mpsc_lockfree;
+use crate::ring::RingConsumer;
+use crate::spsc_lockfree;
+use crate::wait::{self, Instant, SpinThenYield, WaitStrategy};
+
+/// A receiving half that `Select` can wait on.
+pub trait Selectable {
+    /// True if a pop would not find the ring empty: it holds a value, or
+    /// the sending side is gone.
+    fn is_ready(&self) -> bool;
+}
+
+impl<T, W: WaitStrategy> Selectable for spsc_lockfree::Consumer<T, W> {
+    fn is_ready(&self) -> bool {
+        !self.is_empty() || self.is_disconnected()
+    }
+}
+
+#[cfg(feature = "mpsc")]
+impl<T, W: WaitStrategy> Selectable for mpsc_lockfree::Receiver<T, W> {
+    fn is_ready(&self) -> bool {
+        !self.is_empty() || self.is_disconnected()
+    }
+}
+
+/// A boxed consumer, e.g. a decorator from another crate.
+impl<T> Selectable for Box<dyn RingConsumer<T> + '_> {
+    fn is_ready(&self) -> bool {
+        !self.is_empty() || self.is_disconnected()
+    }
+}
+
+impl<T> Selectable for Box<dyn RingConsumer<T> + Send + '_> {
+    fn is_ready(&self) -> bool {
+        !self.is_empty() || self.is_disconnected()
+    }
+}
+
+/// Waits for one of several `Selectable` sources to become ready.
+/// `W` is the strategy used between polls of the sources.
+pub struct Select<W = SpinThenYield> {
+    wait: W,
+    /// Index the next scan starts from, so that a busy source cannot
+    /// starve the ones after it.
+    next: usize,
+}
+
+impl Select {
+    pub fn new() -> Self {
+        Self::with_wait(SpinThenYield::default())
+    }
+}
+
+impl Default for Select {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<W: WaitStrategy> Select<W> {
+    pub fn with_wait(wait: W) -> Self {
+        Select { wait, next: 0 }
+    }
+
+    /// Returns the index in `sources` of a ready source, if any. Sources
+    /// are scanned round-robin across calls.
+    pub fn try_select(&mut self, sources: &[&dyn Selectable]) -> Option<usize> {
+        let n = sources.len();
+        let ready = (0..n).map(|i| (self.next + i) % n).find(|&i| sources[i].is_ready())?;
+        self.next = ready + 1;
+        Some(ready)
+    }
+
+    /// Waits until one of `sources` is ready and returns its index.
+    /// # Panics
+    /// If `sources` is empty.
+    pub fn select(&mut self, sources: &[&dyn Selectable]) -> usize {
+        assert!(!sources.is_empty(), "cannot select over no source");
+        self.select_until(sources, None).unwrap()
+    }
+
+    /// Same as `select`, returns `None` if no source is ready after `timeout`.
+    #[cfg(feature = "std")]
+    pub fn select_timeout(&mut self, sources: &[&dyn Selectable], timeout: Duration) -> Option<usize> {
+        self.select_until(sources, Some(Instant::now() + timeout))
+    }
+
+    fn select_until(&mut self, sources: &[&dyn Selectable], deadline: Option<Instant>) -> Option<usize> {
+        let mut step = 0;
+        loop {
+            if let Some(ready) = self.try_select(sources) {
+                return Some(ready);
+            }
+            if wait::expired(deadline) {
+                return None;
+            }
+            self.wait.wait(step, deadline);
+            step = step.saturating_add(1);
+        }
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use crate::spsc_lockfree::SPSCRingBuffer;
+
+    #[cfg(feature = "mpsc")]
+    #[test]
+    fn select_spsc_and_mpsc() {
+        let (mut spsc_tx, mut spsc_rx) = SPSCRingBuffer::new_split(4);
+        let (mpsc_tx, mut mpsc_rx) = mpsc_lockfree::channel(4);
+        let mut select = Select::new();
+        assert_eq!(select.try_select(&[&spsc_rx, &mpsc_rx]), None);
+
+        let producer = std::thread::spawn(move || {
+            assert!(mpsc_tx.send(1).is_ok());
+            assert!(spsc_tx.push(2).is_ok());
+        });
+        let mut values = Vec::new();
+        while values.len() < 2 {
+            match select.select(&[&spsc_rx, &mpsc_rx]) {
+                0 => values.extend(spsc_rx.pop().ok()),
+                _ => values.extend(mpsc_rx.pop().ok()),
+            }
+        }
+        producer.join().unwrap();
+        values.sort();
+        assert_eq!(values, [1, 2]);
+        // Both senders are gone: ready, in turn.
+        let mut select = Select::new();
+        assert_eq!(select.try_select(&[&spsc_rx, &mpsc_rx]), Some(0));
+        assert_eq!(select.try_select(&[&spsc_rx, &mpsc_rx]), Some(1));
+    }
+
+    #[test]
+    fn select_a_boxed_consumer() {
+        let (mut tx, rx) = SPSCRingBuffer::new_split(4);
+        let mut rx: Box<dyn RingConsumer<u8> + Send> = Box::new(rx);
+        let mut select = Select::new();
+        assert_eq!(select.try_select(&[&rx]), None);
+        tx.push(1).unwrap();
+        assert_eq!(select.try_select(&[&rx]), Some(0));
+        assert_eq!(rx.try_pop(), Ok(1));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn select_timeout() {
+        let (_tx, rx) = SPSCRingBuffer::<u8>::new_split(4);
+        let mut select = Select::new();
+        assert_eq!(select.select_timeout(&[&rx], Duration::from_millis(5)), None);
+    }
+}
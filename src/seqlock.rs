@@ -0,0 +1,153 @@
+//! A latest-value cell guarded by a sequence lock, the usual companion of
+//! the SPSC rings for state that only the newest value of matters (top of
+//! book, configuration). A writer never waits for the readers: readers copy
+//! the value optimistically and retry if a write overlapped their copy.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::mem::MaybeUninit;
+use core::ptr;
+
+use crate::sync::{fence, spin_loop, AtomicUsize, Ordering};
+
+/// Holds a `Copy` value that one writer publishes and any number of readers
+/// load without blocking it. Concurrent writers are serialized, but the
+/// cell is meant for a single one: each write makes the readers retry.
+pub struct SeqLockCell<T> {
+    /// Odd while a write is in progress, incremented by 2 by each write.
+    sequence: AtomicUsize,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: Copy + Send> Sync for SeqLockCell<T> {}
+
+impl<T: Copy> SeqLockCell<T> {
+    pub fn new(value: T) -> Self {
+        SeqLockCell { sequence: AtomicUsize::new(0), value: UnsafeCell::new(value) }
+    }
+
+    /// Returns a copy of the value, retrying while writes overlap it.
+    pub fn load(&self) -> T {
+        loop {
+            if let Some(value) = self.try_load() {
+                return value;
+            }
+            spin_loop();
+        }
+    }
+
+    /// Returns a copy of the value, or `None` if a write overlapped it.
+    pub fn try_load(&self) -> Option<T> {
+        let before = self.sequence.load(Ordering::Acquire);
+        if before & 1 == 1 {
+            return None;
+        }
+        // May race with a write: the copy is only used once the sequence
+        // shows no write overlapped it, as it may be torn otherwise.
+        let value = unsafe { ptr::read_volatile(self.value.get() as *const MaybeUninit<T>) };
+        fence(Ordering::Acquire);
+        if self.sequence.load(Ordering::Relaxed) != before {
+            return None;
+        }
+        Some(unsafe { value.assume_init() })
+    }
+
+    /// Publishes `value`, without waiting for the readers.
+    pub fn store(&self, value: T) {
+        let mut sequence = self.sequence.load(Ordering::Relaxed);
+        loop {
+            // Another writer is in progress.
+            if sequence & 1 == 1 {
+                spin_loop();
+                sequence = self.sequence.load(Ordering::Relaxed);
+                continue;
+            }
+            match self.sequence.compare_exchange_weak(sequence, sequence.wrapping_add(1), Ordering::Acquire, Ordering::Relaxed) {
+                Ok(_) => break,
+                Err(current) => sequence = current,
+            }
+        }
+        // Readers that see the value being written also see the odd sequence.
+        fence(Ordering::Release);
+        unsafe { ptr::write_volatile(self.value.get(), value) };
+        self.sequence.store(sequence.wrapping_add(2), Ordering::Release);
+    }
+
+    /// Returns the value, no reader can be left.
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: Copy + Default> Default for SeqLockCell<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: Copy + fmt::Debug> fmt::Debug for SeqLockCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("SeqLockCell").field(&self.load()).finish()
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri reports the optimistic copy of a seqlock as a data race")]
+    fn readers_never_see_torn_values() {
+        let cell = Arc::new(SeqLockCell::new([0u64; 8]));
+        let done = Arc::new(AtomicBool::new(false));
+        let readers: Vec<_> = (0..2)
+            .map(|_| {
+                let (cell, done) = (cell.clone(), done.clone());
+                thread::spawn(move || {
+                    let mut last = 0;
+                    while !done.load(Ordering::Relaxed) {
+                        let value = cell.load();
+                        assert!(value.iter().all(|&v| v == value[0]), "torn {value:?}");
+                        assert!(value[0] >= last);
+                        last = value[0];
+                        thread::yield_now();
+                    }
+                })
+            })
+            .collect();
+        for i in 1..=10_000 {
+            cell.store([i; 8]);
+        }
+        done.store(true, Ordering::Relaxed);
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        assert_eq!(Arc::try_unwrap(cell).unwrap().into_inner(), [10_000; 8]);
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+    use loom::sync::Arc;
+    use loom::thread;
+
+    #[test]
+    fn load_sees_a_whole_write() {
+        loom::model(|| {
+            let cell = Arc::new(SeqLockCell::new((0, 0)));
+            let writer = {
+                let cell = cell.clone();
+                thread::spawn(move || cell.store((1, 1)))
+            };
+            if let Some((a, b)) = cell.try_load() {
+                assert_eq!(a, b);
+            }
+            writer.join().unwrap();
+            assert_eq!(cell.load(), (1, 1));
+        });
+    }
+}
@@ -0,0 +1,185 @@
+//! A multi-producer multi-consumer queue split into several MPMC rings, for
+//! workloads where many producer threads (32 and more) collapse a single
+//! CAS-based queue on its `write` counter. Each producer pushes to its own
+//! shard, chosen by hashing a key (by default, the calling thread), and
+//! only spills over to the other shards when its own is full; a consumer
+//! pops from its own shard first and steals from the others when it is
+//! empty. Values of one shard stay in FIFO order, there is no order across
+//! shards.
+
+use alloc::boxed::Box;
+
+use crate::error::PushError;
+use crate::mpmc_lockfree::MPMCRingBuffer;
+use crate::sync::Arc;
+
+pub struct ShardedRingBuffer<T> {
+    shards: Box<[MPMCRingBuffer<T>]>,
+}
+
+impl<T> ShardedRingBuffer<T> {
+    /// Creates `shards` rings (at least one) of `capacity` each, rounded up
+    /// as by `mpmc_lockfree::MPMCRingBuffer::new`.
+    pub fn new(shards: usize, capacity: usize) -> Arc<Self> {
+        let shards = (0..shards.max(1)).map(|_| MPMCRingBuffer::with_capacity(capacity)).collect();
+        Arc::new(ShardedRingBuffer { shards })
+    }
+
+    /// Returns the number of shards.
+    pub fn shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Returns the maximum number of values across all the shards.
+    pub fn capacity(&self) -> usize {
+        self.shards.iter().map(MPMCRingBuffer::capacity).sum()
+    }
+
+    /// Returns the number of values queued across all the shards. A
+    /// snapshot: the shards are not read at the same instant.
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(MPMCRingBuffer::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(MPMCRingBuffer::is_empty)
+    }
+
+    /// Pushes a value to the shard of the calling thread.
+    #[cfg(feature = "std")]
+    pub fn try_push(&self, item: T) -> Result<(), PushError<T>> {
+        self.try_push_with(home(), item)
+    }
+
+    /// Pushes a value to shard `key % shards`, or to the next shard with a
+    /// vacant slot if that one is full. Hands the value back in
+    /// `PushError::Full` only if every shard is full.
+    pub fn try_push_with(&self, key: usize, item: T) -> Result<(), PushError<T>> {
+        let mut item = item;
+        for shard in self.from(key) {
+            match shard.try_push(item) {
+                Err(PushError::Full(rejected)) => item = rejected,
+                result => return result,
+            }
+        }
+        Err(PushError::Full(item))
+    }
+
+    /// Pops a value from the shard of the calling thread, or steals one
+    /// from the others if it is empty.
+    #[cfg(feature = "std")]
+    pub fn pop(&self) -> Option<T> {
+        self.pop_with(home())
+    }
+
+    /// Pops the oldest value of shard `key % shards`, or of the next
+    /// non-empty shard. `None` if every shard is empty.
+    pub fn pop_with(&self, key: usize) -> Option<T> {
+        self.from(key).find_map(MPMCRingBuffer::pop)
+    }
+
+    /// The shards, starting from the one of `key`.
+    fn from(&self, key: usize) -> impl Iterator<Item = &MPMCRingBuffer<T>> {
+        let start = key % self.shards.len();
+        self.shards[start..].iter().chain(&self.shards[..start])
+    }
+}
+
+/// Key of the calling thread: threads are numbered in the order they first
+/// use a sharded ring, so consecutive threads land on different shards.
+#[cfg(feature = "std")]
+fn home() -> usize {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    std::thread_local! {
+        static HOME: usize = NEXT.fetch_add(1, Ordering::Relaxed);
+    }
+    HOME.with(|home| *home)
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spills_over_and_steals() {
+        let ring = ShardedRingBuffer::new(3, 2);
+        assert_eq!((ring.shards(), ring.capacity()), (3, 6));
+        for i in 0..6 {
+            assert!(ring.try_push_with(1, i).is_ok());
+        }
+        assert_eq!(ring.try_push_with(1, 6), Err(PushError::Full(6)));
+        assert_eq!(ring.len(), 6);
+        // Shard 1 holds 0 and 1, shard 2 holds 2 and 3, shard 0 holds 4 and 5.
+        assert_eq!(ring.pop_with(2), Some(2));
+        assert_eq!(ring.pop_with(2), Some(3));
+        assert_eq!(ring.pop_with(2), Some(4));
+        assert_eq!(ring.pop_with(4), Some(0));
+        assert_eq!(ring.pop_with(0), Some(5));
+        assert_eq!(ring.pop_with(0), Some(1));
+        assert_eq!(ring.pop_with(0), None);
+        assert!(ring.is_empty());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn many_producers() {
+        use std::thread;
+        // Miri runs the threads a few thousand times slower.
+        const N: usize = if cfg!(miri) { 25 } else { 500 };
+
+        let ring = ShardedRingBuffer::new(4, 16);
+        let producers: Vec<_> = (0..8)
+            .map(|p| {
+                let ring = ring.clone();
+                thread::spawn(move || {
+                    for i in 0..N {
+                        let mut value = p * N + i;
+                        while let Err(e) = ring.try_push(value) {
+                            value = e.into_inner();
+                            thread::yield_now();
+                        }
+                    }
+                })
+            })
+            .collect();
+        let mut received = Vec::new();
+        while received.len() < 8 * N {
+            match ring.pop() {
+                Some(value) => received.push(value),
+                None => thread::yield_now(),
+            }
+        }
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        received.sort();
+        assert_eq!(received, (0..8 * N).collect::<Vec<_>>());
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+    use loom::thread;
+
+    #[test]
+    fn steal_from_a_busy_shard() {
+        loom::model(|| {
+            let ring = ShardedRingBuffer::new(2, 2);
+            let producer = {
+                let ring = ring.clone();
+                thread::spawn(move || ring.try_push_with(0, 1).unwrap())
+            };
+            ring.try_push_with(1, 2).unwrap();
+            let first = ring.pop_with(1).unwrap();
+            producer.join().unwrap();
+            let second = ring.pop_with(1).unwrap();
+            let mut popped = [first, second];
+            popped.sort();
+            assert_eq!(popped, [1, 2]);
+            assert_eq!(ring.pop_with(0), None);
+        });
+    }
+}
@@ -0,0 +1,349 @@
+//! SPSC rings laid out in memory provided by the embedder rather than
+//! allocated by the ring, e.g. between a WebAssembly main thread and a web
+//! worker sharing a `SharedArrayBuffer`-backed memory: one side `init`s
+//! the ring at an address of that memory, the other `attach`es to it, and
+//! each takes the half for its role.
+//! Pushing and popping never block, park or read the clock, which a
+//! browser's main thread must not do; the `no_std` build is enough.
+//!
+//! The layout is stable, so that a C or C++ process can implement either
+//! half against the same shared-memory region, with `T` a `#[repr(C)]`
+//! record and native byte order:
+//!
+//! ```c
+//! struct ringbuf_shared {        /* 64-byte aligned */
+//!     _Atomic uint32_t magic;    /* "RNG1" as a little-endian u32, set last */
+//!     uint32_t capacity;         /* records, a power of two */
+//!     uint32_t record_size;
+//!     uint8_t pad0[52];
+//!     _Atomic uint32_t write;    /* offset 64, free-running */
+//!     uint8_t pad1[60];
+//!     _Atomic uint32_t read;     /* offset 128, free-running */
+//!     uint8_t pad2[60];
+//!     /* slots at offset 192 */
+//! };
+//!
+//! bool ringbuf_shared_push(struct ringbuf_shared *r, const void *record) {
+//!     uint32_t w = atomic_load_explicit(&r->write, memory_order_relaxed);
+//!     if (w - atomic_load_explicit(&r->read, memory_order_acquire) == r->capacity)
+//!         return false;
+//!     memcpy((char *)r + 192 + (w & (r->capacity - 1)) * r->record_size, record, r->record_size);
+//!     atomic_store_explicit(&r->write, w + 1, memory_order_release);
+//!     return true;
+//! }
+//! ```
+//!
+//! A consumer mirrors it: acquire `write`, copy the record at `read`,
+//! release `read + 1`.
+
+use core::marker::PhantomData;
+use core::mem;
+use core::ptr::{self, NonNull};
+// Laid out in the shared memory, so never loom's atomics.
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::error::{PushError, SharedMemoryError};
+
+/// Identifies an initialized ring, and the version of its layout.
+const MAGIC: u32 = u32::from_le_bytes(*b"RNG1");
+
+/// Start of the memory, see the layout in the module documentation. Each
+/// index has a cache line of its own.
+#[repr(C, align(64))]
+struct Header {
+    /// Set to `MAGIC` once the other fields are.
+    magic: AtomicU32,
+    capacity: u32,
+    record_size: u32,
+    _pad0: [u8; 52],
+    write: AtomicU32,
+    _pad1: [u8; 60],
+    read: AtomicU32,
+    _pad2: [u8; 60],
+}
+
+/// Offset of the `write` index in the memory.
+pub const WRITE_OFFSET: usize = 64;
+/// Offset of the `read` index in the memory.
+pub const READ_OFFSET: usize = 128;
+/// Offset of the first slot in the memory.
+pub const SLOTS_OFFSET: usize = 192;
+
+const _: () = {
+    assert!(mem::offset_of!(Header, write) == WRITE_OFFSET);
+    assert!(mem::offset_of!(Header, read) == READ_OFFSET);
+    assert!(mem::size_of::<Header>() == SLOTS_OFFSET);
+};
+
+/// A view of a ring of `T` records in shared memory, to turn into the
+/// `SharedProducer` or the `SharedConsumer` of this side.
+pub struct SharedRing<T> {
+    memory: NonNull<u8>,
+    mask: u32,
+    _records: PhantomData<T>,
+}
+
+// Only `Copy` values cross the shared memory.
+unsafe impl<T: Send> Send for SharedRing<T> {}
+
+impl<T: Copy> SharedRing<T> {
+    /// Returns the bytes of memory a ring of `capacity` records needs,
+    /// `capacity` being rounded up to the next power of two.
+    pub fn required_len(capacity: usize) -> usize {
+        mem::size_of::<Header>() + capacity.max(1).next_power_of_two() * mem::size_of::<T>()
+    }
+
+    /// Lays out an empty ring of `capacity` records, rounded up to the next
+    /// power of two, in the `len` bytes at `memory`.
+    /// # Safety
+    /// The memory must be valid for reads and writes for as long as the
+    /// ring and its views on the other side live, and not be used for
+    /// anything else meanwhile.
+    pub unsafe fn init(memory: NonNull<u8>, len: usize, capacity: usize) -> Result<Self, SharedMemoryError> {
+        assert!(mem::align_of::<T>() <= mem::align_of::<Header>(), "records must not need more than 64-byte alignment");
+        assert!(capacity <= 1 << 31, "capacity must be at most 2^31");
+        let capacity = capacity.max(1).next_power_of_two();
+        check_memory(memory, len, Self::required_len(capacity))?;
+        // Not shared with the other side yet, as `magic` is not set.
+        unsafe {
+            memory.as_ptr().cast::<Header>().write(Header {
+                magic: AtomicU32::new(0),
+                capacity: capacity as u32,
+                record_size: mem::size_of::<T>() as u32,
+                _pad0: [0; 52],
+                write: AtomicU32::new(0),
+                _pad1: [0; 60],
+                read: AtomicU32::new(0),
+                _pad2: [0; 60],
+            });
+        }
+        let ring = SharedRing { memory, mask: capacity as u32 - 1, _records: PhantomData };
+        ring.header().magic.store(MAGIC, Ordering::Release);
+        Ok(ring)
+    }
+
+    /// Attaches to the ring `init`ialized in the `len` bytes at `memory`,
+    /// possibly by another thread or module instance.
+    /// # Safety
+    /// Same as `init`.
+    pub unsafe fn attach(memory: NonNull<u8>, len: usize) -> Result<Self, SharedMemoryError> {
+        check_memory(memory, len, mem::size_of::<Header>())?;
+        let header = unsafe { &*memory.as_ptr().cast::<Header>() };
+        if header.magic.load(Ordering::Acquire) != MAGIC {
+            return Err(SharedMemoryError::NotInitialized);
+        }
+        if header.record_size != mem::size_of::<T>() as u32 {
+            return Err(SharedMemoryError::RecordSize);
+        }
+        // Written by the other side: a zero would wrap the mask, and
+        // `required_len` would round any other capacity up.
+        let capacity = header.capacity as usize;
+        if !capacity.is_power_of_two() {
+            return Err(SharedMemoryError::Capacity);
+        }
+        check_memory(memory, len, Self::required_len(capacity))?;
+        Ok(SharedRing { memory, mask: header.capacity - 1, _records: PhantomData })
+    }
+
+    /// Takes the pushing half. Only one side may push to a ring.
+    pub fn into_producer(self) -> SharedProducer<T> {
+        SharedProducer { ring: self }
+    }
+
+    /// Takes the popping half. Only one side may pop from a ring.
+    pub fn into_consumer(self) -> SharedConsumer<T> {
+        SharedConsumer { ring: self }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.mask as usize + 1
+    }
+
+    /// Returns the number of records queued, which may be stale by the time
+    /// it returns.
+    pub fn len(&self) -> usize {
+        let read = self.header().read.load(Ordering::Acquire);
+        let write = self.header().write.load(Ordering::Acquire);
+        write.wrapping_sub(read) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn header(&self) -> &Header {
+        // Checked by `init`/`attach`, and only accessed through atomics
+        // from then on.
+        unsafe { &*self.memory.as_ptr().cast::<Header>() }
+    }
+
+    /// Returns the slot of the index `i`.
+    fn slot(&self, i: u32) -> *mut T {
+        let offset = mem::size_of::<Header>() + (i & self.mask) as usize * mem::size_of::<T>();
+        unsafe { self.memory.as_ptr().add(offset).cast() }
+    }
+}
+
+fn check_memory(memory: NonNull<u8>, len: usize, required: usize) -> Result<(), SharedMemoryError> {
+    if !memory.as_ptr().cast::<Header>().is_aligned() {
+        return Err(SharedMemoryError::Misaligned);
+    }
+    if len < required {
+        return Err(SharedMemoryError::TooShort(required));
+    }
+    Ok(())
+}
+
+/// The pushing half of a `SharedRing`.
+pub struct SharedProducer<T> {
+    ring: SharedRing<T>,
+}
+
+impl<T: Copy> SharedProducer<T> {
+    /// Pushes a record, or hands it back in `PushError::Full`.
+    pub fn try_push(&mut self, value: T) -> Result<(), PushError<T>> {
+        let header = self.ring.header();
+        let write = header.write.load(Ordering::Relaxed);
+        if write.wrapping_sub(header.read.load(Ordering::Acquire)) > self.ring.mask {
+            return Err(PushError::Full(value));
+        }
+        // The slot is vacant and only this producer fills slots.
+        unsafe { ptr::write(self.ring.slot(write), value) };
+        header.write.store(write.wrapping_add(1), Ordering::Release);
+        Ok(())
+    }
+
+    pub fn ring(&self) -> &SharedRing<T> {
+        &self.ring
+    }
+}
+
+/// The popping half of a `SharedRing`.
+pub struct SharedConsumer<T> {
+    ring: SharedRing<T>,
+}
+
+impl<T: Copy> SharedConsumer<T> {
+    /// Pops the oldest record, if any.
+    pub fn pop(&mut self) -> Option<T> {
+        let header = self.ring.header();
+        let read = header.read.load(Ordering::Relaxed);
+        if header.write.load(Ordering::Acquire) == read {
+            return None;
+        }
+        // Published by the producer, which does not reuse the slot until
+        // `read` moves past it.
+        let value = unsafe { ptr::read(self.ring.slot(read)) };
+        header.read.store(read.wrapping_add(1), Ordering::Release);
+        Some(value)
+    }
+
+    pub fn ring(&self) -> &SharedRing<T> {
+        &self.ring
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    /// Memory standing for the embedder's, aligned as a ring needs.
+    #[repr(C, align(64))]
+    struct Memory([u8; 512]);
+
+    #[test]
+    fn init_and_attach() {
+        let mut memory = Memory([0; 512]);
+        let base = NonNull::from(&mut memory).cast::<u8>();
+        unsafe {
+            assert_eq!(SharedRing::<u64>::attach(base, 512).err(), Some(SharedMemoryError::NotInitialized));
+            assert_eq!(SharedRing::<u64>::init(base, 512, 64).err(), Some(SharedMemoryError::TooShort(704)));
+            let offset = NonNull::new_unchecked(base.as_ptr().add(8));
+            assert_eq!(SharedRing::<u64>::init(offset, 504, 4).err(), Some(SharedMemoryError::Misaligned));
+        }
+        let mut tx = unsafe { SharedRing::<u64>::init(base, 512, 3) }.unwrap().into_producer();
+        assert_eq!(unsafe { SharedRing::<u32>::attach(base, 512) }.err(), Some(SharedMemoryError::RecordSize));
+        let mut rx = unsafe { SharedRing::<u64>::attach(base, 512) }.unwrap().into_consumer();
+        assert_eq!(rx.ring().capacity(), 4);
+        for i in 0..4 {
+            assert!(tx.try_push(i).is_ok());
+        }
+        assert_eq!(tx.try_push(4), Err(PushError::Full(4)));
+        assert_eq!(rx.pop(), Some(0));
+        assert!(tx.try_push(4).is_ok());
+        let popped: Vec<_> = core::iter::from_fn(|| rx.pop()).collect();
+        assert_eq!(popped, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn attach_rejects_a_bad_capacity() {
+        let mut memory = Memory([0; 512]);
+        let base = NonNull::from(&mut memory).cast::<u8>();
+        unsafe { SharedRing::<u64>::init(base, 512, 4) }.unwrap();
+        // `capacity` follows `magic` in the header.
+        let capacity = unsafe { base.as_ptr().add(4).cast::<u32>() };
+        for bad in [0, 3] {
+            unsafe { capacity.write(bad) };
+            assert_eq!(unsafe { SharedRing::<u64>::attach(base, 512) }.err(), Some(SharedMemoryError::Capacity));
+        }
+        unsafe { capacity.write(4) };
+        assert!(unsafe { SharedRing::<u64>::attach(base, 512) }.is_ok());
+    }
+
+    #[test]
+    fn producer_at_the_documented_offsets() {
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        #[repr(C)]
+        struct Sample {
+            id: u32,
+            value: f32,
+        }
+        let mut memory = Memory([0; 512]);
+        let base = NonNull::from(&mut memory).cast::<u8>();
+        let mut rx = unsafe { SharedRing::<Sample>::init(base, 512, 4) }.unwrap().into_consumer();
+        // What `ringbuf_shared_push` does.
+        let push = |record: Sample| unsafe {
+            let write = &*base.as_ptr().add(WRITE_OFFSET).cast::<AtomicU32>();
+            let w = write.load(Ordering::Relaxed);
+            let slot = base.as_ptr().add(SLOTS_OFFSET + (w & 3) as usize * 8).cast::<Sample>();
+            slot.write(record);
+            write.store(w + 1, Ordering::Release);
+        };
+        push(Sample { id: 1, value: 0.5 });
+        push(Sample { id: 2, value: 1.5 });
+        assert_eq!(rx.pop(), Some(Sample { id: 1, value: 0.5 }));
+        assert_eq!(rx.pop(), Some(Sample { id: 2, value: 1.5 }));
+        let read = unsafe { &*base.as_ptr().add(READ_OFFSET).cast::<AtomicU32>() };
+        assert_eq!(read.load(Ordering::Acquire), 2);
+    }
+
+    #[test]
+    fn across_threads() {
+        let mut memory = Box::new(Memory([0; 512]));
+        let base = NonNull::from(&mut *memory).cast::<u8>();
+        let mut tx = unsafe { SharedRing::<u32>::init(base, 512, 8) }.unwrap().into_producer();
+        let mut rx = unsafe { SharedRing::<u32>::attach(base, 512) }.unwrap().into_consumer();
+        let worker = thread::spawn(move || {
+            let mut next = 0;
+            while next < 1000 {
+                match rx.pop() {
+                    Some(value) => {
+                        assert_eq!(value, next);
+                        next += 1;
+                    }
+                    None => thread::yield_now(),
+                }
+            }
+        });
+        for i in 0..1000 {
+            let mut value = i;
+            while let Err(e) = tx.try_push(value) {
+                value = e.into_inner();
+                thread::yield_now();
+            }
+        }
+        worker.join().unwrap();
+        drop(memory);
+    }
+}
@@ -0,0 +1,367 @@
+//! Shared-memory SPSC rings, for a producer process and a consumer process
+//! exchanging fixed-size records lock-free. The ring lives in a
+//! memory-mapped file (preferably on a tmpfs such as `/dev/shm`): a
+//! `#[repr(C)]` header holding the capacity, the record size and the
+//! `read`/`write` counters, each on its own cache line, followed by the
+//! slots. One process `create`s it, the other `open`s it; each then takes
+//! the half for its role.
+//! Records are copied as raw bytes between address spaces, so `T` must be
+//! plain data: no pointers, references or handles.
+//! The consumer can also commit its read offset to the header, every so
+//! many pops, and a restarted consumer resume from it: a log-shipping agent
+//! then ships again the records it popped but did not commit, except those
+//! the producer has overwritten since, which it counts as lost.
+
+use core::marker::PhantomData;
+use core::mem;
+use core::ptr;
+use std::fs::OpenOptions;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{self, AtomicU64, Ordering};
+
+use crate::error::PushError;
+use crate::mmap::Mapping;
+use crate::spsc_lockfree::CachePadded;
+
+/// Identifies an initialized ring, and the version of its layout.
+const MAGIC: u64 = u64::from_le_bytes(*b"RINGSHM2");
+
+/// Start of the mapped file.
+#[repr(C)]
+struct Header {
+    /// Set to `MAGIC` once the other fields are.
+    magic: AtomicU64,
+    capacity: u64,
+    record_size: u64,
+    write: CachePadded<AtomicU64>,
+    read: CachePadded<AtomicU64>,
+    /// `read` as of the consumer's last commit.
+    committed: CachePadded<AtomicU64>,
+}
+
+/// A ring of `T` records in a memory-mapped file, to turn into the
+/// `ShmProducer` or the `ShmConsumer` of this process.
+pub struct ShmRingBuffer<T> {
+    map: Mapping,
+    mask: u64,
+    _records: PhantomData<T>,
+}
+
+// Only plain data crosses the mapping.
+unsafe impl<T: Send> Send for ShmRingBuffer<T> {}
+
+impl<T: Copy> ShmRingBuffer<T> {
+    /// Creates (or truncates) the file at `path` and lays out an empty ring
+    /// of `capacity` records in it, rounded up to the next power of two.
+    pub fn create(path: impl AsRef<Path>, capacity: usize) -> io::Result<Self> {
+        assert!(mem::align_of::<T>() <= mem::align_of::<Header>(), "records must not need more than 64-byte alignment");
+        let capacity = capacity.max(1).next_power_of_two();
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path)?;
+        let len = Self::file_len(capacity);
+        file.set_len(len as u64)?;
+        let map = Mapping::new(&file, len)?;
+        // Fresh mapping of a zero-filled file, not shared with anyone yet
+        // as `magic` is not set.
+        let header = map.as_ptr() as *mut Header;
+        unsafe {
+            (*header).capacity = capacity as u64;
+            (*header).record_size = mem::size_of::<T>() as u64;
+            (*header).magic.store(MAGIC, Ordering::Release);
+        }
+        Ok(ShmRingBuffer { map, mask: capacity as u64 - 1, _records: PhantomData })
+    }
+
+    /// Maps the ring created at `path` by `create`. Fails with
+    /// `InvalidData` if the file is not such a ring (or not initialized
+    /// yet), or if its records are not the size of `T`.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let len = file.metadata()?.len() as usize;
+        if len < mem::size_of::<Header>() {
+            return Err(invalid_data("file too short for a ring header"));
+        }
+        let map = Mapping::new(&file, len)?;
+        let header = unsafe { &*(map.as_ptr() as *const Header) };
+        if header.magic.load(Ordering::Acquire) != MAGIC {
+            return Err(invalid_data("not an initialized ring"));
+        }
+        if header.record_size != mem::size_of::<T>() as u64 {
+            return Err(invalid_data("record size mismatch"));
+        }
+        let capacity = header.capacity as usize;
+        if !capacity.is_power_of_two() || Self::file_len(capacity) > len {
+            return Err(invalid_data("capacity does not match the file"));
+        }
+        Ok(ShmRingBuffer { map, mask: capacity as u64 - 1, _records: PhantomData })
+    }
+
+    fn file_len(capacity: usize) -> usize {
+        mem::size_of::<Header>() + capacity * mem::size_of::<T>()
+    }
+
+    /// Takes the pushing half. Only one process may push to a ring.
+    pub fn into_producer(self) -> ShmProducer<T> {
+        ShmProducer { ring: self }
+    }
+
+    /// Takes the popping half. Only one process may pop from a ring.
+    pub fn into_consumer(self) -> ShmConsumer<T> {
+        let read = self.header().read.load(Ordering::Relaxed);
+        ShmConsumer { ring: self, read, replay_end: read, lost: 0, commit_interval: 0, uncommitted: 0 }
+    }
+
+    /// Takes the popping half, resuming from the last committed offset
+    /// rather than from the last pop, see the module documentation.
+    pub fn into_resumed_consumer(self) -> ShmConsumer<T> {
+        let header = self.header();
+        let (read, committed) = (header.read.load(Ordering::Relaxed), header.committed.load(Ordering::Relaxed));
+        // Stops the producer from reusing more of the slots to replay.
+        header.read.store(committed, Ordering::Release);
+        ShmConsumer { ring: self, read: committed, replay_end: read, lost: 0, commit_interval: 0, uncommitted: 0 }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.mask as usize + 1
+    }
+
+    /// Returns the number of records queued, which may be stale by the time
+    /// it returns.
+    pub fn len(&self) -> usize {
+        let read = self.header().read.load(Ordering::Acquire);
+        let write = self.header().write.load(Ordering::Acquire);
+        write.wrapping_sub(read) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn header(&self) -> &Header {
+        // Checked by `create`/`open`, and only accessed through atomics
+        // from then on.
+        unsafe { &*(self.map.as_ptr() as *const Header) }
+    }
+
+    /// Returns the slot of the counter `i`.
+    fn slot(&self, i: u64) -> *mut T {
+        let offset = mem::size_of::<Header>() + (i & self.mask) as usize * mem::size_of::<T>();
+        unsafe { self.map.as_ptr().add(offset) as *mut T }
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// The pushing half of a `ShmRingBuffer`.
+pub struct ShmProducer<T> {
+    ring: ShmRingBuffer<T>,
+}
+
+impl<T: Copy> ShmProducer<T> {
+    /// Pushes a record, or hands it back in `PushError::Full`.
+    pub fn try_push(&mut self, value: T) -> Result<(), PushError<T>> {
+        let header = self.ring.header();
+        let write = header.write.load(Ordering::Relaxed);
+        if write.wrapping_sub(header.read.load(Ordering::Acquire)) > self.ring.mask {
+            return Err(PushError::Full(value));
+        }
+        // Orders the last `write` store before the slot is reused, for a
+        // resumed consumer that may be replaying it.
+        atomic::fence(Ordering::Release);
+        // The slot is vacant and only this producer fills slots.
+        unsafe { ptr::write(self.ring.slot(write), value) };
+        header.write.store(write.wrapping_add(1), Ordering::Release);
+        Ok(())
+    }
+
+    pub fn ring(&self) -> &ShmRingBuffer<T> {
+        &self.ring
+    }
+}
+
+/// The popping half of a `ShmRingBuffer`.
+pub struct ShmConsumer<T> {
+    ring: ShmRingBuffer<T>,
+    read: u64,
+    /// `read` of the previous consumer: the producer may reuse the slots
+    /// before it.
+    replay_end: u64,
+    lost: u64,
+    commit_interval: u64,
+    uncommitted: u64,
+}
+
+impl<T: Copy> ShmConsumer<T> {
+    /// Pops the oldest record, if any.
+    pub fn pop(&mut self) -> Option<T> {
+        loop {
+            let read = self.read;
+            if self.ring.header().write.load(Ordering::Acquire) == read {
+                return None;
+            }
+            // Published by the producer, which does not reuse the slot
+            // until `read` moves past it, unless it is replayed.
+            let value = unsafe { ptr::read(self.ring.slot(read)) };
+            if read.wrapping_sub(self.replay_end) as i64 >= 0 {
+                self.advance(read.wrapping_add(1));
+                return Some(value);
+            }
+            // Replayed: the copy is only valid if the producer did not
+            // start to overwrite the slot, as in a seqlock.
+            atomic::fence(Ordering::Acquire);
+            let write = self.ring.header().write.load(Ordering::Relaxed);
+            if write.wrapping_sub(read) <= self.ring.mask {
+                self.advance(read.wrapping_add(1));
+                return Some(value);
+            }
+            // Skips to the oldest record the producer cannot be overwriting.
+            let oldest = write.wrapping_sub(self.ring.mask);
+            let next = if self.replay_end.wrapping_sub(oldest) as i64 > 0 { oldest } else { self.replay_end };
+            self.lost += next.wrapping_sub(read);
+            self.advance(next);
+        }
+    }
+
+    fn advance(&mut self, read: u64) {
+        self.read = read;
+        self.ring.header().read.store(read, Ordering::Release);
+        self.uncommitted += 1;
+        if self.commit_interval != 0 && self.uncommitted >= self.commit_interval {
+            self.commit();
+        }
+    }
+
+    /// Commits the read offset to the header: a consumer resumed with
+    /// `into_resumed_consumer` starts from there.
+    pub fn commit(&mut self) {
+        self.ring.header().committed.store(self.read, Ordering::Release);
+        self.uncommitted = 0;
+    }
+
+    /// Commits every `pops` pops from now on, or only on `commit` if 0.
+    pub fn set_commit_interval(&mut self, pops: u64) {
+        self.commit_interval = pops;
+    }
+
+    /// Returns the number of records skipped when resuming because the
+    /// producer had overwritten them.
+    pub fn lost(&self) -> u64 {
+        self.lost
+    }
+
+    pub fn ring(&self) -> &ShmRingBuffer<T> {
+        &self.ring
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::thread;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    #[repr(C)]
+    struct Tick {
+        seq: u64,
+        price: f64,
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ringbuf-shm-{name}-{}", std::process::id()))
+    }
+
+    #[test]
+    fn two_mappings_of_one_file() {
+        let path = temp_path("mappings");
+        let mut tx = ShmRingBuffer::create(&path, 3).unwrap().into_producer();
+        let mut rx = ShmRingBuffer::<Tick>::open(&path).unwrap().into_consumer();
+        assert_eq!(rx.ring().capacity(), 4);
+        for seq in 0..4 {
+            assert!(tx.try_push(Tick { seq, price: 1.5 }).is_ok());
+        }
+        assert!(tx.try_push(Tick { seq: 4, price: 0.0 }).unwrap_err().is_full());
+        assert_eq!(rx.pop(), Some(Tick { seq: 0, price: 1.5 }));
+        assert_eq!(tx.ring().len(), 3);
+
+        let consumer = thread::spawn(move || {
+            let mut next = 1;
+            while next < 1000 {
+                match rx.pop() {
+                    Some(tick) => {
+                        assert_eq!(tick.seq, next);
+                        next += 1;
+                    }
+                    None => thread::yield_now(),
+                }
+            }
+        });
+        for seq in 4..1000 {
+            let mut tick = Tick { seq, price: 0.0 };
+            while let Err(e) = tx.try_push(tick) {
+                tick = e.into_inner();
+                thread::yield_now();
+            }
+        }
+        consumer.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn open_checks_the_header() {
+        let path = temp_path("header");
+        assert!(ShmRingBuffer::<u64>::open(&path).is_err());
+        std::fs::write(&path, [0; 256]).unwrap();
+        assert_eq!(ShmRingBuffer::<u64>::open(&path).err().unwrap().kind(), io::ErrorKind::InvalidData);
+        drop(ShmRingBuffer::<u64>::create(&path, 8).unwrap());
+        assert_eq!(ShmRingBuffer::<u32>::open(&path).err().unwrap().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(ShmRingBuffer::<u64>::open(&path).unwrap().capacity(), 8);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn resumes_from_the_commit() {
+        let path = temp_path("resume");
+        let mut tx = ShmRingBuffer::<u64>::create(&path, 4).unwrap().into_producer();
+        let mut rx = ShmRingBuffer::<u64>::open(&path).unwrap().into_consumer();
+        rx.set_commit_interval(2);
+        for i in 0..4 {
+            assert!(tx.try_push(i).is_ok());
+        }
+        assert_eq!((rx.pop(), rx.pop(), rx.pop()), (Some(0), Some(1), Some(2)));
+        // Crashes after shipping 2 without committing it.
+        drop(rx);
+        let mut rx = ShmRingBuffer::<u64>::open(&path).unwrap().into_resumed_consumer();
+        assert_eq!(tx.ring().len(), 2);
+        assert_eq!((rx.pop(), rx.pop(), rx.pop()), (Some(2), Some(3), None));
+        assert_eq!(rx.lost(), 0);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn resume_skips_overwritten_records() {
+        let path = temp_path("overwritten");
+        let mut tx = ShmRingBuffer::<u64>::create(&path, 4).unwrap().into_producer();
+        let mut rx = ShmRingBuffer::<u64>::open(&path).unwrap().into_consumer();
+        for i in 0..4 {
+            assert!(tx.try_push(i).is_ok());
+        }
+        for i in 0..4 {
+            assert_eq!(rx.pop(), Some(i));
+        }
+        // Reuses the slots of 0..3, popped but never committed.
+        for i in 4..7 {
+            assert!(tx.try_push(i).is_ok());
+        }
+        drop(rx);
+        let mut rx = ShmRingBuffer::<u64>::open(&path).unwrap().into_resumed_consumer();
+        let popped: Vec<_> = core::iter::from_fn(|| rx.pop()).collect();
+        // 3 is skipped too: the producer could have been about to reuse its
+        // slot for 7.
+        assert_eq!(popped, [4, 5, 6]);
+        assert_eq!(rx.lost(), 4);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
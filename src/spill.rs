@@ -0,0 +1,186 @@
+//! A lossy ring of byte blocks, such as batches of log lines waiting to be
+//! shipped, that does not lose its blocks when it overflows: the producer
+//! evicts the oldest block to make room, as with `force_push`, and
+//! compresses it with LZ4 into a `SpillSink`, a secondary message ring or
+//! a file, from which it can be shipped later.
+//! A block spilled to a `Write` sink is framed by its 4-byte little-endian
+//! length, so the file can be read back with `read_spilled`.
+
+use alloc::vec::Vec;
+use std::io::{self, Read, Write};
+
+use crate::error::MessageError;
+use crate::spsc_lockfree::{MessageProducer, SPSCRingBuffer};
+use crate::sync::Arc;
+
+/// Where the compressed evicted blocks go.
+pub trait SpillSink {
+    fn spill(&mut self, compressed: &[u8]) -> io::Result<()>;
+}
+
+/// Writes each block after its length.
+impl<W: Write> SpillSink for W {
+    fn spill(&mut self, compressed: &[u8]) -> io::Result<()> {
+        self.write_all(&(compressed.len() as u32).to_le_bytes())?;
+        self.write_all(compressed)
+    }
+}
+
+/// Pushes each block as a message. Fails with `WouldBlock` while the ring
+/// is full, `InvalidInput` for a block it cannot hold and `BrokenPipe`
+/// once its consumer is dropped.
+impl SpillSink for MessageProducer {
+    fn spill(&mut self, compressed: &[u8]) -> io::Result<()> {
+        self.push_msg(compressed).map_err(|e| {
+            let kind = match e {
+                MessageError::TooLong(_) => io::ErrorKind::InvalidInput,
+                MessageError::Full => io::ErrorKind::WouldBlock,
+                MessageError::Disconnected => io::ErrorKind::BrokenPipe,
+            };
+            io::Error::new(kind, e)
+        })
+    }
+}
+
+/// Decompresses a spilled block, e.g. a message of a spill ring. Fails
+/// with `InvalidData` if it is not one.
+pub fn decompress(compressed: &[u8]) -> io::Result<Vec<u8>> {
+    lz4_flex::decompress_size_prepended(compressed).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Reads and decompresses the next block spilled to a file, `None` at its
+/// end.
+pub fn read_spilled<R: Read + ?Sized>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let mut compressed = vec![0; u32::from_le_bytes(len) as usize];
+    reader.read_exact(&mut compressed)?;
+    decompress(&compressed).map(Some)
+}
+
+/// A ring of blocks spilling its evicted blocks, to split into a
+/// `SpillingProducer` and a `SpillingConsumer`.
+pub struct SpillingRing<S> {
+    ring: SPSCRingBuffer<Vec<u8>>,
+    sink: S,
+}
+
+impl<S: SpillSink> SpillingRing<S> {
+    /// Creates a ring of `capacity` blocks, rounded up to the next power of
+    /// two, spilling to `sink`.
+    pub fn new(capacity: usize, sink: S) -> Self {
+        SpillingRing { ring: SPSCRingBuffer::new_overwriting(capacity), sink }
+    }
+
+    /// Splits the ring into halves that can be moved to different threads.
+    pub fn split(self) -> (SpillingProducer<S>, SpillingConsumer) {
+        let ring = Arc::new(self.ring);
+        (SpillingProducer { ring: ring.clone(), sink: self.sink, spilled: 0 }, SpillingConsumer { ring })
+    }
+}
+
+/// The pushing half of a `SpillingRing`, which owns the sink.
+pub struct SpillingProducer<S> {
+    ring: Arc<SPSCRingBuffer<Vec<u8>>>,
+    sink: S,
+    spilled: usize,
+}
+
+impl<S: SpillSink> SpillingProducer<S> {
+    /// Pushes a block. If the ring is full, the oldest block is evicted and
+    /// spilled first; if the sink fails, that block is lost and its error
+    /// returned, `block` is pushed all the same.
+    pub fn push(&mut self, block: Vec<u8>) -> io::Result<()> {
+        match self.ring.force_push(block) {
+            Some(evicted) => {
+                self.sink.spill(&lz4_flex::compress_prepend_size(&evicted))?;
+                self.spilled += 1;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the number of blocks spilled so far.
+    pub fn spilled(&self) -> usize {
+        self.spilled
+    }
+
+    /// Returns the sink, e.g. to flush a file.
+    pub fn sink_mut(&mut self) -> &mut S {
+        &mut self.sink
+    }
+}
+
+/// The popping half of a `SpillingRing`, shipping the blocks not spilled.
+pub struct SpillingConsumer {
+    ring: Arc<SPSCRingBuffer<Vec<u8>>>,
+}
+
+impl SpillingConsumer {
+    /// Pops the oldest block still in the ring.
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        self.ring.pop().map(|(_, block)| block)
+    }
+
+    /// Returns the number of blocks in the ring.
+    pub fn len(&self) -> usize {
+        self.ring.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ring.is_empty()
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use crate::spsc_lockfree::MessageRingBuffer;
+
+    fn block(i: usize) -> Vec<u8> {
+        format!("log line {i}\n").repeat(20).into_bytes()
+    }
+
+    #[test]
+    fn spills_to_a_file() {
+        let (mut tx, mut rx) = SpillingRing::new(2, Vec::new()).split();
+        for i in 0..5 {
+            tx.push(block(i)).unwrap();
+        }
+        assert_eq!(tx.spilled(), 3);
+        assert_eq!(rx.len(), 2);
+        assert_eq!(rx.pop().unwrap(), block(3));
+        assert_eq!(rx.pop().unwrap(), block(4));
+        assert!(rx.is_empty());
+        // Compressed: the lines repeat.
+        let file = tx.sink_mut();
+        assert!(file.len() < 3 * block(0).len());
+        let mut reader = &file[..];
+        for i in 0..3 {
+            assert_eq!(read_spilled(&mut reader).unwrap().unwrap(), block(i));
+        }
+        assert!(read_spilled(&mut reader).unwrap().is_none());
+    }
+
+    #[test]
+    fn spills_to_a_message_ring() {
+        let (spill_tx, mut spill_rx) = MessageRingBuffer::new_split(256);
+        let (mut tx, _rx) = SpillingRing::new(1, spill_tx).split();
+        tx.push(block(0)).unwrap();
+        tx.push(block(1)).unwrap();
+        assert_eq!(decompress(&spill_rx.pop_msg().unwrap()).unwrap(), block(0));
+        assert!(decompress(b"not lz4").is_err());
+        let noise: Vec<u8> = (0..200u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8).collect();
+        tx.push(noise).unwrap();
+        // The noise does not compress, and is too long for the spill ring.
+        assert_eq!(tx.push(block(2)).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(tx.spilled(), 2);
+        drop(spill_rx);
+        assert_eq!(tx.push(block(3)).unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+    }
+}
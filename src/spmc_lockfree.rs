@@ -0,0 +1,196 @@
+//! A bounded single-producer multi-consumer queue for work distribution:
+//! each value is taken by exactly one of the competing `Consumer`s, e.g.
+//! jobs produced by an I/O thread and fanned out to a pool of workers.
+//! The slots are those of `mpmc_lockfree`; with a single producer
+//! a push needs no CAS.
+
+use crate::error::{PopError, PushError};
+use crate::mpmc_lockfree::MPMCRingBuffer;
+use crate::sync::{Arc, AtomicBool, AtomicUsize, Ordering};
+
+struct Shared<T> {
+    queue: MPMCRingBuffer<T>,
+    /// Live `Consumer`s.
+    consumers: AtomicUsize,
+    /// Set when the `Producer` is dropped.
+    producer_dropped: AtomicBool,
+}
+
+/// Creates a queue with `capacity` rounded up to the next power of two, and
+/// at least 2, and returns its producer and first consumer. Clone the
+/// `Consumer` for each worker.
+pub fn channel<T>(capacity: usize) -> (Producer<T>, Consumer<T>) {
+    let shared = Arc::new(Shared {
+        queue: MPMCRingBuffer::with_capacity(capacity),
+        consumers: AtomicUsize::new(1),
+        producer_dropped: AtomicBool::new(false),
+    });
+    (Producer { shared: shared.clone() }, Consumer { shared })
+}
+
+/// The pushing half of an SPMC `channel`, there is only one.
+pub struct Producer<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Producer<T> {
+    /// Pushes a value. Fails with `Full`, or with `Disconnected` once all
+    /// the `Consumer`s are dropped, handing the value back.
+    pub fn push(&mut self, value: T) -> Result<(), PushError<T>> {
+        if self.is_disconnected() {
+            return Err(PushError::Disconnected(value));
+        }
+        // `&mut self` on the only producer: no other push can run.
+        unsafe { self.shared.queue.push_single(value) }
+    }
+
+    /// Returns true if all the `Consumer`s were dropped.
+    pub fn is_disconnected(&self) -> bool {
+        self.shared.consumers.load(Ordering::Acquire) == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared.queue.capacity()
+    }
+
+    /// See `mpmc_lockfree::MPMCRingBuffer::len`.
+    pub fn len(&self) -> usize {
+        self.shared.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shared.queue.is_empty()
+    }
+}
+
+impl<T> Drop for Producer<T> {
+    fn drop(&mut self) {
+        self.shared.producer_dropped.store(true, Ordering::Release);
+    }
+}
+
+/// A popping half of an SPMC `channel`. Each value goes to only one of the
+/// clones.
+pub struct Consumer<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Consumer<T> {
+    /// Pops the oldest value not taken by another consumer. Values pushed
+    /// before the `Producer` was dropped are still delivered, `Disconnected`
+    /// is only returned once the queue is drained.
+    pub fn pop(&self) -> Result<T, PopError> {
+        if let Some(value) = self.shared.queue.pop() {
+            return Ok(value);
+        }
+        if !self.is_disconnected() {
+            return Err(PopError::Empty);
+        }
+        // The producer may have pushed right before dropping.
+        self.shared.queue.pop().ok_or(PopError::Disconnected)
+    }
+
+    /// Returns true if the `Producer` was dropped.
+    pub fn is_disconnected(&self) -> bool {
+        self.shared.producer_dropped.load(Ordering::Acquire)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared.queue.capacity()
+    }
+}
+
+impl<T> Clone for Consumer<T> {
+    fn clone(&self) -> Self {
+        self.shared.consumers.fetch_add(1, Ordering::Relaxed);
+        Consumer { shared: self.shared.clone() }
+    }
+}
+
+impl<T> Drop for Consumer<T> {
+    fn drop(&mut self) {
+        self.shared.consumers.fetch_sub(1, Ordering::Release);
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn push_and_pop() {
+        let (mut tx, rx) = channel(2);
+        assert!(tx.push(1).is_ok());
+        assert!(tx.push(2).is_ok());
+        assert_eq!(tx.push(3), Err(PushError::Full(3)));
+        let rx2 = rx.clone();
+        assert_eq!(rx2.pop(), Ok(1));
+        assert_eq!(rx.pop(), Ok(2));
+        assert_eq!(rx.pop(), Err(PopError::Empty));
+        assert!(tx.push(3).is_ok());
+        drop(tx);
+        assert_eq!(rx2.pop(), Ok(3));
+        assert_eq!(rx.pop(), Err(PopError::Disconnected));
+
+        let (mut tx, rx) = channel(2);
+        drop(rx);
+        assert_eq!(tx.push(1), Err(PushError::Disconnected(1)));
+    }
+
+    #[test]
+    fn competing_consumers() {
+        let (mut tx, rx) = channel(16);
+        let workers: Vec<_> = (0..3)
+            .map(|_| {
+                let rx = rx.clone();
+                thread::spawn(move || {
+                    let mut jobs = Vec::new();
+                    loop {
+                        match rx.pop() {
+                            Ok(job) => jobs.push(job),
+                            Err(PopError::Empty) => thread::yield_now(),
+                            Err(PopError::Disconnected) => return jobs,
+                        }
+                    }
+                })
+            })
+            .collect();
+        drop(rx);
+        for job in 0..1000 {
+            let mut job = job;
+            while let Err(e) = tx.push(job) {
+                job = e.into_inner();
+                thread::yield_now();
+            }
+        }
+        drop(tx);
+        let mut jobs: Vec<_> = workers.into_iter().flat_map(|w| w.join().unwrap()).collect();
+        jobs.sort();
+        assert_eq!(jobs, (0..1000).collect::<Vec<_>>());
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+    use loom::thread;
+
+    #[test]
+    fn each_value_taken_once() {
+        loom::model(|| {
+            let (mut tx, rx) = channel(2);
+            let worker = {
+                let rx = rx.clone();
+                thread::spawn(move || rx.pop().ok())
+            };
+            tx.push(1).unwrap();
+            tx.push(2).unwrap();
+            let mine = rx.pop().ok();
+            let theirs = worker.join().unwrap();
+            let mut taken: Vec<_> = [mine, theirs, rx.pop().ok()].into_iter().flatten().collect();
+            taken.sort();
+            assert_eq!(taken, [1, 2]);
+        });
+    }
+}
@@ -0,0 +1,520 @@
+//! The ring buffer implementation that supports Single Producer and Single Consumer.
+//! The ring buffer is a FIFO data structure that uses a single,
+//! fixed-size buffer as if it were connected end-to-end.
+//! Design choices:
+//! The implementation is not thread-safe.
+//! When the buffer is full, the oldest value is overwritten.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+
+#[cfg(feature = "serde")]
+use serde::{de, ser::SerializeStruct, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::error::{PopError, PushError};
+
+/// Populate the array with this value to check if the value is popped.
+/// Useful for debugging.
+const SENTINEL_VALUE: u64 = 0xdeadc0de;
+
+/// Starts the output of `save_to`.
+const SNAPSHOT_MAGIC: [u8; 4] = *b"RBUF";
+/// Version of the format written by `save_to`.
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// Capacity used by `ArrayRingBuffer::default()`.
+pub const DEFAULT_CAPACITY: usize = 64;
+
+/// FIFO ring buffer with Single Producer and Single Consumer.
+pub struct ArrayRingBuffer {
+    read: u64, // From where we will **pop** the next value.
+    write: u64, // To where we will **push** the next value.
+    buffer: Vec<u64>,
+}
+
+#[deprecated(note = "renamed to `ArrayRingBuffer`")]
+pub type SPSCRingBuffer = ArrayRingBuffer;
+
+impl ArrayRingBuffer {
+    #[deprecated(note = "use `with_capacity`; `cap` is the number of slots, not a length")]
+    pub fn new(cap: usize) -> Self {
+        Self::with_capacity(cap.saturating_sub(1))
+    }
+    /// Creates a ring buffer holding up to `cap` values. One more slot is
+    /// allocated, kept as sentinel.
+    pub fn with_capacity(cap: usize) -> Self {
+        let read = 0;
+        let write = 0;
+        let buffer = vec!(0; cap.checked_add(1).expect("capacity overflow"));
+        Self {
+            read,
+            write,
+            buffer
+        }
+    }
+    /// Pushes a value, or hands it back in `PushError::Full`.
+    pub fn push(&mut self, v: u64) -> Result<(), PushError<u64>> {
+        if !self.full() {
+            let idx = self.modulo(self.write) as usize;
+            self.buffer[idx] = v;
+            self.write = self.fold(self.write + 1);
+            Ok(())
+        } else {
+            Err(PushError::Full(v))
+        }
+    }
+    /// Forcefully pushes a value into the ring buffer.
+    /// If the buffer is full, it will overwrite the oldest value.
+    pub fn force_push(&mut self, v: u64) {
+        if self.full() {
+            self.read = self.fold(self.read + 1);
+        }
+        let idx = self.modulo(self.write) as usize;
+        self.buffer[idx] = v;
+        self.write = self.fold(self.write + 1);
+    }
+    /// Pops a value from the ring buffer.
+    /// Returns an error if the buffer is empty.
+    pub fn pop(&mut self) -> Result<u64, PopError> {
+        let idx = self.modulo(self.read) as usize;
+        let v = self.buffer[idx];
+        if self.empty() {
+            Err(PopError::Empty)
+        } else {
+            // For debugging purpose.
+            self.buffer[idx] = SENTINEL_VALUE;
+            self.read = self.fold(self.read + 1);
+            Ok(v)
+        }
+    }
+    pub fn full(&self) -> bool {
+        self.free() == 0
+    }
+    pub fn empty(&self) -> bool {
+        self.write == self.read
+    }
+    /// Returns the capacity (maximum number of elements that
+    /// can be queued) of the ring buffer.
+    pub fn capacity(&self) -> usize {
+        self.buffer.capacity() - 1
+    }
+    pub fn wrapped_distance(&self) -> u64 {
+        if self.write >= self.read {
+            (self.write - self.read) % self.buffer.capacity() as u64
+        } else {
+            (self.write + self.buffer.capacity() as u64 - self.read) % self.buffer.capacity() as u64
+        }
+    }
+    /// Returns the number of elements in the ring buffer.
+    pub fn size(&self) -> usize {
+        self.wrapped_distance() as usize
+    }
+    /// Returns the number of free slots in the ring buffer, not counting
+    /// the sentinel.
+    pub fn free(&self) -> usize {
+        self.capacity() - self.size()
+    }
+    /// Grows the ring buffer to a capacity of `new_cap` values, keeping the
+    /// queued values in FIFO order. Does nothing if `new_cap` is not larger
+    /// than the current capacity.
+    pub fn grow(&mut self, new_cap: usize) {
+        if new_cap > self.capacity() {
+            self.relocate(new_cap + 1);
+        }
+    }
+    /// Shrinks the ring buffer to the smallest capacity that still holds
+    /// all the queued values.
+    pub fn shrink_to_fit(&mut self) {
+        let new_cap = self.size();
+        if new_cap < self.capacity() {
+            self.relocate(new_cap + 1);
+        }
+    }
+    /// Moves the occupied region into a fresh buffer of `new_cap` slots.
+    /// The occupied region may wrap around the end of the old buffer, so
+    /// it is copied in two parts and unrolled to start at index 0.
+    fn relocate(&mut self, new_cap: usize) {
+        let size = self.size();
+        assert!(new_cap > size, "new capacity {new_cap} cannot hold {size} values");
+        let mut buffer = vec!(0; new_cap);
+        let start = self.modulo(self.read) as usize;
+        let first = size.min(self.buffer.len() - start);
+        buffer[..first].copy_from_slice(&self.buffer[start..start + first]);
+        buffer[first..size].copy_from_slice(&self.buffer[..size - first]);
+        self.buffer = buffer;
+        self.read = 0;
+        self.write = size as u64;
+    }
+    /// Writes the ring, cursors and all slots, in a small binary format:
+    /// `RBUF`, the format version, then the slot count, `read`, `write` and
+    /// the slots, each as a little-endian `u64` (the version as a `u32`).
+    pub fn save_to<W: Write + ?Sized>(&self, out: &mut W) -> io::Result<()> {
+        out.write_all(&SNAPSHOT_MAGIC)?;
+        out.write_all(&SNAPSHOT_VERSION.to_le_bytes())?;
+        for v in [self.buffer.len() as u64, self.read, self.write].iter().chain(&self.buffer) {
+            out.write_all(&v.to_le_bytes())?;
+        }
+        Ok(())
+    }
+    /// Reads back a ring written by `save_to`. Fails with `InvalidData` on
+    /// anything else, or a version this build does not know.
+    pub fn load_from<R: Read + ?Sized>(input: &mut R) -> io::Result<Self> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let mut header = [0; 8];
+        input.read_exact(&mut header)?;
+        if header[..4] != SNAPSHOT_MAGIC {
+            return Err(invalid("not a ring buffer snapshot".to_string()));
+        }
+        let version = u32::from_le_bytes(header[4..].try_into().unwrap());
+        if version != SNAPSHOT_VERSION {
+            return Err(invalid(format!("unsupported snapshot version {version}")));
+        }
+        let mut next = || -> io::Result<u64> {
+            let mut bytes = [0; 8];
+            input.read_exact(&mut bytes)?;
+            Ok(u64::from_le_bytes(bytes))
+        };
+        let (cap, read, write) = (next()?, next()?, next()?);
+        // Read slot by slot: a corrupt count fails at the end of the input
+        // instead of allocating it all up front.
+        let mut slots = Vec::new();
+        for _ in 0..cap {
+            slots.push(next()?);
+        }
+        Self::from_snapshot(read, write, &slots).map_err(invalid)
+    }
+    /// Rebuilds a ring from its cursors and slots, checking that they are
+    /// consistent.
+    fn from_snapshot(read: u64, write: u64, slots: &[u64]) -> Result<Self, String> {
+        if slots.is_empty() {
+            return Err("a ring buffer has at least one slot".to_string());
+        }
+        // The cursors are folded below `64 * cap`, see `fold`.
+        let limit = 64 * slots.len() as u64;
+        if read >= limit || write >= limit {
+            return Err(format!("cursors must be below {limit}"));
+        }
+        // `capacity` is that of the `Vec`, which must be exactly the slot
+        // count.
+        let mut buffer = vec!(0; slots.len());
+        buffer.copy_from_slice(slots);
+        Ok(Self { read, write, buffer })
+    }
+    fn fold(&self, val: u64) -> u64 {
+        // See dizzy57's answer on https://www.snellman.net/blog/archive/2016-12-13-ring-buffers/
+        val % (64*self.buffer.capacity()) as u64
+    }
+    fn modulo(&self, val: u64) -> u64 {
+        val % self.buffer.capacity() as u64
+    }
+}
+
+impl Default for ArrayRingBuffer {
+    fn default() -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+}
+
+impl fmt::Debug for ArrayRingBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ArrayRingBuffer")
+            .field("read", &self.read)
+            .field("write", &self.write)
+            .field("buffer", &self.buffer)
+            .finish()
+    }
+}
+
+/// Serialized as its cursors and all of its slots, so that it comes back
+/// exactly as it was: `{ read, write, buffer }`.
+#[cfg(feature = "serde")]
+impl Serialize for ArrayRingBuffer {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("ArrayRingBuffer", 3)?;
+        state.serialize_field("read", &self.read)?;
+        state.serialize_field("write", &self.write)?;
+        state.serialize_field("buffer", &self.buffer)?;
+        state.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for ArrayRingBuffer {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(rename = "ArrayRingBuffer")]
+        struct Snapshot {
+            read: u64,
+            write: u64,
+            buffer: Vec<u64>,
+        }
+
+        let snapshot = Snapshot::deserialize(deserializer)?;
+        Self::from_snapshot(snapshot.read, snapshot.write, &snapshot.buffer).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    #[test]
+    fn create() {
+        let rb : ArrayRingBuffer = ArrayRingBuffer::with_capacity(10);
+        assert_eq!(rb.capacity(), 10);
+        assert_eq!(rb.size(), 0);
+        assert_eq!(rb.free(), rb.capacity() - rb.size());
+    }
+    #[test]
+    fn with_capacity_holds_that_many_values() {
+        let mut rb = ArrayRingBuffer::with_capacity(1);
+        assert_eq!(rb.capacity(), 1);
+        assert!(rb.push(1).is_ok());
+        assert!(rb.full());
+        assert_eq!(rb.push(2), Err(PushError::Full(2)));
+        assert_eq!(rb.pop(), Ok(1));
+        let mut rb = ArrayRingBuffer::with_capacity(0);
+        assert_eq!(rb.capacity(), 0);
+        assert!(rb.full() && rb.empty());
+        assert_eq!(rb.push(1), Err(PushError::Full(1)));
+        assert_eq!(rb.pop(), Err(PopError::Empty));
+        rb.force_push(2);
+        assert_eq!(rb.pop(), Err(PopError::Empty));
+    }
+    #[test]
+    #[should_panic(expected = "capacity overflow")]
+    fn with_capacity_overflow() {
+        ArrayRingBuffer::with_capacity(usize::MAX);
+    }
+    #[test]
+    fn default() {
+        let rb = ArrayRingBuffer::default();
+        assert_eq!(rb.capacity(), DEFAULT_CAPACITY);
+        assert!(rb.empty());
+    }
+    #[test]
+    fn push() {
+        let mut rb : ArrayRingBuffer = ArrayRingBuffer::with_capacity(8);
+        for i in 0..7 {
+            assert!(rb.push(i).is_ok());
+        }
+        assert_eq!(rb.size(), 7);
+        assert_eq!(rb.free(), rb.capacity() - rb.size());
+    }
+    #[test]
+    fn full_and_empty_errors() {
+        let mut rb : ArrayRingBuffer = ArrayRingBuffer::with_capacity(1);
+        assert_eq!(rb.pop(), Err(PopError::Empty));
+        assert!(rb.push(1).is_ok());
+        assert_eq!(rb.push(2), Err(PushError::Full(2)));
+    }
+    #[test]
+    fn force_push() {
+        let mut rb : ArrayRingBuffer = ArrayRingBuffer::with_capacity(7);
+        for i in 0..97 {
+            rb.force_push(i);
+        }
+        assert_eq!(rb.pop().unwrap(), 90);
+        assert_eq!(rb.pop().unwrap(), 91);
+        assert_eq!(rb.size(), 5);
+        assert_eq!(rb.free(), rb.capacity() - rb.size());
+    }
+    #[test]
+    fn force_push_and_pop() {
+        let mut rb = ArrayRingBuffer::with_capacity(16);
+        for i in 0..10 {
+            rb.force_push(i+2);
+        }
+        for _ in 0..10 {
+            assert!(rb.pop().is_ok());
+        }
+        assert_eq!(rb.free(), 16);
+    }
+    #[test]
+    fn push_and_pop() {
+        let mut rb = ArrayRingBuffer::with_capacity(16);
+        for i in 0..10 {
+            assert!(rb.push(i).is_ok());
+        }
+        for _ in 0..10 {
+            assert!(rb.pop().is_ok());
+        }
+        assert_eq!(rb.size(), 0);
+        assert_eq!(rb.free(), rb.capacity() - rb.size());
+    }
+    #[test]
+    fn push_and_pop_at_random() {
+        let mut rb = ArrayRingBuffer::with_capacity(16);
+        assert!(rb.push(0).is_ok());
+        assert!(rb.push(1).is_ok());
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..100 {
+            let y: f64 = rng.gen();
+            if y < 0.5 {
+                if !rb.full() {
+                    assert!(rb.push(1).is_ok());
+                }
+            } else {
+                if !rb.empty() {
+                    assert!(rb.pop().is_ok());
+                }
+            }
+        }
+        assert_eq!(rb.free(), rb.capacity() - rb.size());
+    }
+    #[test]
+    fn grow_across_wrap() {
+        let mut rb : ArrayRingBuffer = ArrayRingBuffer::with_capacity(3);
+        for i in 0..6 {
+            rb.force_push(i);
+        }
+        // Occupied region now wraps around the end of the buffer.
+        rb.grow(7);
+        assert_eq!(rb.capacity(), 7);
+        assert_eq!(rb.size(), 3);
+        for i in 6..10 {
+            assert!(rb.push(i).is_ok());
+        }
+        for i in 3..10 {
+            assert_eq!(rb.pop().unwrap(), i);
+        }
+        assert!(rb.empty());
+    }
+    #[test]
+    fn shrink_to_fit() {
+        let mut rb : ArrayRingBuffer = ArrayRingBuffer::with_capacity(16);
+        for i in 0..14 {
+            assert!(rb.push(i).is_ok());
+        }
+        for i in 0..10 {
+            assert_eq!(rb.pop().unwrap(), i);
+        }
+        rb.shrink_to_fit();
+        assert_eq!(rb.capacity(), 4);
+        assert!(rb.full());
+        for i in 10..14 {
+            assert_eq!(rb.pop().unwrap(), i);
+        }
+        rb.grow(2);
+        assert_eq!(rb.capacity(), 4);
+    }
+    #[test]
+    fn check_size() {
+        let mut rb : ArrayRingBuffer = ArrayRingBuffer::with_capacity(16);
+        for i in 0..11 {
+            rb.force_push(i);
+        }
+        assert_eq!(rb.size(), 11);
+        assert_eq!(rb.free(), rb.capacity() - rb.size());
+    }
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let mut rb : ArrayRingBuffer = ArrayRingBuffer::with_capacity(3);
+        for i in 0..6 {
+            rb.force_push(i);
+        }
+        let json = serde_json::to_string(&rb).unwrap();
+        let mut restored: ArrayRingBuffer = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.capacity(), 3);
+        assert_eq!(restored.size(), 3);
+        assert!(restored.push(6).is_err());
+        for i in 3..6 {
+            assert_eq!(restored.pop().unwrap(), i);
+        }
+        assert!(serde_json::from_str::<ArrayRingBuffer>(r#"{"read":0,"write":0,"buffer":[]}"#).is_err());
+        assert!(serde_json::from_str::<ArrayRingBuffer>(r#"{"read":9,"write":0,"buffer":[0]}"#).is_ok());
+        assert!(serde_json::from_str::<ArrayRingBuffer>(r#"{"read":64,"write":0,"buffer":[0]}"#).is_err());
+    }
+    #[test]
+    fn save_and_load() {
+        let mut rb : ArrayRingBuffer = ArrayRingBuffer::with_capacity(3);
+        for i in 0..6 {
+            rb.force_push(i);
+        }
+        let mut bytes = Vec::new();
+        rb.save_to(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), 8 + 8 * (3 + 4));
+        let mut restored = ArrayRingBuffer::load_from(&mut &bytes[..]).unwrap();
+        assert_eq!(restored.capacity(), 3);
+        for i in 3..6 {
+            assert_eq!(restored.pop().unwrap(), i);
+        }
+        bytes[4] = 2;
+        let err = ArrayRingBuffer::load_from(&mut &bytes[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let truncated = ArrayRingBuffer::load_from(&mut &b"RBUF\x01\0\0\0"[..]).unwrap_err();
+        assert_eq!(truncated.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
+
+/// Proofs for `cargo kani`: whatever the cursors `fold` can produce, the
+/// slots indexed are in the buffer and `size` never reaches the capacity.
+#[cfg(kani)]
+mod verification {
+    use super::*;
+
+    /// Keeps the allocations within what CBMC can model.
+    const MAX_SLOTS: usize = 1 << 20;
+
+    /// Any ring `from_snapshot` accepts: cursors folded below `64 * cap`.
+    fn any_ring() -> ArrayRingBuffer {
+        let cap: usize = kani::any();
+        kani::assume(cap > 0 && cap <= MAX_SLOTS);
+        let limit = 64 * cap as u64;
+        let read: u64 = kani::any();
+        let write: u64 = kani::any();
+        kani::assume(read < limit && write < limit);
+        ArrayRingBuffer { read, write, buffer: vec!(0; cap) }
+    }
+
+    #[kani::proof]
+    fn slots_in_bounds() {
+        let rb = any_ring();
+        assert!((rb.modulo(rb.read) as usize) < rb.buffer.len());
+        assert!((rb.modulo(rb.write) as usize) < rb.buffer.len());
+    }
+
+    #[kani::proof]
+    fn size_below_capacity() {
+        let rb = any_ring();
+        assert!(rb.size() <= rb.capacity());
+        // Cannot underflow, the sentinel slot is always free.
+        assert_eq!(rb.size() + rb.free(), rb.capacity());
+    }
+
+    #[kani::proof]
+    fn fold_keeps_the_slot() {
+        let rb = any_ring();
+        let next = rb.fold(rb.write + 1);
+        assert!(next < 64 * rb.buffer.len() as u64);
+        // `64 * cap` is a multiple of `cap`, so folding never moves a
+        // cursor to another slot.
+        assert_eq!(rb.modulo(next), rb.modulo(rb.write + 1));
+    }
+
+    #[kani::proof]
+    #[kani::unwind(9)]
+    fn relocate_keeps_the_values() {
+        let mut rb = any_ring();
+        // Small enough to unroll the copies, whatever the slots hold.
+        kani::assume(rb.buffer.len() <= 8);
+        for slot in rb.buffer.iter_mut() {
+            *slot = kani::any();
+        }
+        let size = rb.size();
+        let queued: Vec<u64> = (0..size).map(|i| rb.buffer[rb.modulo(rb.read + i as u64) as usize]).collect();
+        let new_cap: usize = kani::any();
+        kani::assume(new_cap > size && new_cap <= 8);
+        rb.relocate(new_cap);
+        assert_eq!(rb.size(), size);
+        assert_eq!(rb.buffer.len(), new_cap);
+        for value in queued {
+            assert_eq!(rb.pop(), Ok(value));
+        }
+        assert!(rb.empty());
+    }
+}
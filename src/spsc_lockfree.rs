@@ -0,0 +1,2162 @@
+//! A lock-free single-producer single-consumer (SPSC) bounded ring buffer.
+//! This implementation uses atomic operations to manage the head and tail indices
+//! of the buffer, ensuring that the producer and consumer can operate concurrently
+//! without the need for locks. The buffer has a fixed capacity, and attempts to
+//! push to a full buffer or pop from an empty buffer will fail gracefully.
+//!
+//! Remember this is SPSC (One producer running in some sort of loop,
+//! and same for the consumer). So the producer and consumer
+//! only need to sync with each other.
+
+use core::cell::{Cell, UnsafeCell};
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem::{self, MaybeUninit};
+use core::ops::Deref;
+use core::ptr;
+use core::slice;
+#[cfg(feature = "std")]
+use core::time::Duration;
+use crate::error::{ChunkError, Disconnected, PopError, PushError};
+use crate::index::RingIndex;
+use crate::sync::{AtomicBool, Arc, Ordering, SlotTracker};
+use crate::wait::{self, Instant, SpinThenYield, WaitStrategy};
+#[cfg(feature = "async")]
+use crate::waker::WakerSlot;
+#[cfg(feature = "metrics")]
+use crate::metrics::LatencyHistogram;
+#[cfg(feature = "stats")]
+use crate::stats::{Stats, StatsSnapshot};
+
+#[cfg(feature = "async")]
+mod future;
+#[cfg(feature = "async")]
+pub use future::{PopFuture, PushFuture};
+#[cfg(feature = "async")]
+mod async_io;
+#[cfg(feature = "std")]
+mod io;
+#[cfg(feature = "std")]
+pub use io::ByteRingBuffer;
+#[cfg(feature = "std")]
+mod ingest;
+#[cfg(feature = "std")]
+pub use ingest::Ingest;
+#[cfg(feature = "std")]
+mod packet;
+#[cfg(feature = "std")]
+pub use packet::{Packet, PacketRingBuffer};
+#[cfg(feature = "bytes")]
+mod buf;
+#[cfg(all(feature = "eventfd", target_os = "linux"))]
+mod eventfd;
+#[cfg(all(feature = "splice", target_os = "linux"))]
+mod splice;
+#[cfg(all(feature = "splice", target_os = "linux"))]
+pub use splice::SplicePipe;
+#[cfg(feature = "tokio")]
+mod notify;
+#[cfg(feature = "tokio")]
+pub use notify::{TokioConsumer, TokioProducer};
+mod audio;
+pub use audio::{AudioConsumer, AudioProducer, AudioRingBuffer, Sample};
+mod message;
+pub use message::{Message, MessageConsumer, MessageProducer, MessageRingBuffer};
+mod storage;
+use storage::Storage;
+pub use storage::{AlignedStorage, StorageOptions};
+#[cfg(all(feature = "huge-pages", target_os = "linux"))]
+pub use storage::HugePages;
+mod watch;
+pub use watch::{watch, WatchReceiver, WatchSender};
+
+/// Aligns (and so pads) the wrapped value to a cache line, so that values
+/// written by different threads never share a line.
+#[derive(Debug, Default)]
+#[repr(align(64))]
+pub(crate) struct CachePadded<T>(T);
+
+impl<T> CachePadded<T> {
+    pub(crate) fn new(value: T) -> Self {
+        CachePadded(value)
+    }
+}
+
+impl<T> Deref for CachePadded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+/// `I` is the width of the shared indices, see `RingIndex`. Only the
+/// default `usize` indices can be split into halves.
+pub struct SPSCRingBuffer<T, I: RingIndex = usize> {
+    /// Slots between `read` and `write` are initialized, all the others are not.
+    buffer: Storage<T>,
+    /// `capacity - 1`, the capacity is always a power of two. The slot of
+    /// the counter `i` is `i & mask`.
+    mask: usize,
+    write: CachePadded<I::Atomic>,
+    read: CachePadded<I::Atomic>,
+    /// Last `read` observed by the producer. Only touched by the producer.
+    cached_read: CachePadded<Cell<usize>>,
+    /// Last `write` observed by the consumer. Only touched by the consumer.
+    cached_write: CachePadded<Cell<usize>>,
+    /// Set when either the `Producer` or the `Consumer` is dropped.
+    disconnected: AtomicBool,
+    /// Created by `new_overwriting`: the producer may evict the oldest value.
+    overwrite: bool,
+    /// Set by the consumer of an overwriting ring from before its CAS on
+    /// `read` until it has moved the value out, so that the producer does
+    /// not write the freed slot while it is still being read.
+    popping: AtomicBool,
+    tracker: SlotTracker,
+    /// Task waiting in `Producer::push_async` for a vacant slot.
+    #[cfg(feature = "async")]
+    producer_waker: WakerSlot,
+    /// Task waiting in `Consumer::pop_async` for a value.
+    #[cfg(feature = "async")]
+    consumer_waker: WakerSlot,
+    /// Raised when the ring goes from empty to non-empty.
+    #[cfg(all(feature = "eventfd", target_os = "linux"))]
+    event: Option<eventfd::EventFd>,
+    /// Latencies of the successful `push`/`force_push` calls.
+    #[cfg(feature = "metrics")]
+    push_latency: LatencyHistogram,
+    /// Latencies of the successful `pop` calls.
+    #[cfg(feature = "metrics")]
+    pop_latency: LatencyHistogram,
+    #[cfg(feature = "stats")]
+    stats: Stats,
+}
+
+unsafe impl<T: Send, I: RingIndex> Sync for SPSCRingBuffer<T, I> {}
+
+/// A ring buffer taken apart by `SPSCRingBuffer::into_raw_parts`.
+/// `read` and `write` are the free-running counters, the slot of the
+/// counter `i` is `i & (capacity - 1)`.
+#[repr(C)]
+#[derive(Debug)]
+pub struct RawParts<T> {
+    pub buffer: *mut T,
+    pub capacity: usize,
+    /// Alignment of `buffer`, see `AlignedStorage`.
+    pub align: usize,
+    pub read: usize,
+    pub write: usize,
+}
+
+impl<T> SPSCRingBuffer<T> {
+    /// Creates a ring buffer with `capacity` rounded up to the next power
+    /// of two, so slots can be found with a mask instead of `%`.
+    /// `read` and `write` are free-running counters: the ring is empty when
+    /// they are equal and full when they are `capacity` apart, so every
+    /// slot can be used.
+    pub fn new(capacity: usize) -> Self {
+        Self::with_index_width(capacity)
+    }
+
+    /// Creates a ring buffer where `force_push` can overwrite the oldest
+    /// value, for lossy streams where the newest data matters most.
+    /// In this mode the consumer advances `read` with a CAS, as the producer
+    /// may advance it too. Such a ring cannot be split: the halves hand out
+    /// borrows of queued values that the producer could evict.
+    pub fn new_overwriting(capacity: usize) -> Self {
+        let mut rb = Self::new(capacity);
+        rb.overwrite = true;
+        rb
+    }
+
+    /// Same as `new`, with the slots allocated as set by `options`.
+    pub fn with_storage(capacity: usize, options: StorageOptions) -> Self {
+        Self::allocate(capacity, &options)
+    }
+
+    /// Creates a ring buffer and splits it into its two owned halves.
+    pub fn new_split(capacity: usize) -> (Producer<T>, Consumer<T>) {
+        Self::new(capacity).split()
+    }
+
+    /// Splits the ring buffer into a `Producer` and a `Consumer` that can be
+    /// moved to different threads. Dropping one half disconnects the other.
+    pub fn split(self) -> (Producer<T>, Consumer<T>) {
+        self.split_with(SpinThenYield::default(), SpinThenYield::default())
+    }
+
+    /// Same as `split`, with the wait strategies used by the blocking
+    /// operations of each half.
+    pub fn split_with<WP, WC>(self, producer_wait: WP, consumer_wait: WC) -> (Producer<T, WP>, Consumer<T, WC>)
+    where
+        WP: WaitStrategy,
+        WC: WaitStrategy,
+    {
+        assert!(!self.overwrite, "an overwriting ring buffer cannot be split");
+        let rb = Arc::new(self);
+        let producer = Producer { rb: rb.clone(), wait: producer_wait, pending: 0, flush_every: 0, not_sync: PhantomData };
+        (producer, Consumer { rb, wait: consumer_wait, not_sync: PhantomData })
+    }
+}
+
+impl<T, I: RingIndex> SPSCRingBuffer<T, I> {
+    /// Same as `new`, with `I` indices: `SPSCRingBuffer::<T, u16>::with_index_width(n)`.
+    /// # Panics
+    /// If the rounded capacity exceeds `I::MAX_CAPACITY`.
+    pub fn with_index_width(capacity: usize) -> Self {
+        Self::allocate(capacity, &StorageOptions::default())
+    }
+
+    fn allocate(capacity: usize, options: &StorageOptions) -> Self {
+        let capacity = capacity.next_power_of_two();
+        assert!(capacity <= I::MAX_CAPACITY, "capacity {capacity} too large for the index type");
+        Self::from_buffer(Storage::new(capacity, options), 0, 0)
+    }
+
+    /// Builds the ring around `buffer`, whose length is the capacity, with
+    /// the slots from the counter `read` to `write` initialized.
+    fn from_buffer(buffer: Storage<T>, read: usize, write: usize) -> Self {
+        let capacity = buffer.len();
+        SPSCRingBuffer {
+            buffer,
+            mask: capacity - 1,
+            write: CachePadded::new(I::new_atomic(write)),
+            read: CachePadded::new(I::new_atomic(read)),
+            cached_read: CachePadded::new(Cell::new(read)),
+            cached_write: CachePadded::new(Cell::new(write)),
+            disconnected: AtomicBool::new(false),
+            overwrite: false,
+            popping: AtomicBool::new(false),
+            tracker: SlotTracker::new(capacity),
+            #[cfg(feature = "async")]
+            producer_waker: WakerSlot::default(),
+            #[cfg(feature = "async")]
+            consumer_waker: WakerSlot::default(),
+            #[cfg(all(feature = "eventfd", target_os = "linux"))]
+            event: None,
+            #[cfg(feature = "metrics")]
+            push_latency: LatencyHistogram::new(),
+            #[cfg(feature = "metrics")]
+            pop_latency: LatencyHistogram::new(),
+            #[cfg(feature = "stats")]
+            stats: Stats::default(),
+        }
+    }
+
+    /// Decomposes the ring into its buffer and counters, e.g. to hand it
+    /// over to a C component. The slots from `read` to `write` (masked by
+    /// `capacity - 1`) hold the queued values, which are leaked unless the
+    /// ring is rebuilt by `from_raw_parts`. The eventfd, if any, is closed.
+    /// # Panics
+    /// If the slots are in a mapping of their own (huge pages, NUMA node).
+    pub fn into_raw_parts(mut self) -> RawParts<T> {
+        // Before the buffer is taken out: `Drop` still needs it then.
+        self.buffer.assert_not_mapped();
+        let empty = Storage::new(0, &StorageOptions::default());
+        let (buffer, capacity, align) = mem::replace(&mut self.buffer, empty).into_raw();
+        let read = I::load(&self.read, Ordering::Acquire);
+        let write = I::load(&self.write, Ordering::Acquire);
+        // The values now belong to the raw parts, `Drop` must see no value.
+        I::store(&self.read, write, Ordering::Relaxed);
+        RawParts { buffer, capacity, align, read, write }
+    }
+
+    /// Rebuilds a ring from the parts returned by `into_raw_parts`, with the
+    /// counters advanced by whoever owned the buffer in the meantime.
+    /// # Safety
+    /// `buffer`, `capacity` and `align` must come from `into_raw_parts` on a
+    /// ring of the same `T`, and only once. The counters must be valid for
+    /// `I`, at most `capacity` apart, and every slot between them initialized.
+    pub unsafe fn from_raw_parts(parts: RawParts<T>) -> Self {
+        let RawParts { buffer, capacity, align, read, write } = parts;
+        debug_assert!(I::distance(read, write) <= capacity, "counters {read} and {write} too far apart");
+        Self::from_buffer(Storage::from_raw(buffer, capacity, align), read, write)
+    }
+
+    /// Pushes a value, returns the slot it was written to. The value is
+    /// handed back in `PushError::Full` if the ring is full.
+    pub fn push(&self, value: T) -> Result<usize, PushError<T>> {
+        #[cfg(feature = "metrics")]
+        let start = Instant::now();
+        let write = I::load(&self.write, Ordering::Relaxed);
+        let slot = write & self.mask;
+
+        // Only go to the shared `read` index when the ring looks full
+        // from the last value we saw.
+        if I::distance(self.cached_read.get(), write) == self.capacity() {
+            self.cached_read.set(I::load(&self.read, Ordering::Acquire));
+            if I::distance(self.cached_read.get(), write) == self.capacity() {
+                self.count_full();
+                return Err(PushError::Full(value));
+            }
+        }
+
+        if self.overwrite {
+            self.wait_for_pop();
+        }
+        // The slot is vacant: never assign through it, the old bytes
+        // are either uninitialized or were already moved out by `pop`.
+        self.tracker.write(slot);
+        unsafe {
+            (*self.buffer[slot].get()).write(value);
+        }
+        I::store(&self.write, I::add(write, 1), Ordering::Release);
+        self.count_pushed(1, I::add(write, 1));
+        self.wake_consumer();
+        self.signal_event(write);
+        #[cfg(feature = "metrics")]
+        self.push_latency.record_since(start);
+        Ok(slot)
+    }
+
+    /// Pushes a value, evicting the oldest one if the ring is full.
+    /// Returns the evicted value. Only for rings created by `new_overwriting`.
+    pub fn force_push(&self, value: T) -> Option<T> {
+        assert!(self.overwrite, "force_push needs a ring created by new_overwriting");
+        #[cfg(feature = "metrics")]
+        let start = Instant::now();
+        let write = I::load(&self.write, Ordering::Relaxed);
+        let slot = write & self.mask;
+        let mut evicted = None;
+        let mut read = I::load(&self.read, Ordering::Acquire);
+        while I::distance(read, write) == self.capacity() {
+            // Full: claim the oldest slot, racing with the consumer.
+            match I::compare_exchange(
+                &self.read,
+                read,
+                I::add(read, 1),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => {
+                    let oldest = read & self.mask;
+                    self.tracker.read(oldest);
+                    evicted = Some(unsafe { (*self.buffer[oldest].get()).assume_init_read() });
+                    break;
+                }
+                // The consumer popped it first, there is room now.
+                Err(current) => read = current,
+            }
+        }
+        self.wait_for_pop();
+        self.tracker.write(slot);
+        unsafe {
+            (*self.buffer[slot].get()).write(value);
+        }
+        I::store(&self.write, I::add(write, 1), Ordering::Release);
+        self.count_pushed(1, I::add(write, 1));
+        self.wake_consumer();
+        self.signal_event(write);
+        #[cfg(feature = "metrics")]
+        self.push_latency.record_since(start);
+        evicted
+    }
+
+    /// Waits for a `pop_overwriting` in progress to move its value out, as
+    /// the slot it freed may be the next one written. That pop published
+    /// `popping` with its CAS on `read`, which the caller loaded.
+    fn wait_for_pop(&self) {
+        while self.popping.load(Ordering::Acquire) {
+            crate::sync::spin_loop();
+        }
+    }
+
+    /// `pop` for overwriting rings: the slot is owned once the CAS on
+    /// `read` succeeds, and only then moved out. `popping` stays set until
+    /// it is, which the producer waits for before writing a slot.
+    fn pop_overwriting(&self) -> Option<(usize, T)> {
+        loop {
+            let read = I::load(&self.read, Ordering::Acquire);
+            if empty(read, I::load(&self.write, Ordering::Acquire)) {
+                self.count_empty();
+                return None;
+            }
+            // Released by the CAS below, so a producer that sees the new
+            // `read` also sees the flag.
+            self.popping.store(true, Ordering::Relaxed);
+            let next_read = I::add(read, 1);
+            if I::compare_exchange(&self.read, read, next_read, Ordering::AcqRel, Ordering::Relaxed).is_ok() {
+                let slot = read & self.mask;
+                self.tracker.read(slot);
+                let value = unsafe { (*self.buffer[slot].get()).assume_init_read() };
+                self.popping.store(false, Ordering::Release);
+                self.count_popped(1);
+                self.wake_producer();
+                return Some((slot, value));
+            }
+            // The producer evicted it first and owns the slot.
+            self.popping.store(false, Ordering::Relaxed);
+        }
+    }
+
+    pub fn pop(&self) -> Option<(usize, T)> {
+        #[cfg(feature = "metrics")]
+        let start = Instant::now();
+        if self.overwrite {
+            let popped = self.pop_overwriting();
+            #[cfg(feature = "metrics")]
+            if popped.is_some() {
+                self.pop_latency.record_since(start);
+            }
+            return popped;
+        }
+        let read = I::load(&self.read, Ordering::Relaxed);
+
+        // Same as in `push`: reload `write` only when the ring looks empty.
+        if empty(read, self.cached_write.get()) {
+            // Pairs with the fence in `signal_event`: either the producer
+            // sees our last `read` store, or we see its `write` store.
+            #[cfg(all(feature = "eventfd", target_os = "linux"))]
+            if self.event.is_some() {
+                crate::sync::fence(Ordering::SeqCst);
+            }
+            self.cached_write.set(I::load(&self.write, Ordering::Acquire));
+            if empty(read, self.cached_write.get()) {
+                self.count_empty();
+                return None;
+            }
+        }
+
+        // The slot was initialized by `push` and is moved out exactly once.
+        let slot = read & self.mask;
+        self.prefetch_ahead(read);
+        self.tracker.read(slot);
+        let value = unsafe { (*self.buffer[slot].get()).assume_init_read() };
+        I::store(&self.read, I::add(read, 1), Ordering::Release);
+        self.count_popped(1);
+        self.wake_producer();
+        #[cfg(feature = "metrics")]
+        self.pop_latency.record_since(start);
+        Some((slot, value))
+    }
+
+    /// With the `stats` feature, returns the push/pop counters and the
+    /// high-water mark, each read with a relaxed load: the snapshot may mix
+    /// counters from slightly different points in time.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> StatsSnapshot {
+        self.stats.snapshot()
+    }
+
+    /// With the `metrics` feature, the latencies of the successful
+    /// `push` and `force_push` calls.
+    #[cfg(feature = "metrics")]
+    pub fn push_latency(&self) -> &LatencyHistogram {
+        &self.push_latency
+    }
+
+    /// With the `metrics` feature, the latencies of the successful `pop`
+    /// calls.
+    #[cfg(feature = "metrics")]
+    pub fn pop_latency(&self) -> &LatencyHistogram {
+        &self.pop_latency
+    }
+
+    pub fn empty(&self) -> bool {
+        I::load(&self.read, Ordering::Relaxed) == I::load(&self.write, Ordering::Relaxed)
+    }
+
+    /// Same as `empty`, named like the std collections.
+    pub fn is_empty(&self) -> bool {
+        self.empty()
+    }
+
+    /// Returns the maximum number of values the ring buffer can hold.
+    pub fn capacity(&self) -> usize {
+        self.mask + 1
+    }
+
+    /// Returns the number of queued values.
+    /// The result is a snapshot: when called concurrently with `push` or
+    /// `pop` it may already be stale. The producer can only see the length
+    /// shrink and the consumer can only see it grow, so it is an upper bound
+    /// for the producer and a lower bound for the consumer.
+    pub fn len(&self) -> usize {
+        let read = I::load(&self.read, Ordering::Acquire);
+        let write = I::load(&self.write, Ordering::Acquire);
+        I::distance(read, write)
+    }
+
+    /// Returns true if no more values can be pushed, same consistency
+    /// caveats as `len`.
+    pub fn is_full(&self) -> bool {
+        self.len() == self.capacity()
+    }
+
+    /// Wakes the task waiting for a value, if any. Call after publishing `write`.
+    fn wake_consumer(&self) {
+        #[cfg(feature = "async")]
+        self.consumer_waker.wake();
+    }
+
+    /// Wakes the task waiting for a vacant slot, if any. Call after
+    /// publishing `read`.
+    fn wake_producer(&self) {
+        #[cfg(feature = "async")]
+        self.producer_waker.wake();
+    }
+
+    /// Counts `_n` values published, up to the counter `_write`. Call after
+    /// publishing `write`.
+    #[inline(always)]
+    fn count_pushed(&self, _n: usize, _write: usize) {
+        #[cfg(feature = "stats")]
+        self.stats.pushed(_n, I::distance(I::load(&self.read, Ordering::Relaxed), _write));
+    }
+
+    /// Counts `_n` values consumed.
+    #[inline(always)]
+    fn count_popped(&self, _n: usize) {
+        #[cfg(feature = "stats")]
+        self.stats.popped(_n);
+    }
+
+    #[inline(always)]
+    fn count_full(&self) {
+        #[cfg(feature = "stats")]
+        self.stats.full();
+    }
+
+    #[inline(always)]
+    fn count_empty(&self) {
+        #[cfg(feature = "stats")]
+        self.stats.empty();
+    }
+
+    /// Raises the eventfd, if any, when the values published from
+    /// `_old_write` on are the only ones in the ring, i.e. the ring was
+    /// empty before. Call after publishing `write`.
+    fn signal_event(&self, _old_write: usize) {
+        #[cfg(all(feature = "eventfd", target_os = "linux"))]
+        if let Some(event) = &self.event {
+            crate::sync::fence(Ordering::SeqCst);
+            if I::load(&self.read, Ordering::Relaxed) == _old_write {
+                event.signal();
+            }
+        }
+    }
+
+    /// With the `prefetch` feature, prefetches the value one cache line
+    /// after the counter `_read`, if the consumer already saw it published.
+    /// Unpublished slots are left alone, pulling them in would only steal
+    /// the line from the producer.
+    #[inline(always)]
+    fn prefetch_ahead(&self, _read: usize) {
+        #[cfg(feature = "prefetch")]
+        {
+            let ahead = (storage::CACHE_LINE / mem::size_of::<T>().max(1)).max(1);
+            if I::distance(_read, self.cached_write.get()) > ahead {
+                prefetch(self.buffer[I::add(_read, ahead) & self.mask].get() as *const u8);
+            }
+        }
+    }
+}
+
+impl<T> SPSCRingBuffer<T> {
+    /// Number of slots the producer can fill without overrunning the
+    /// consumer. Only called by the producer, refreshes `cached_read`.
+    fn vacant(&self) -> usize {
+        let write = self.write.load(Ordering::Relaxed);
+        self.cached_read.set(self.read.load(Ordering::Acquire));
+        self.capacity() - write.wrapping_sub(self.cached_read.get())
+    }
+
+    /// Number of values the consumer can read. Only called by the consumer,
+    /// refreshes `cached_write`.
+    fn occupied(&self) -> usize {
+        let read = self.read.load(Ordering::Relaxed);
+        self.cached_write.set(self.write.load(Ordering::Acquire));
+        self.cached_write.get().wrapping_sub(read)
+    }
+
+    /// Returns the `len` slots starting at the counter `start` as two slices,
+    /// the second one is non-empty when the range wraps around the end of
+    /// the buffer.
+    /// # Safety
+    /// The caller must own all the slots in the range (no other reference
+    /// to them may be alive while the slices are).
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn slots(&self, start: usize, len: usize) -> (&mut [MaybeUninit<T>], &mut [MaybeUninit<T>]) {
+        let start = start & self.mask;
+        for i in 0..len {
+            self.tracker.write((start + i) & self.mask);
+        }
+        let first = len.min(self.buffer.len() - start);
+        // `UnsafeCell` is `repr(transparent)`, the buffer can be viewed as a
+        // plain `[MaybeUninit<T>]`.
+        let base = UnsafeCell::raw_get(self.buffer.as_ptr());
+        (
+            slice::from_raw_parts_mut(base.add(start), first),
+            slice::from_raw_parts_mut(base, len - first),
+        )
+    }
+
+    /// `slots` as raw pointers and lengths, for the regions handed to a
+    /// device: no reference to the slots is created, even briefly.
+    fn regions(&self, start: usize, len: usize) -> [(*mut MaybeUninit<T>, usize); 2] {
+        let start = start & self.mask;
+        let first = len.min(self.buffer.len() - start);
+        let base = UnsafeCell::raw_get(self.buffer.as_ptr());
+        // `start` is below the length of the buffer.
+        [(unsafe { base.add(start) }, first), (base, len - first)]
+    }
+}
+
+/// The pushing half of a split `SPSCRingBuffer`.
+/// `W` is the strategy used to wait for a vacant slot in blocking pushes.
+pub struct Producer<T, W = SpinThenYield> {
+    rb: Arc<SPSCRingBuffer<T>>,
+    wait: W,
+    /// Values written by `push_batched` after `write`, not published yet.
+    pending: usize,
+    /// `push_batched` flushes once this many values are pending, 0 means
+    /// only on `flush`.
+    flush_every: usize,
+    /// Each half is the only user of its cache of the other counter, a
+    /// `Cell` in the ring, and also reaches it through `&self`: it must not
+    /// be shared between threads, only sent.
+    not_sync: PhantomData<Cell<()>>,
+}
+
+impl<T, W: WaitStrategy> Producer<T, W> {
+    /// Pushes a value, returns the slot it was written to.
+    /// Fails with `Disconnected` once the `Consumer` is dropped.
+    /// Values pending from `push_batched` are flushed first.
+    pub fn push(&mut self, value: T) -> Result<usize, PushError<T>> {
+        if self.is_disconnected() {
+            return Err(PushError::Disconnected(value));
+        }
+        self.flush();
+        self.rb.push(value)
+    }
+
+    /// Pushes a value, waiting for a vacant slot if the ring is full.
+    /// Fails only with `Disconnected`.
+    pub fn push_blocking(&mut self, value: T) -> Result<usize, PushError<T>> {
+        self.push_until(value, None)
+    }
+
+    /// Pushes a value, waiting up to `timeout` for a vacant slot.
+    /// On timeout the value is handed back in `PushError::Full`.
+    #[cfg(feature = "std")]
+    pub fn push_timeout(&mut self, value: T, timeout: Duration) -> Result<usize, PushError<T>> {
+        self.push_until(value, Some(Instant::now() + timeout))
+    }
+
+    fn push_until(&mut self, value: T, deadline: Option<Instant>) -> Result<usize, PushError<T>> {
+        let mut step = 0;
+        self.flush();
+        // Only this producer fills slots, so once one is vacant it stays so.
+        while self.rb.vacant() == 0 {
+            if self.is_disconnected() {
+                return Err(PushError::Disconnected(value));
+            }
+            if wait::expired(deadline) {
+                return Err(PushError::Full(value));
+            }
+            self.wait.wait(step, deadline);
+            step = step.saturating_add(1);
+        }
+        self.push(value)
+    }
+
+    /// Reserves exactly `n` vacant slots for in-place writes.
+    /// The slots are handed out as up to two slices (the second one is used
+    /// when the reservation wraps around); nothing is visible to the consumer
+    /// until `WriteChunk::commit`.
+    pub fn write_chunk(&mut self, n: usize) -> Result<WriteChunk<'_, T>, ChunkError> {
+        if self.is_disconnected() {
+            return Err(ChunkError::Disconnected);
+        }
+        self.flush();
+        let vacant = self.rb.vacant();
+        if vacant < n {
+            self.rb.count_full();
+            return Err(ChunkError::TooFewSlots(vacant));
+        }
+        let start = self.rb.write.load(Ordering::Relaxed);
+        // The vacant slots belong to the producer, and `&mut self` keeps
+        // any other write from happening while the chunk is alive.
+        let (first, second) = unsafe { self.rb.slots(start, n) };
+        Ok(WriteChunk { rb: &self.rb, start, first, second })
+    }
+
+    /// Returns true if the `Consumer` was dropped.
+    pub fn is_disconnected(&self) -> bool {
+        self.rb.disconnected.load(Ordering::Acquire)
+    }
+
+    /// See `SPSCRingBuffer::capacity`.
+    pub fn capacity(&self) -> usize {
+        self.rb.capacity()
+    }
+
+    /// See `SPSCRingBuffer::len`.
+    pub fn len(&self) -> usize {
+        self.rb.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rb.empty()
+    }
+
+    /// See `SPSCRingBuffer::is_full`.
+    pub fn is_full(&self) -> bool {
+        self.rb.is_full()
+    }
+
+    /// See `SPSCRingBuffer::stats`.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> StatsSnapshot {
+        self.rb.stats()
+    }
+
+    /// See `SPSCRingBuffer::push_latency`.
+    #[cfg(feature = "metrics")]
+    pub fn push_latency(&self) -> &LatencyHistogram {
+        self.rb.push_latency()
+    }
+
+    /// See `SPSCRingBuffer::pop_latency`.
+    #[cfg(feature = "metrics")]
+    pub fn pop_latency(&self) -> &LatencyHistogram {
+        self.rb.pop_latency()
+    }
+}
+
+impl<T: Copy, W: WaitStrategy> Producer<T, W> {
+    /// Pushes as many values from `values` as there are vacant slots, with
+    /// at most two `memcpy`s and a single publish of the write index.
+    /// Returns how many values were pushed.
+    pub fn push_slice(&mut self, values: &[T]) -> Result<usize, Disconnected> {
+        self.flush();
+        let n = values.len().min(self.rb.vacant());
+        // There are `n` vacant slots, so it can only fail with `Disconnected`.
+        let Ok(mut chunk) = self.write_chunk(n) else {
+            return Err(Disconnected);
+        };
+        let (first, second) = chunk.as_mut_slices();
+        unsafe {
+            ptr::copy_nonoverlapping(values.as_ptr(), first.as_mut_ptr() as *mut T, first.len());
+            ptr::copy_nonoverlapping(values[first.len()..].as_ptr(), second.as_mut_ptr() as *mut T, second.len());
+            chunk.commit(n);
+        }
+        Ok(n)
+    }
+
+    /// Returns the vacant slots as up to two raw regions (pointer, length),
+    /// the second one is non-empty when they wrap around, so that a DMA
+    /// engine or a kernel bypass NIC can fill the ring in place. Nothing is
+    /// visible to the consumer until `advance_write`. Any other push in
+    /// between invalidates the regions.
+    pub fn vacant_regions(&mut self) -> [(*mut T, usize); 2] {
+        self.flush();
+        let start = self.rb.write.load(Ordering::Relaxed);
+        // Writing through the pointers is up to the caller.
+        let [(first, first_len), (second, second_len)] = self.rb.regions(start, self.rb.vacant());
+        [(first as *mut T, first_len), (second as *mut T, second_len)]
+    }
+
+    /// Publishes the first `n` slots of `vacant_regions`, in order, after
+    /// the values pending from `push_batched`.
+    /// # Safety
+    /// Those slots must have been fully written, and the device must be
+    /// done with them.
+    /// # Panics
+    /// If there are fewer than `n` vacant slots.
+    pub unsafe fn advance_write(&mut self, n: usize) {
+        self.flush();
+        let vacant = self.rb.vacant();
+        assert!(n <= vacant, "cannot advance by {n} slots, only {vacant} vacant");
+        let start = self.rb.write.load(Ordering::Relaxed);
+        self.rb.write.store(start.wrapping_add(n), Ordering::Release);
+        self.rb.count_pushed(n, start.wrapping_add(n));
+        self.rb.wake_consumer();
+        self.rb.signal_event(start);
+    }
+}
+
+/// Local batching: `push_batched` writes values without publishing them,
+/// and `flush` makes all of them visible to the consumer with a single
+/// Release store of the write index, for high-rate streams where that
+/// store (and the cache line it bounces) dominates.
+impl<T, W> Producer<T, W> {
+    /// Writes a value into the next vacant slot, without publishing it.
+    /// It is published by `flush`, by any other push, when the ring is full,
+    /// or automatically once `flush_every` values are pending.
+    /// Returns the slot the value was written to.
+    pub fn push_batched(&mut self, value: T) -> Result<usize, PushError<T>> {
+        if self.rb.disconnected.load(Ordering::Acquire) {
+            return Err(PushError::Disconnected(value));
+        }
+        let rb = &self.rb;
+        let write = rb.write.load(Ordering::Relaxed).wrapping_add(self.pending);
+        let slot = write & rb.mask;
+        if write.wrapping_sub(rb.cached_read.get()) == rb.capacity() {
+            rb.cached_read.set(rb.read.load(Ordering::Acquire));
+            if write.wrapping_sub(rb.cached_read.get()) == rb.capacity() {
+                // The consumer may be waiting for the pending values.
+                rb.count_full();
+                self.flush();
+                return Err(PushError::Full(value));
+            }
+        }
+        rb.tracker.write(slot);
+        unsafe {
+            (*rb.buffer[slot].get()).write(value);
+        }
+        self.pending += 1;
+        if self.pending == self.flush_every {
+            self.flush();
+        }
+        Ok(slot)
+    }
+
+    /// Publishes the values written by `push_batched`.
+    pub fn flush(&mut self) {
+        if self.pending == 0 {
+            return;
+        }
+        let write = self.rb.write.load(Ordering::Relaxed);
+        self.rb.write.store(write.wrapping_add(self.pending), Ordering::Release);
+        self.rb.count_pushed(self.pending, write.wrapping_add(self.pending));
+        self.pending = 0;
+        self.rb.wake_consumer();
+        self.rb.signal_event(write);
+    }
+
+    /// Number of values written by `push_batched` and not published yet.
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+
+    /// Makes `push_batched` flush every `n` values, 0 (the default) leaves
+    /// it to `flush`.
+    pub fn set_flush_every(&mut self, n: usize) {
+        self.flush_every = n;
+    }
+}
+
+impl<T, W> Drop for Producer<T, W> {
+    fn drop(&mut self) {
+        self.flush();
+        self.rb.disconnected.store(true, Ordering::Release);
+        self.rb.wake_consumer();
+        #[cfg(all(feature = "eventfd", target_os = "linux"))]
+        if let Some(event) = &self.rb.event {
+            event.signal();
+        }
+    }
+}
+
+impl<T: PartialEq + Clone, W> Producer<T, W> {
+    /// Turns this producer into one that drops a value equal to the one
+    /// pushed just before it, for state-change streams that would flood
+    /// the consumer with duplicates otherwise.
+    pub fn dedup(self) -> DedupProducer<T, W> {
+        DedupProducer { producer: self, last: None, duplicates: 0 }
+    }
+}
+
+/// A `Producer` dropping consecutive equal values, made by
+/// `Producer::dedup`.
+pub struct DedupProducer<T, W = SpinThenYield> {
+    producer: Producer<T, W>,
+    /// Copy of the last value pushed, as the consumer may have taken it.
+    last: Option<T>,
+    duplicates: usize,
+}
+
+impl<T: PartialEq + Clone, W: WaitStrategy> DedupProducer<T, W> {
+    /// Pushes a value unless it equals the last value pushed. Returns the
+    /// slot it was written to, or `None` if it was dropped as a duplicate.
+    /// A value rejected with an error does not count as pushed.
+    pub fn push(&mut self, value: T) -> Result<Option<usize>, PushError<T>> {
+        if self.last.as_ref() == Some(&value) {
+            self.duplicates += 1;
+            return Ok(None);
+        }
+        let copy = value.clone();
+        let slot = self.producer.push(value)?;
+        self.last = Some(copy);
+        Ok(Some(slot))
+    }
+
+    /// Returns the number of values dropped as duplicates.
+    pub fn duplicates(&self) -> usize {
+        self.duplicates
+    }
+
+    pub fn producer(&self) -> &Producer<T, W> {
+        &self.producer
+    }
+}
+
+/// Vacant slots reserved by `Producer::write_chunk`.
+pub struct WriteChunk<'a, T> {
+    rb: &'a SPSCRingBuffer<T>,
+    start: usize,
+    /// Raw, as `commit` publishes the slots to the consumer while the chunk
+    /// is still alive, which a reference to them must never be.
+    first: *mut [MaybeUninit<T>],
+    second: *mut [MaybeUninit<T>],
+}
+
+// Same as `&mut [T]`.
+unsafe impl<T: Send> Send for WriteChunk<'_, T> {}
+unsafe impl<T: Sync> Sync for WriteChunk<'_, T> {}
+
+impl<T> WriteChunk<'_, T> {
+    /// Returns the reserved slots, in FIFO order.
+    pub fn as_mut_slices(&mut self) -> (&mut [MaybeUninit<T>], &mut [MaybeUninit<T>]) {
+        unsafe { (&mut *self.first, &mut *self.second) }
+    }
+
+    /// Number of reserved slots.
+    pub fn len(&self) -> usize {
+        self.first.len() + self.second.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Makes the first `count` reserved slots visible to the consumer.
+    /// Slots past `count` are given back, and are not dropped.
+    /// # Safety
+    /// The first `count` slots (`first` then `second`) must be initialized.
+    pub unsafe fn commit(self, count: usize) {
+        assert!(count <= self.len(), "cannot commit {count} slots out of {}", self.len());
+        self.rb
+            .write
+            .store(self.start.wrapping_add(count), Ordering::Release);
+        self.rb.count_pushed(count, self.start.wrapping_add(count));
+        self.rb.wake_consumer();
+        self.rb.signal_event(self.start);
+    }
+
+    /// Moves values from `iter` into the reserved slots, in order, until
+    /// either runs out, then commits them. Returns how many were committed.
+    /// If `iter` panics, the values already moved in are dropped and none
+    /// is committed.
+    pub fn fill_from_iter<I: IntoIterator<Item = T>>(mut self, iter: I) -> usize {
+        let len = self.len();
+        let mut iter = iter.into_iter();
+        let mut written = DropWritten { chunk: &mut self, count: 0 };
+        while written.count < len {
+            let Some(value) = iter.next() else { break };
+            let i = written.count;
+            let (first, second) = written.chunk.as_mut_slices();
+            let slot = if i < first.len() { &mut first[i] } else { &mut second[i - first.len()] };
+            slot.write(value);
+            written.count += 1;
+        }
+        let count = written.count;
+        mem::forget(written);
+        // The first `count` slots were just initialized.
+        unsafe { self.commit(count) };
+        count
+    }
+}
+
+/// Drops the values written so far by `WriteChunk::fill_from_iter` when
+/// the iterator panics.
+struct DropWritten<'c, 'a, T> {
+    chunk: &'c mut WriteChunk<'a, T>,
+    count: usize,
+}
+
+impl<T> Drop for DropWritten<'_, '_, T> {
+    fn drop(&mut self) {
+        let (first, second) = self.chunk.as_mut_slices();
+        for slot in first.iter_mut().chain(second.iter_mut()).take(self.count) {
+            unsafe { slot.assume_init_drop() };
+        }
+    }
+}
+
+/// The oldest value of a ring, claimed by `Consumer::claim`.
+pub struct Claim<'a, T, W> {
+    consumer: &'a mut Consumer<T, W>,
+}
+
+impl<T, W: WaitStrategy> Claim<'_, T, W> {
+    /// Pops the claimed value: it was processed.
+    pub fn ack(self) -> T {
+        match self.consumer.pop() {
+            Ok(value) => value,
+            // Only this consumer pops, the claimed value is still there.
+            Err(_) => unreachable!("a claimed value is in the ring"),
+        }
+    }
+
+    /// Leaves the claimed value in the ring, the next `claim` or `pop`
+    /// returns it again. Same as dropping the claim.
+    pub fn nack(self) {}
+}
+
+impl<T, W: WaitStrategy> Deref for Claim<'_, T, W> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        match self.consumer.peek() {
+            Some(value) => value,
+            None => unreachable!("a claimed value is in the ring"),
+        }
+    }
+}
+
+/// Iterator returned by `Consumer::pop_iter`. It ends the first time the
+/// ring is found empty, calling `next` again may yield values pushed since.
+pub struct PopIter<'a, T, W> {
+    consumer: &'a mut Consumer<T, W>,
+}
+
+impl<T, W> Iterator for PopIter<'_, T, W> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.consumer.rb.pop().map(|(_, value)| value)
+    }
+
+    /// The values known to be readable without reloading the write index.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let rb = &self.consumer.rb;
+        let read = rb.read.load(Ordering::Relaxed);
+        (rb.cached_write.get().wrapping_sub(read), None)
+    }
+}
+
+/// The popping half of a split `SPSCRingBuffer`.
+/// `W` is the strategy used to wait for a value in blocking pops.
+///
+/// It can be sent to another thread but not shared with one, as `peek`
+/// lends values through `&self`:
+/// ```compile_fail
+/// fn shared<T: Sync>() {}
+/// shared::<ringbuf::spsc_lockfree::Consumer<std::cell::Cell<u8>>>();
+/// ```
+pub struct Consumer<T, W = SpinThenYield> {
+    rb: Arc<SPSCRingBuffer<T>>,
+    wait: W,
+    /// See `Producer`.
+    not_sync: PhantomData<Cell<()>>,
+}
+
+impl<T, W: WaitStrategy> Consumer<T, W> {
+    /// Pops the oldest value. Values pushed before the `Producer` was dropped
+    /// are still delivered, `Disconnected` is only returned once the ring is
+    /// drained.
+    pub fn pop(&mut self) -> Result<T, PopError> {
+        if let Some((_, value)) = self.rb.pop() {
+            return Ok(value);
+        }
+        if !self.is_disconnected() {
+            return Err(PopError::Empty);
+        }
+        // The producer may have pushed right before dropping, the Acquire
+        // load of the flag makes that push visible to this second attempt.
+        match self.rb.pop() {
+            Some((_, value)) => Ok(value),
+            None => Err(PopError::Disconnected),
+        }
+    }
+
+    /// Returns the oldest value without popping it.
+    /// The producer never writes to an occupied slot, and the only consumer
+    /// cannot pop while the reference is borrowed, so it stays valid.
+    pub fn peek(&self) -> Option<&T> {
+        if self.rb.occupied() == 0 {
+            return None;
+        }
+        let slot = self.rb.read.load(Ordering::Relaxed) & self.rb.mask;
+        self.rb.tracker.read(slot);
+        Some(unsafe { (*self.rb.buffer[slot].get()).assume_init_ref() })
+    }
+
+    /// Claims the oldest value for two-phase consumption: it stays in the
+    /// ring while it is processed, and is only popped by `Claim::ack`.
+    /// Dropping the claim, or a panic while processing, leaves it to be
+    /// delivered again.
+    pub fn claim(&mut self) -> Option<Claim<'_, T, W>> {
+        self.peek()?;
+        Some(Claim { consumer: self })
+    }
+
+    /// Pops the oldest value, waiting for one if the ring is empty.
+    /// Fails only with `Disconnected`.
+    pub fn pop_blocking(&mut self) -> Result<T, PopError> {
+        self.pop_until(None)
+    }
+
+    /// Pops the oldest value, waiting up to `timeout` for one to be pushed.
+    /// Returns `PopError::Empty` on timeout.
+    #[cfg(feature = "std")]
+    pub fn pop_timeout(&mut self, timeout: Duration) -> Result<T, PopError> {
+        self.pop_until(Some(Instant::now() + timeout))
+    }
+
+    fn pop_until(&mut self, deadline: Option<Instant>) -> Result<T, PopError> {
+        let mut step = 0;
+        loop {
+            match self.pop() {
+                Err(PopError::Empty) if !wait::expired(deadline) => {
+                    self.wait.wait(step, deadline);
+                    step = step.saturating_add(1);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Returns all the readable values as up to two slices (the second one
+    /// is used when they wrap around), without moving them out of the ring.
+    /// `ReadChunk::commit` then releases the slots that were processed.
+    /// Fails with `Disconnected` only once the ring is drained.
+    pub fn read_chunk(&mut self) -> Result<ReadChunk<'_, T>, Disconnected> {
+        let mut n = self.rb.occupied();
+        if n == 0 && self.is_disconnected() {
+            // See `pop` for why the ring is checked again.
+            n = self.rb.occupied();
+            if n == 0 {
+                return Err(Disconnected);
+            }
+        }
+        let start = self.rb.read.load(Ordering::Relaxed);
+        self.rb.prefetch_ahead(start);
+        // The occupied slots are initialized and only the consumer, borrowed
+        // mutably by the chunk, may release them.
+        let (first, second) = unsafe { self.rb.slots(start, n) };
+        Ok(ReadChunk { rb: &self.rb, start, first, second })
+    }
+
+    /// Returns an iterator popping values until the ring is observed empty:
+    /// `for value in rx.pop_iter()`. The write index is only reloaded once
+    /// the values seen on the last load are exhausted.
+    pub fn pop_iter(&mut self) -> PopIter<'_, T, W> {
+        PopIter { consumer: self }
+    }
+
+    /// Returns true if the `Producer` was dropped.
+    pub fn is_disconnected(&self) -> bool {
+        self.rb.disconnected.load(Ordering::Acquire)
+    }
+
+    /// See `SPSCRingBuffer::capacity`.
+    pub fn capacity(&self) -> usize {
+        self.rb.capacity()
+    }
+
+    /// See `SPSCRingBuffer::len`.
+    pub fn len(&self) -> usize {
+        self.rb.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rb.empty()
+    }
+
+    /// See `SPSCRingBuffer::is_full`.
+    pub fn is_full(&self) -> bool {
+        self.rb.is_full()
+    }
+
+    /// See `SPSCRingBuffer::stats`.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> StatsSnapshot {
+        self.rb.stats()
+    }
+
+    /// See `SPSCRingBuffer::push_latency`.
+    #[cfg(feature = "metrics")]
+    pub fn push_latency(&self) -> &LatencyHistogram {
+        self.rb.push_latency()
+    }
+
+    /// See `SPSCRingBuffer::pop_latency`.
+    #[cfg(feature = "metrics")]
+    pub fn pop_latency(&self) -> &LatencyHistogram {
+        self.rb.pop_latency()
+    }
+}
+
+impl<T: Copy, W: WaitStrategy> Consumer<T, W> {
+    /// Pops as many values as fit in `out`, with at most two `memcpy`s and
+    /// a single publish of the read index. Returns how many values were
+    /// popped.
+    pub fn pop_slice(&mut self, out: &mut [T]) -> Result<usize, Disconnected> {
+        let chunk = self.read_chunk()?;
+        let (first, second) = chunk.as_slices();
+        let n = out.len().min(chunk.len());
+        let from_first = n.min(first.len());
+        out[..from_first].copy_from_slice(&first[..from_first]);
+        out[from_first..n].copy_from_slice(&second[..n - from_first]);
+        chunk.commit(n);
+        Ok(n)
+    }
+
+    /// Returns the readable values as up to two raw regions (pointer,
+    /// length), in FIFO order, so that a DMA engine can copy them out in
+    /// place. The slots stay occupied until `advance_read`.
+    pub fn occupied_regions(&self) -> [(*const T, usize); 2] {
+        let start = self.rb.read.load(Ordering::Relaxed);
+        // The producer never writes to occupied slots. `occupied` refreshes
+        // the consumer's cache of `write` through `&self`, which is fine as
+        // the consumer is not `Sync`.
+        let [(first, first_len), (second, second_len)] = self.rb.regions(start, self.rb.occupied());
+        [(first as *const T, first_len), (second as *const T, second_len)]
+    }
+
+    /// Hands the first `n` values of `occupied_regions` back to the
+    /// producer. The values are `Copy`, so nothing needs to be dropped, but
+    /// the device must be done reading them.
+    /// # Panics
+    /// If fewer than `n` values are readable.
+    pub fn advance_read(&mut self, n: usize) {
+        let occupied = self.rb.occupied();
+        assert!(n <= occupied, "cannot advance by {n} values, only {occupied} readable");
+        let start = self.rb.read.load(Ordering::Relaxed);
+        self.rb.read.store(start.wrapping_add(n), Ordering::Release);
+        self.rb.count_popped(n);
+        self.rb.wake_producer();
+    }
+}
+
+/// Readable values borrowed by `Consumer::read_chunk`.
+pub struct ReadChunk<'a, T> {
+    rb: &'a SPSCRingBuffer<T>,
+    start: usize,
+    /// All initialized. Raw for the same reason as in `WriteChunk`.
+    first: *mut [MaybeUninit<T>],
+    second: *mut [MaybeUninit<T>],
+}
+
+// Same as `&mut [T]`: the values can only be reached through the chunk.
+unsafe impl<T: Send> Send for ReadChunk<'_, T> {}
+unsafe impl<T: Sync> Sync for ReadChunk<'_, T> {}
+
+impl<T> ReadChunk<'_, T> {
+    /// Returns the readable values, in FIFO order.
+    pub fn as_slices(&self) -> (&[T], &[T]) {
+        unsafe { (&*(self.first as *const [T]), &*(self.second as *const [T])) }
+    }
+
+    /// Number of readable values.
+    pub fn len(&self) -> usize {
+        self.first.len() + self.second.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drops the first `count` values and hands their slots back to the
+    /// producer. The other values stay queued.
+    /// If a destructor panics, that value counts as dropped and the values
+    /// after it stay queued.
+    pub fn commit(self, count: usize) {
+        assert!(count <= self.len(), "cannot commit {count} values out of {}", self.len());
+        let ReadChunk { rb, start, first, second } = self;
+        let mut release = ReleaseSlots { rb, read: start };
+        let (first, second) = unsafe { (&mut *first, &mut *second) };
+        for (i, slot) in first.iter_mut().chain(second.iter_mut()).take(count).enumerate() {
+            // Moved past the value before its destructor runs, so a panic
+            // in it can never lead to a second drop.
+            release.read = start.wrapping_add(i + 1);
+            unsafe { slot.assume_init_drop() };
+        }
+    }
+}
+
+/// Hands the slots before `read` back to the producer when dropped, also
+/// while unwinding from a panicking destructor in `ReadChunk::commit`.
+struct ReleaseSlots<'a, T> {
+    rb: &'a SPSCRingBuffer<T>,
+    read: usize,
+}
+
+impl<T> Drop for ReleaseSlots<'_, T> {
+    fn drop(&mut self) {
+        let released = self.read.wrapping_sub(self.rb.read.load(Ordering::Relaxed));
+        self.rb.read.store(self.read, Ordering::Release);
+        self.rb.count_popped(released);
+        self.rb.wake_producer();
+    }
+}
+
+impl<T, W> Drop for Consumer<T, W> {
+    fn drop(&mut self) {
+        self.rb.disconnected.store(true, Ordering::Release);
+        self.rb.wake_producer();
+    }
+}
+
+impl<T, I: RingIndex> Drop for SPSCRingBuffer<T, I> {
+    /// Runs the destructors of the values still queued, vacant slots are skipped.
+    fn drop(&mut self) {
+        let mut read = I::load(&self.read, Ordering::Relaxed);
+        let write = I::load(&self.write, Ordering::Relaxed);
+        while !empty(read, write) {
+            unsafe { self.buffer[read & self.mask].get_mut().assume_init_drop() };
+            read = I::add(read, 1);
+        }
+    }
+}
+
+/// The counters and capacity; the values may be under a concurrent push
+/// or pop, so they are not shown.
+impl<T, I: RingIndex> fmt::Debug for SPSCRingBuffer<T, I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SPSCRingBuffer")
+            .field("read", &I::load(&self.read, Ordering::Relaxed))
+            .field("write", &I::load(&self.write, Ordering::Relaxed))
+            .field("capacity", &self.capacity())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Software prefetch of the cache line holding `ptr` into L1, a no-op on
+/// other architectures.
+#[cfg(feature = "prefetch")]
+#[inline(always)]
+fn prefetch(_ptr: *const u8) {
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        use core::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+        _mm_prefetch::<_MM_HINT_T0>(_ptr as *const i8);
+    }
+    #[cfg(target_arch = "aarch64")]
+    unsafe {
+        core::arch::asm!("prfm pldl1keep, [{}]", in(reg) _ptr, options(nostack, readonly, preserves_flags));
+    }
+}
+
+pub fn empty(read_idx: usize, write_idx: usize) -> bool {
+    read_idx == write_idx
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use rand::Rng;
+    use crate::sync::AtomicUsize;
+    use std::sync::atomic::AtomicIsize;
+
+    #[test]
+    fn test_false_sharing() {
+        let f: SPSCRingBuffer<u64> = SPSCRingBuffer::<u64>::new(4);
+        let addrs = [
+            &*f.read as *const AtomicUsize as usize,
+            &*f.write as *const AtomicUsize as usize,
+            &*f.cached_read as *const Cell<usize> as usize,
+            &*f.cached_write as *const Cell<usize> as usize,
+        ];
+        for (i, addr1) in addrs.iter().enumerate() {
+            for addr2 in &addrs[i + 1..] {
+                if addr1 / 64 == addr2 / 64 {
+                    panic!("false sharing at {:x} and {:x}", addr1, addr2);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_spsc_ring_buffer() {
+        // Rounded up to 4 slots, all of them usable.
+        let buffer: SPSCRingBuffer<u64> = SPSCRingBuffer::<u64>::new(3);
+
+        assert!(buffer.push(1).is_ok());
+        assert!(buffer.push(2).is_ok());
+        assert!(buffer.push(3).is_ok());
+        assert!(buffer.push(4).is_ok());
+        assert!(buffer.push(5).is_err()); // Buffer should be full
+
+        assert_eq!(buffer.pop(), Some((0, 1)));
+        assert_eq!(buffer.pop(), Some((1, 2)));
+        assert_eq!(buffer.pop(), Some((2, 3)));
+        assert_eq!(buffer.pop(), Some((3, 4)));
+        assert_eq!(buffer.pop(), None); // Buffer should be empty
+    }
+    #[test]
+    fn push_and_pop() {
+        let rb = SPSCRingBuffer::new(8);
+        for i in 0..8u64 {
+            assert!(rb.push(i).is_ok());
+        }
+        assert_eq!(rb.read.load(Ordering::SeqCst), 0);
+        assert_eq!(rb.write.load(Ordering::SeqCst), 8);
+        assert!(rb.push(0).is_err());
+        for _ in 0..8u64 {
+            assert!(rb.pop().is_some());
+        }
+        assert!(rb.pop().is_none());
+    }
+    #[test]
+    fn non_zeroable_types() {
+        let rb: SPSCRingBuffer<String> = SPSCRingBuffer::new(4);
+        assert!(rb.push("hello".to_string()).is_ok());
+        assert!(rb.push("world".to_string()).is_ok());
+        assert_eq!(rb.pop().unwrap().1, "hello");
+        assert!(rb.push("again".to_string()).is_ok());
+        assert_eq!(rb.pop().unwrap().1, "world");
+        assert_eq!(rb.pop().unwrap().1, "again");
+        assert!(rb.pop().is_none());
+
+        let rb: SPSCRingBuffer<Box<u64>> = SPSCRingBuffer::new(2);
+        assert!(rb.push(Box::new(7)).is_ok());
+        assert_eq!(*rb.pop().unwrap().1, 7);
+    }
+    #[test]
+    fn drop_queued_values() {
+        let counter = std::sync::Arc::new(());
+        let rb = SPSCRingBuffer::new(4);
+        for _ in 0..3 {
+            assert!(rb.push(counter.clone()).is_ok());
+        }
+        drop(rb.pop());
+        assert!(rb.push(counter.clone()).is_ok());
+        assert_eq!(std::sync::Arc::strong_count(&counter), 4);
+        drop(rb);
+        assert_eq!(std::sync::Arc::strong_count(&counter), 1);
+    }
+    #[test]
+    fn cached_indices() {
+        let rb = SPSCRingBuffer::new(4);
+        for i in 0..4u64 {
+            assert!(rb.push(i).is_ok());
+        }
+        // The producer has not seen the pops yet, the first push after
+        // them has to refresh its cached read index.
+        assert_eq!(rb.cached_read.get(), 0);
+        assert_eq!(rb.pop(), Some((0, 0)));
+        assert_eq!(rb.pop(), Some((1, 1)));
+        assert_eq!(rb.cached_write.get(), 4);
+        assert!(rb.push(4).is_ok());
+        assert_eq!(rb.cached_read.get(), 2);
+        assert_eq!(rb.pop(), Some((2, 2)));
+        assert_eq!(rb.pop(), Some((3, 3)));
+        assert_eq!(rb.pop(), Some((0, 4)));
+        assert_eq!(rb.pop(), None);
+    }
+    #[test]
+    fn len_and_capacity() {
+        let rb = SPSCRingBuffer::new(8);
+        assert_eq!(rb.capacity(), 8);
+        assert_eq!(rb.len(), 0);
+        for i in 0..8u64 {
+            assert!(rb.push(i).is_ok());
+            assert_eq!(rb.len(), i as usize + 1);
+        }
+        assert!(rb.is_full());
+        for _ in 0..5 {
+            assert!(rb.pop().is_some());
+        }
+        // Slots have wrapped around here.
+        for i in 0..4u64 {
+            assert!(rb.push(i).is_ok());
+        }
+        assert_eq!(rb.len(), 7);
+        assert!(!rb.is_full());
+
+        let (tx, rx) = rb.split();
+        assert_eq!(tx.len(), 7);
+        assert_eq!(rx.capacity(), 8);
+        assert!(!rx.is_empty());
+    }
+    #[test]
+    fn write_chunk() {
+        let (mut tx, mut rx) = SPSCRingBuffer::<u64>::new_split(8);
+        for i in 0..5 {
+            assert!(tx.push(i).is_ok());
+        }
+        for i in 0..5 {
+            assert_eq!(rx.pop().unwrap(), i);
+        }
+        assert!(matches!(tx.write_chunk(9), Err(ChunkError::TooFewSlots(8))));
+
+        // Slots 5, 6, 7 then 0, 1, 2.
+        let mut chunk = tx.write_chunk(6).unwrap();
+        let (first, second) = chunk.as_mut_slices();
+        assert_eq!((first.len(), second.len()), (3, 3));
+        for (i, slot) in first.iter_mut().chain(second.iter_mut()).enumerate() {
+            slot.write(10 + i as u64);
+        }
+        // Nothing is visible before the commit.
+        assert!(rx.is_empty());
+        unsafe { chunk.commit(4) };
+        assert_eq!(rx.len(), 4);
+        for i in 0..4 {
+            assert_eq!(rx.pop().unwrap(), 10 + i);
+        }
+        assert!(rx.pop().is_err());
+    }
+    #[test]
+    fn read_chunk() {
+        let (mut tx, mut rx) = SPSCRingBuffer::new_split(8);
+        for i in 0..6 {
+            assert!(tx.push(i.to_string()).is_ok());
+        }
+        for i in 0..6 {
+            assert_eq!(rx.pop().unwrap(), i.to_string());
+        }
+        for i in 0..5 {
+            assert!(tx.push(i.to_string()).is_ok());
+        }
+        let chunk = rx.read_chunk().unwrap();
+        let (first, second) = chunk.as_slices();
+        assert_eq!(first, ["0", "1"]);
+        assert_eq!(second, ["2", "3", "4"]);
+        chunk.commit(3);
+        assert_eq!(rx.len(), 2);
+        assert_eq!(rx.pop().unwrap(), "3");
+        drop(tx);
+        let chunk = rx.read_chunk().unwrap();
+        assert_eq!(chunk.as_slices(), (&["4".to_string()][..], &[][..]));
+        chunk.commit(1);
+        assert_eq!(rx.read_chunk().err(), Some(Disconnected));
+    }
+    #[test]
+    fn pop_iter() {
+        let (mut tx, mut rx) = SPSCRingBuffer::new_split(8);
+        assert_eq!(rx.pop_iter().next(), None);
+        for i in 0..5 {
+            assert!(tx.push(i).is_ok());
+        }
+        let mut iter = rx.pop_iter();
+        assert_eq!(iter.next(), Some(0));
+        assert_eq!(iter.size_hint(), (4, None));
+        assert_eq!(iter.collect::<Vec<_>>(), [1, 2, 3, 4]);
+        assert!(tx.push(5).is_ok());
+        let mut sum = 0;
+        for value in rx.pop_iter() {
+            sum += value;
+        }
+        assert_eq!(sum, 5);
+    }
+    #[test]
+    fn fill_from_iter() {
+        let (mut tx, mut rx) = SPSCRingBuffer::new_split(4);
+        assert_eq!(tx.write_chunk(3).unwrap().fill_from_iter(0..2), 2);
+        assert_eq!(tx.write_chunk(1).unwrap().fill_from_iter(2..10), 1);
+        assert_eq!((rx.pop().unwrap(), rx.pop().unwrap(), rx.pop().unwrap()), (0, 1, 2));
+
+        // A panicking iterator drops what it produced and commits nothing.
+        let (mut tx, mut rx) = SPSCRingBuffer::new_split(4);
+        let counter = std::sync::Arc::new(());
+        let values = std::iter::repeat_with(|| counter.clone());
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let mut n = 0;
+            tx.write_chunk(3).unwrap().fill_from_iter(values.inspect(|_| {
+                n += 1;
+                assert!(n < 3, "iterator panic");
+            }))
+        }));
+        assert!(result.is_err());
+        assert_eq!(std::sync::Arc::strong_count(&counter), 1);
+        assert!(rx.pop().is_err());
+        assert!(tx.push(std::sync::Arc::new(())).is_ok());
+        assert!(rx.pop().is_ok());
+    }
+    #[test]
+    fn read_chunk_commit_panicking_drop() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct PanicOnDrop(bool);
+        impl Drop for PanicOnDrop {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Ordering::SeqCst);
+                assert!(!self.0, "drop panic");
+            }
+        }
+
+        let (mut tx, mut rx) = SPSCRingBuffer::new_split(4);
+        for panics in [false, true, false] {
+            assert!(tx.push(PanicOnDrop(panics)).is_ok());
+        }
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            rx.read_chunk().unwrap().commit(3);
+        }));
+        assert!(result.is_err());
+        assert_eq!(DROPS.load(Ordering::SeqCst), 2);
+        // The value after the panicking one is still queued, and each value
+        // is dropped exactly once.
+        assert_eq!(rx.len(), 1);
+        drop(rx.pop().unwrap());
+        drop((tx, rx));
+        assert_eq!(DROPS.load(Ordering::SeqCst), 3);
+    }
+    #[test]
+    fn push_and_pop_slices() {
+        let (mut tx, mut rx) = SPSCRingBuffer::<u32>::new_split(8);
+        let values: Vec<u32> = (0..20).collect();
+        assert_eq!(tx.push_slice(&values[..6]).unwrap(), 6);
+        let mut out = [0; 5];
+        assert_eq!(rx.pop_slice(&mut out).unwrap(), 5);
+        assert_eq!(out, [0, 1, 2, 3, 4]);
+        // Wraps around the end of the buffer, cut to the 7 vacant slots.
+        assert_eq!(tx.push_slice(&values[6..]).unwrap(), 7);
+        let mut out = [0; 10];
+        assert_eq!(rx.pop_slice(&mut out).unwrap(), 8);
+        assert_eq!(out[..8], [5, 6, 7, 8, 9, 10, 11, 12]);
+        assert_eq!(rx.pop_slice(&mut out).unwrap(), 0);
+    }
+    #[test]
+    fn raw_regions() {
+        let (mut tx, mut rx) = SPSCRingBuffer::<u8>::new_split(8);
+        let [(first, len), (_, 0)] = tx.vacant_regions() else { panic!("regions should not wrap") };
+        assert_eq!(len, 8);
+        // Stands in for a DMA engine writing into the ring.
+        unsafe {
+            ptr::copy_nonoverlapping(b"abcdef".as_ptr(), first, 6);
+            tx.advance_write(6);
+        }
+        let [(first, 6), (_, 0)] = rx.occupied_regions() else { panic!("expected 6 values") };
+        assert_eq!(unsafe { slice::from_raw_parts(first, 6) }, b"abcdef");
+        rx.advance_read(4);
+        assert_eq!(rx.pop().unwrap(), b'e');
+        // The 7 vacant slots now wrap around the end of the buffer.
+        let regions = tx.vacant_regions();
+        assert_eq!((regions[0].1, regions[1].1), (2, 5));
+        unsafe {
+            ptr::copy_nonoverlapping(b"gh".as_ptr(), regions[0].0, 2);
+            ptr::copy_nonoverlapping(b"ijk".as_ptr(), regions[1].0, 3);
+            tx.advance_write(5);
+        }
+        let mut out = [0; 8];
+        assert_eq!(rx.pop_slice(&mut out).unwrap(), 6);
+        assert_eq!(&out[..6], b"fghijk");
+    }
+    #[test]
+    #[should_panic(expected = "only 8 vacant")]
+    fn advance_write_past_vacant() {
+        let (mut tx, _rx) = SPSCRingBuffer::<u8>::new_split(8);
+        unsafe { tx.advance_write(9) };
+    }
+    #[test]
+    fn advance_write_after_push_batched() {
+        let (mut tx, mut rx) = SPSCRingBuffer::<u8>::new_split(8);
+        tx.push_batched(b'a').unwrap();
+        tx.push_batched(b'b').unwrap();
+        // The batch is published first, and its slots are not vacant.
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| unsafe { tx.advance_write(7) }));
+        assert!(result.is_err());
+        assert_eq!(tx.pending(), 0);
+        let [(first, 6), _] = tx.vacant_regions() else { panic!("expected 6 vacant slots") };
+        unsafe {
+            ptr::copy_nonoverlapping(b"cd".as_ptr(), first, 2);
+            tx.advance_write(2);
+        }
+        let mut out = [0; 8];
+        assert_eq!(rx.pop_slice(&mut out).unwrap(), 4);
+        assert_eq!(&out[..4], b"abcd");
+    }
+    #[test]
+    fn raw_parts() {
+        let rb = SPSCRingBuffer::new(4);
+        for i in 0..6 {
+            assert!(rb.push(i.to_string()).is_ok());
+            if i % 2 == 0 {
+                assert!(rb.pop().is_some());
+            }
+        }
+        let parts = rb.into_raw_parts();
+        assert_eq!((parts.capacity, parts.read, parts.write), (4, 3, 6));
+        // The other side pops one value and pushes two.
+        let parts = unsafe {
+            assert_eq!(ptr::read(parts.buffer.add(3)), "3");
+            ptr::write(parts.buffer.add(2), "6".to_string());
+            ptr::write(parts.buffer.add(3), "7".to_string());
+            RawParts { read: 4, write: 8, ..parts }
+        };
+        let rb = unsafe { SPSCRingBuffer::<String>::from_raw_parts(parts) };
+        assert_eq!(rb.len(), 4);
+        let values: Vec<_> = core::iter::from_fn(|| rb.pop().map(|(_, v)| v)).collect();
+        assert_eq!(values, ["4", "5", "6", "7"]);
+    }
+    #[test]
+    fn cache_line_storage() {
+        let options = StorageOptions::new().aligned(AlignedStorage::CacheLine);
+        let rb = SPSCRingBuffer::<u8>::with_storage(8, options);
+        assert_eq!(rb.buffer.as_ptr() as usize % 64, 0);
+        assert!(rb.push(1).is_ok());
+        let parts = rb.into_raw_parts();
+        assert_eq!((parts.capacity, parts.align), (8, 64));
+        let rb = unsafe { SPSCRingBuffer::<u8>::from_raw_parts(parts) };
+        assert_eq!(rb.pop().unwrap().1, 1);
+        let parts = SPSCRingBuffer::<u8>::new(8).into_raw_parts();
+        assert_eq!(parts.align, 1);
+        drop(unsafe { SPSCRingBuffer::<u8>::from_raw_parts(parts) });
+    }
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn latency_metrics() {
+        let (mut tx, mut rx) = SPSCRingBuffer::new_split(2);
+        assert!(tx.push(1).is_ok());
+        assert!(tx.push(2).is_ok());
+        assert!(tx.push(3).is_err());
+        assert_eq!(rx.pop().unwrap(), 1);
+        assert_eq!(tx.push_latency().count(), 2);
+        assert_eq!(rx.pop_latency().count(), 1);
+        assert!(rx.push_latency().p99() <= rx.push_latency().max());
+    }
+    #[cfg(feature = "stats")]
+    #[test]
+    fn stats() {
+        let (mut tx, mut rx) = SPSCRingBuffer::new_split(4);
+        for i in 0..3 {
+            assert!(tx.push(i).is_ok());
+        }
+        assert_eq!(rx.pop().unwrap(), 0);
+        assert_eq!(tx.push_slice(&[3, 4, 5]), Ok(2));
+        assert!(tx.push(6).is_err());
+        rx.read_chunk().unwrap().commit(4);
+        assert!(rx.pop().is_err());
+        let expected = StatsSnapshot { pushes: 5, pops: 5, full: 1, empty: 1, high_water: 4 };
+        assert_eq!(tx.stats(), expected);
+    }
+
+    #[test]
+    fn dedup() {
+        let (tx, mut rx) = SPSCRingBuffer::new_split(2);
+        let mut tx = tx.dedup();
+        assert_eq!(tx.push("on"), Ok(Some(0)));
+        assert_eq!(tx.push("on"), Ok(None));
+        assert_eq!(tx.push("off"), Ok(Some(1)));
+        assert_eq!(tx.push("on"), Err(PushError::Full("on")));
+        assert_eq!(rx.pop(), Ok("on"));
+        // Still compared to "off", popped or not.
+        assert_eq!(rx.pop(), Ok("off"));
+        assert_eq!(tx.push("off"), Ok(None));
+        assert_eq!(tx.push("on"), Ok(Some(0)));
+        assert_eq!(tx.duplicates(), 2);
+    }
+
+    #[test]
+    fn push_batched() {
+        let (mut tx, mut rx) = SPSCRingBuffer::new_split(4);
+        assert_eq!(tx.push_batched(1).unwrap(), 0);
+        assert_eq!(tx.push_batched(2).unwrap(), 1);
+        assert_eq!(tx.pending(), 2);
+        assert!(rx.pop().is_err());
+        tx.flush();
+        assert_eq!(tx.pending(), 0);
+        assert_eq!(rx.pop().unwrap(), 1);
+        // A plain push publishes the pending values first, in order.
+        assert!(tx.push_batched(3).is_ok());
+        assert!(tx.push(4).is_ok());
+        assert_eq!(rx.pop().unwrap(), 2);
+        assert_eq!(rx.pop().unwrap(), 3);
+        assert_eq!(rx.pop().unwrap(), 4);
+        // A full ring publishes what is pending.
+        for i in 5..9 {
+            assert!(tx.push_batched(i).is_ok());
+        }
+        assert_eq!(tx.push_batched(9), Err(PushError::Full(9)));
+        assert_eq!(tx.pending(), 0);
+        assert_eq!(rx.pop().unwrap(), 5);
+        tx.set_flush_every(2);
+        assert!(tx.push_batched(9).is_ok());
+        assert_eq!(tx.pending(), 1);
+        drop(tx);
+        assert_eq!(rx.pop_iter().collect::<Vec<_>>(), [6, 7, 8, 9]);
+        assert_eq!(rx.pop(), Err(PopError::Disconnected));
+    }
+    #[test]
+    fn push_batched_across_threads() {
+        let (mut tx, mut rx) = SPSCRingBuffer::new_split(64);
+        tx.set_flush_every(16);
+        let consumer = std::thread::spawn(move || {
+            let mut next = 0u64;
+            while let Ok(v) = rx.pop_blocking() {
+                assert_eq!(v, next);
+                next += 1;
+            }
+            next
+        });
+        for i in 0..10_000 {
+            while tx.push_batched(i).is_err() {
+                std::thread::yield_now();
+            }
+        }
+        drop(tx);
+        assert_eq!(consumer.join().unwrap(), 10_000);
+    }
+    #[test]
+    #[cfg(feature = "std")]
+    fn timeouts() {
+        let (mut tx, mut rx) = SPSCRingBuffer::<u64>::new_split(2);
+        let timeout = Duration::from_millis(10);
+        let start = Instant::now();
+        assert_eq!(rx.pop_timeout(timeout), Err(PopError::Empty));
+        assert!(start.elapsed() >= timeout);
+        assert!(tx.push_timeout(1, timeout).is_ok());
+        assert!(tx.push_timeout(2, timeout).is_ok());
+        assert_eq!(tx.push_timeout(3, timeout), Err(PushError::Full(3)));
+
+        let consumer = std::thread::spawn(move || {
+            let mut got = vec![];
+            while let Ok(v) = rx.pop_timeout(Duration::from_secs(5)) {
+                got.push(v);
+            }
+            got
+        });
+        for i in 3..100 {
+            assert!(tx.push_blocking(i).is_ok());
+        }
+        drop(tx);
+        assert_eq!(consumer.join().unwrap(), (1..100).collect::<Vec<_>>());
+    }
+    #[test]
+    #[cfg(feature = "std")]
+    fn wait_strategies() {
+        use crate::wait::{BusySpin, Hybrid, Park};
+
+        let (mut tx, mut rx) = SPSCRingBuffer::new(4).split_with(BusySpin, Park::default());
+        let consumer = std::thread::spawn(move || {
+            let mut sum = 0;
+            while let Ok(v) = rx.pop_blocking() {
+                sum += v;
+            }
+            sum
+        });
+        for i in 0..1000u64 {
+            assert!(tx.push_blocking(i).is_ok());
+        }
+        drop(tx);
+        assert_eq!(consumer.join().unwrap(), 999 * 1000 / 2);
+
+        let (mut tx, mut rx) = SPSCRingBuffer::new(2).split_with(Hybrid::default(), Hybrid::default());
+        assert!(tx.push_timeout(1u64, Duration::from_millis(1)).is_ok());
+        assert!(tx.push_timeout(2, Duration::from_millis(1)).is_ok());
+        assert!(tx.push_timeout(3, Duration::from_millis(5)).is_err());
+        assert_eq!(rx.pop_timeout(Duration::from_millis(1)).unwrap(), 1);
+        assert_eq!(rx.pop_timeout(Duration::from_millis(1)).unwrap(), 2);
+        assert!(rx.pop_timeout(Duration::from_millis(5)).is_err());
+    }
+    #[test]
+    fn claim_ack_nack() {
+        let (mut tx, mut rx) = SPSCRingBuffer::new_split(4);
+        assert!(rx.claim().is_none());
+        assert!(tx.push("a".to_string()).is_ok());
+        assert!(tx.push("b".to_string()).is_ok());
+        let claim = rx.claim().unwrap();
+        assert_eq!(*claim, "a");
+        claim.nack();
+        // A panic while processing does not lose the value either.
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _claim = rx.claim().unwrap();
+            panic!("processing failed");
+        }));
+        assert!(panicked.is_err());
+        assert_eq!(rx.claim().unwrap().ack(), "a");
+        assert_eq!(rx.claim().unwrap().ack(), "b");
+        assert!(rx.claim().is_none());
+    }
+    #[test]
+    fn peek() {
+        let (mut tx, mut rx) = SPSCRingBuffer::new_split(4);
+        assert!(rx.peek().is_none());
+        assert!(tx.push("a".to_string()).is_ok());
+        assert!(tx.push("b".to_string()).is_ok());
+        assert_eq!(rx.peek().unwrap(), "a");
+        assert_eq!(rx.peek().unwrap(), "a");
+        assert_eq!(rx.pop().unwrap(), "a");
+        assert_eq!(rx.peek().unwrap(), "b");
+        assert_eq!(rx.pop().unwrap(), "b");
+        assert!(rx.peek().is_none());
+    }
+    #[test]
+    fn index_width() {
+        let rb = SPSCRingBuffer::<u64, u16>::with_index_width(4);
+        assert_eq!(rb.capacity(), 4);
+        // Goes around the 16 bit counters several times.
+        for i in 0..100_000 {
+            assert!(rb.push(i).is_ok());
+            assert!(rb.push(i + 1).is_ok());
+            assert_eq!(rb.pop().unwrap().1, i);
+            assert_eq!(rb.pop().unwrap().1, i + 1);
+        }
+        assert!(rb.is_empty());
+        let rb = SPSCRingBuffer::<u8, u16>::with_index_width(1 << 15);
+        assert_eq!(rb.capacity(), 1 << 15);
+    }
+    #[test]
+    #[should_panic(expected = "too large for the index type")]
+    fn index_width_too_small() {
+        SPSCRingBuffer::<u8, u16>::with_index_width((1 << 16) + 1);
+    }
+    #[test]
+    fn force_push() {
+        let rb = SPSCRingBuffer::new_overwriting(4);
+        for i in 0..4 {
+            assert_eq!(rb.force_push(i.to_string()), None);
+        }
+        assert_eq!(rb.force_push("4".to_string()), Some("0".to_string()));
+        assert_eq!(rb.force_push("5".to_string()), Some("1".to_string()));
+        assert_eq!(rb.len(), 4);
+        assert_eq!(rb.pop().unwrap().1, "2");
+        assert_eq!(rb.force_push("6".to_string()), None);
+        assert_eq!(rb.pop().unwrap().1, "3");
+        assert_eq!(rb.pop().unwrap().1, "4");
+        assert_eq!(rb.pop().unwrap().1, "5");
+        assert_eq!(rb.pop().unwrap().1, "6");
+        assert!(rb.pop().is_none());
+    }
+    #[test]
+    fn force_push_across_threads() {
+        const COUNT: u64 = 10_000;
+        let rb = SPSCRingBuffer::new_overwriting(8);
+        let done = AtomicBool::new(false);
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                for i in 0..COUNT {
+                    rb.force_push(i);
+                }
+                done.store(true, Ordering::Release);
+            });
+            // Values may be skipped, but never reordered or duplicated.
+            let mut last = None;
+            loop {
+                let finished = done.load(Ordering::Acquire);
+                while let Some((_, v)) = rb.pop() {
+                    assert!(last.is_none_or(|l| v > l));
+                    last = Some(v);
+                }
+                if finished {
+                    break;
+                }
+            }
+            assert_eq!(last, Some(COUNT - 1));
+        });
+    }
+    #[test]
+    fn push_to_an_overwriting_ring_across_threads() {
+        const COUNT: usize = 10_000;
+        let rb = SPSCRingBuffer::new_overwriting(4);
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                for i in 0..COUNT {
+                    while rb.push(i.to_string()).is_err() {
+                        std::thread::yield_now();
+                    }
+                }
+            });
+            // Plain pushes never evict, so every value comes out, in order.
+            for i in 0..COUNT {
+                let value = loop {
+                    match rb.pop() {
+                        Some((_, value)) => break value,
+                        None => std::thread::yield_now(),
+                    }
+                };
+                assert_eq!(value, i.to_string());
+            }
+        });
+    }
+    #[test]
+    fn split_across_threads() {
+        const COUNT: u64 = 1000;
+        let (mut tx, mut rx) = SPSCRingBuffer::new_split(8);
+        let producer = std::thread::spawn(move || {
+            for i in 0..COUNT {
+                while tx.push(i).is_err() {
+                    std::thread::yield_now();
+                }
+            }
+        });
+        let mut expected = 0;
+        loop {
+            match rx.pop() {
+                Ok(v) => {
+                    assert_eq!(v, expected);
+                    expected += 1;
+                }
+                Err(PopError::Disconnected) => break,
+                Err(_) => std::thread::yield_now(),
+            }
+        }
+        assert_eq!(expected, COUNT);
+        producer.join().unwrap();
+    }
+    #[test]
+    fn disconnect() {
+        let (mut tx, mut rx) = SPSCRingBuffer::<u64>::new_split(4);
+        assert_eq!(rx.pop(), Err(PopError::Empty));
+        assert!(tx.push(1).is_ok());
+        drop(tx);
+        assert!(rx.is_disconnected());
+        assert_eq!(rx.pop().unwrap(), 1);
+        assert_eq!(rx.pop(), Err(PopError::Disconnected));
+
+        let (mut tx, rx) = SPSCRingBuffer::<u64>::new_split(4);
+        drop(rx);
+        assert_eq!(tx.push(1), Err(PushError::Disconnected(1)));
+    }
+    #[test]
+    fn push_and_pop_at_random() {
+        let rb = SPSCRingBuffer::new(16);
+        assert!(rb.push(0).is_ok());
+        assert!(rb.push(1).is_ok());
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..100 {
+            let y: f64 = rng.gen();
+            if y < 0.5 {
+                let _ = rb.push(1);
+            } else {
+                if !rb.empty() {
+                    assert!(rb.pop().is_some());
+                }
+            }
+        }
+    }
+    #[test]
+    #[allow(unused_variables, clippy::explicit_counter_loop)]
+    fn spsc_ring_buffer() {
+        const COUNT: u64 = 8;
+        let t = AtomicUsize::new(1);
+        let q: SPSCRingBuffer<u64> = SPSCRingBuffer::<u64>::new(16);
+        let tracker: Vec<AtomicIsize> = (0..COUNT).map(|_| AtomicIsize::new(0)).collect::<Vec<_>>();
+
+        std::thread::scope(|scope| {
+            // Pop after producer is done
+            scope.spawn(|| loop {
+                match t.load(Ordering::SeqCst) {
+                    0 if q.empty() => break,
+                    _ => {
+                        while let Some((idx, val)) = q.pop() {
+                            tracker[idx].fetch_sub(1, Ordering::SeqCst);
+                        }
+                    }
+                }
+            });
+
+            scope.spawn(|| {
+                // Keep pushing until the buffer is full
+                for i in 0..COUNT {
+                    match q.push(i) {
+                        Ok(n) => {
+                            tracker[n].fetch_add(1, Ordering::SeqCst);
+                        }
+                        Err(PushError::Full(x)) => {
+                            println!("Error while pushing the value {x}");
+                            std::thread::sleep(std::time::Duration::from_millis(1));
+                            continue;
+                        }
+                        _ => {
+                            panic!("Unexpected error");
+                        }
+                    }
+                }
+                // Signal the consumer to stop after COUNT iterations.
+                t.fetch_sub(1, Ordering::SeqCst);
+            });
+        });
+
+        let mut idx: i32 = 0;
+        for c in tracker {
+            println!("tracker[{}]", idx);
+            idx += 1;
+            assert_eq!(c.load(Ordering::SeqCst), 0);
+        }
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+    use loom::thread;
+
+    #[test]
+    fn push_and_pop() {
+        loom::model(|| {
+            let (mut tx, mut rx) = SPSCRingBuffer::new_split(2);
+            let producer = thread::spawn(move || {
+                for i in 0..2 {
+                    while tx.push(i).is_err() {
+                        thread::yield_now();
+                    }
+                }
+            });
+            for i in 0..2 {
+                loop {
+                    match rx.pop() {
+                        Ok(v) => {
+                            assert_eq!(v, i);
+                            break;
+                        }
+                        Err(_) => thread::yield_now(),
+                    }
+                }
+            }
+            producer.join().unwrap();
+        });
+    }
+
+    #[test]
+    fn disconnect_delivers_queued_values() {
+        loom::model(|| {
+            let (mut tx, mut rx) = SPSCRingBuffer::new_split(2);
+            let producer = thread::spawn(move || {
+                assert!(tx.push(7).is_ok());
+            });
+            let mut got = None;
+            loop {
+                match rx.pop() {
+                    Ok(v) => got = Some(v),
+                    Err(PopError::Disconnected) => break,
+                    Err(_) => thread::yield_now(),
+                }
+            }
+            assert_eq!(got, Some(7));
+            producer.join().unwrap();
+        });
+    }
+
+    #[test]
+    fn force_push_races_pop() {
+        loom::model(|| {
+            let rb = Arc::new(SPSCRingBuffer::new_overwriting(1));
+            assert_eq!(rb.force_push(0), None);
+            let producer = {
+                let rb = rb.clone();
+                thread::spawn(move || rb.force_push(1))
+            };
+            let popped = rb.pop().map(|(_, v)| v);
+            let evicted = producer.join().unwrap();
+            let left = rb.pop().map(|(_, v)| v);
+            // Every value ends up exactly once in one of the three places.
+            let mut all: Vec<_> = [popped, evicted, left].into_iter().flatten().collect();
+            all.sort();
+            assert_eq!(all, vec![0, 1]);
+        });
+    }
+}
+
+/// Proofs for `cargo kani`: for any counter, the two slices of `slots` lie
+/// in the buffer, do not overlap and hold exactly the slots asked for.
+#[cfg(kani)]
+mod verification {
+    use super::*;
+
+    #[kani::proof]
+    #[kani::unwind(17)]
+    fn slots_in_bounds() {
+        let shift: u32 = kani::any();
+        kani::assume(shift <= 4);
+        let rb = SPSCRingBuffer::<u8>::new(1 << shift);
+        let start: usize = kani::any();
+        let len: usize = kani::any();
+        kani::assume(len <= rb.capacity());
+        let (first, second) = unsafe { rb.slots(start, len) };
+        assert_eq!(first.len() + second.len(), len);
+        // A wrapping range ends before it started.
+        assert!(second.len() <= start & rb.mask);
+        // Kani checks that both ends are in the allocation.
+        if let Some(slot) = first.last_mut() {
+            slot.write(1);
+        }
+        if let Some(slot) = second.last_mut() {
+            slot.write(2);
+        }
+    }
+}
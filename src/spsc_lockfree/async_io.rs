@@ -0,0 +1,160 @@
+//! Async byte rings: the consumer of a `ByteRingBuffer` is a
+//! `futures_io::AsyncRead` and the producer an `AsyncWrite`, the async
+//! counterparts of its `io::Read` and `io::Write` impls. A task that finds
+//! the ring empty (or full) registers its waker in the ring, and the
+//! opposite half wakes it once bytes are written (or read), or when it is
+//! dropped.
+
+use futures_io::{AsyncRead, AsyncWrite};
+use std::io::{self, IoSlice, IoSliceMut};
+use std::pin::Pin;
+use std::sync::atomic::Ordering;
+use std::task::{Context, Poll};
+
+use super::{Consumer, Producer};
+use crate::error::Disconnected;
+use crate::wait::WaitStrategy;
+
+impl<W: WaitStrategy> Consumer<u8, W> {
+    /// Polls for at least one readable byte. `Ready(false)` is the end of
+    /// the stream: the producer is dropped and the ring drained.
+    fn poll_readable(&mut self, cx: &mut Context<'_>) -> Poll<bool> {
+        if let Poll::Ready(readable) = self.try_readable() {
+            return Poll::Ready(readable);
+        }
+        self.rb.consumer_waker.register(cx.waker());
+        // The producer may have written before the waker was registered.
+        self.try_readable()
+    }
+
+    fn try_readable(&self) -> Poll<bool> {
+        if self.rb.occupied() > 0 {
+            Poll::Ready(true)
+        } else if self.is_disconnected() {
+            // The producer may have written right before dropping.
+            Poll::Ready(self.rb.occupied() > 0)
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+/// Reads return 0 at the end of the stream, once the producer is dropped
+/// and the ring drained.
+impl<W: WaitStrategy> AsyncRead for Consumer<u8, W> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        self.poll_read_vectored(cx, &mut [IoSliceMut::new(buf)])
+    }
+
+    fn poll_read_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &mut [IoSliceMut<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if bufs.iter().all(|buf| buf.is_empty()) {
+            return Poll::Ready(Ok(0));
+        }
+        match this.poll_readable(cx) {
+            Poll::Ready(true) => {}
+            Poll::Ready(false) => return Poll::Ready(Ok(0)),
+            Poll::Pending => return Poll::Pending,
+        }
+        let mut read = 0;
+        for buf in bufs {
+            // Readable bytes are left, so it cannot fail.
+            let n = this.pop_slice(buf).unwrap_or(0);
+            read += n;
+            if n < buf.len() {
+                break;
+            }
+        }
+        Poll::Ready(Ok(read))
+    }
+}
+
+/// Writes fail with `BrokenPipe` once the consumer is dropped. Closing
+/// disconnects the consumer, whose reads return 0 once the ring is drained.
+impl<W: WaitStrategy> AsyncWrite for Producer<u8, W> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        self.poll_write_vectored(cx, &[IoSlice::new(buf)])
+    }
+
+    fn poll_write_vectored(self: Pin<&mut Self>, cx: &mut Context<'_>, bufs: &[IoSlice<'_>]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if bufs.iter().all(|buf| buf.is_empty()) {
+            return Poll::Ready(Ok(0));
+        }
+        match this.poll_vacant(cx) {
+            Poll::Ready(Ok(())) => {}
+            Poll::Ready(Err(Disconnected)) => return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into())),
+            Poll::Pending => return Poll::Pending,
+        }
+        let mut written = 0;
+        for buf in bufs {
+            let n = this.push_slice(buf).map_err(|Disconnected| io::Error::from(io::ErrorKind::BrokenPipe))?;
+            written += n;
+            if n < buf.len() {
+                break;
+            }
+        }
+        Poll::Ready(Ok(written))
+    }
+
+    /// Publishes the bytes pending from `push_batched`, the others are
+    /// visible as soon as written.
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().flush();
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.flush();
+        this.rb.disconnected.store(true, Ordering::Release);
+        this.rb.wake_consumer();
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use crate::spsc_lockfree::future::tests::block_on;
+    use crate::spsc_lockfree::ByteRingBuffer;
+    use futures::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use futures::StreamExt;
+    use std::thread;
+
+    #[test]
+    fn pipe_between_tasks() {
+        let (mut tx, rx) = ByteRingBuffer::new_split(16);
+        let reader = thread::spawn(move || block_on(BufReader::new(rx).lines().map(Result::unwrap).collect::<Vec<_>>()));
+        block_on(async {
+            for i in 0..100 {
+                tx.write_all(format!("line {i}\n").as_bytes()).await.unwrap();
+            }
+            tx.close().await.unwrap();
+        });
+        let lines = reader.join().unwrap();
+        assert_eq!(lines.len(), 100);
+        assert_eq!(lines[99], "line 99");
+    }
+
+    #[test]
+    fn end_of_stream_and_broken_pipe() {
+        let (mut tx, mut rx) = ByteRingBuffer::new_split(4);
+        block_on(async {
+            assert_eq!(tx.write(b"abcdef").await.unwrap(), 4);
+            drop(tx);
+            let mut out = Vec::new();
+            assert_eq!(rx.read_to_end(&mut out).await.unwrap(), 4);
+            assert_eq!(out, b"abcd");
+        });
+
+        let (mut tx, rx) = ByteRingBuffer::new_split(4);
+        drop(rx);
+        let err = block_on(tx.write(b"ab")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+    }
+}
@@ -0,0 +1,191 @@
+//! Audio rings: interleaved `f32` or `i16` samples moved a whole number of
+//! frames (one sample per channel) at a time, between an audio callback
+//! and another thread. `write_frames` and `read_frames` move exactly the
+//! frames asked for or nothing, so a callback never deals with a partial
+//! buffer; they never allocate, lock or wait, which callbacks must not do.
+
+use core::ptr;
+
+use super::{Consumer, Producer, SPSCRingBuffer};
+use crate::error::ChunkError;
+
+/// A sample type of an audio ring, implemented for `f32` and `i16`.
+pub trait Sample: Copy + Default + Send + 'static {}
+
+impl Sample for f32 {}
+impl Sample for i16 {}
+
+/// A ring of interleaved samples, to split into an `AudioProducer` and an
+/// `AudioConsumer`.
+pub struct AudioRingBuffer<S> {
+    rb: SPSCRingBuffer<S>,
+    channels: usize,
+}
+
+impl<S: Sample> AudioRingBuffer<S> {
+    /// Creates a ring holding at least `frames` frames of `channels`
+    /// samples, the sample count being rounded up to the next power of two.
+    /// # Panics
+    /// If `channels` is 0.
+    pub fn new(channels: usize, frames: usize) -> Self {
+        assert!(channels > 0, "an audio ring needs at least one channel");
+        AudioRingBuffer { rb: SPSCRingBuffer::new(channels * frames.max(1)), channels }
+    }
+
+    /// Creates a ring and splits it into its two owned halves.
+    pub fn new_split(channels: usize, frames: usize) -> (AudioProducer<S>, AudioConsumer<S>) {
+        Self::new(channels, frames).split()
+    }
+
+    /// Splits the ring into halves that can be moved to different threads.
+    /// Dropping one half disconnects the other.
+    pub fn split(self) -> (AudioProducer<S>, AudioConsumer<S>) {
+        let (producer, consumer) = self.rb.split();
+        (AudioProducer { producer, channels: self.channels }, AudioConsumer { consumer, channels: self.channels })
+    }
+}
+
+/// The writing half of a split `AudioRingBuffer`.
+pub struct AudioProducer<S> {
+    producer: Producer<S>,
+    channels: usize,
+}
+
+impl<S: Sample> AudioProducer<S> {
+    /// Writes all the interleaved frames of `samples`, or none of them:
+    /// fails with `ChunkError::TooFewSlots`, counting the vacant frames,
+    /// while they do not all fit.
+    /// # Panics
+    /// If `samples` is not a whole number of frames.
+    pub fn write_frames(&mut self, samples: &[S]) -> Result<(), ChunkError> {
+        assert_eq!(samples.len() % self.channels, 0, "not a whole number of {}-channel frames", self.channels);
+        let channels = self.channels;
+        let mut chunk = self.producer.write_chunk(samples.len()).map_err(|e| in_frames(e, channels))?;
+        let (first, second) = chunk.as_mut_slices();
+        // The chunk has exactly `samples.len()` slots, and `MaybeUninit<S>`
+        // has the layout of `S`.
+        unsafe {
+            ptr::copy_nonoverlapping(samples.as_ptr(), first.as_mut_ptr() as *mut S, first.len());
+            ptr::copy_nonoverlapping(samples[first.len()..].as_ptr(), second.as_mut_ptr() as *mut S, second.len());
+            chunk.commit(samples.len());
+        }
+        Ok(())
+    }
+
+    /// Returns the number of frames that can be written.
+    pub fn vacant_frames(&self) -> usize {
+        (self.producer.capacity() - self.producer.len()) / self.channels
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// Returns true if the `AudioConsumer` was dropped.
+    pub fn is_disconnected(&self) -> bool {
+        self.producer.is_disconnected()
+    }
+}
+
+/// Counts the slots of `TooFewSlots` in frames.
+fn in_frames(e: ChunkError, channels: usize) -> ChunkError {
+    match e {
+        ChunkError::TooFewSlots(samples) => ChunkError::TooFewSlots(samples / channels),
+        e => e,
+    }
+}
+
+/// The reading half of a split `AudioRingBuffer`.
+pub struct AudioConsumer<S> {
+    consumer: Consumer<S>,
+    channels: usize,
+}
+
+impl<S: Sample> AudioConsumer<S> {
+    /// Fills `out` with interleaved frames, or reads nothing: fails with
+    /// `ChunkError::TooFewSlots`, counting the readable frames, while there
+    /// are not enough of them, e.g. to play silence instead on an
+    /// underrun. Fails with `Disconnected` once the producer is dropped and
+    /// the rest of the ring does not fill `out`.
+    /// # Panics
+    /// If `out` is not a whole number of frames.
+    pub fn read_frames(&mut self, out: &mut [S]) -> Result<(), ChunkError> {
+        assert_eq!(out.len() % self.channels, 0, "not a whole number of {}-channel frames", self.channels);
+        let disconnected = self.consumer.is_disconnected();
+        let chunk = self.consumer.read_chunk()?;
+        if chunk.len() < out.len() {
+            if disconnected {
+                return Err(ChunkError::Disconnected);
+            }
+            return Err(in_frames(ChunkError::TooFewSlots(chunk.len()), self.channels));
+        }
+        let n = out.len();
+        let (first, second) = chunk.as_slices();
+        let from_first = n.min(first.len());
+        out[..from_first].copy_from_slice(&first[..from_first]);
+        out[from_first..].copy_from_slice(&second[..n - from_first]);
+        chunk.commit(n);
+        Ok(())
+    }
+
+    /// Returns the number of frames that can be read.
+    pub fn available_frames(&self) -> usize {
+        self.consumer.len() / self.channels
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// Returns true if the `AudioProducer` was dropped.
+    pub fn is_disconnected(&self) -> bool {
+        self.consumer.is_disconnected()
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exact_chunks() {
+        // 3 stereo frames rounded up to 8 samples, so 4 frames.
+        let (mut tx, mut rx) = AudioRingBuffer::<f32>::new_split(2, 3);
+        assert_eq!(tx.vacant_frames(), 4);
+        assert!(tx.write_frames(&[0.1, -0.1, 0.2, -0.2, 0.3, -0.3]).is_ok());
+        assert_eq!(tx.write_frames(&[0.4, -0.4, 0.5, -0.5]), Err(ChunkError::TooFewSlots(1)));
+        assert_eq!(rx.available_frames(), 3);
+
+        let mut out = [0.0; 4];
+        assert!(rx.read_frames(&mut out).is_ok());
+        assert_eq!(out, [0.1, -0.1, 0.2, -0.2]);
+        // Only one frame left: nothing is read.
+        assert_eq!(rx.read_frames(&mut out), Err(ChunkError::TooFewSlots(1)));
+        // Wraps around the end of the buffer.
+        assert!(tx.write_frames(&[0.4, -0.4, 0.5, -0.5]).is_ok());
+        let mut out = [0.0; 6];
+        assert!(rx.read_frames(&mut out).is_ok());
+        assert_eq!(out, [0.3, -0.3, 0.4, -0.4, 0.5, -0.5]);
+    }
+
+    #[test]
+    fn disconnection() {
+        let (mut tx, mut rx) = AudioRingBuffer::<i16>::new_split(1, 4);
+        assert!(tx.write_frames(&[1, 2]).is_ok());
+        drop(tx);
+        let mut out = [0; 3];
+        assert_eq!(rx.read_frames(&mut out), Err(ChunkError::Disconnected));
+        let mut out = [0; 2];
+        assert!(rx.read_frames(&mut out).is_ok());
+        assert_eq!(out, [1, 2]);
+        assert_eq!(rx.read_frames(&mut out), Err(ChunkError::Disconnected));
+        assert!(rx.is_disconnected());
+    }
+
+    #[test]
+    #[should_panic(expected = "not a whole number of 2-channel frames")]
+    fn partial_frame() {
+        let (mut tx, _rx) = AudioRingBuffer::<f32>::new_split(2, 4);
+        let _ = tx.write_frames(&[0.0; 3]);
+    }
+}
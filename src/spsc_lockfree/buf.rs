@@ -0,0 +1,102 @@
+//! `bytes` integration of the byte ring: the consumer is a `Buf` over its
+//! readable bytes and the producer a `BufMut` over its vacant ones, so
+//! tokio codecs and framing code can decode from and encode into the ring
+//! in place. Neither waits: `remaining` is what is readable (or vacant)
+//! right now, and grows as the other side makes progress.
+
+use bytes::buf::UninitSlice;
+use bytes::{Buf, BufMut};
+use core::slice;
+
+use super::{Consumer, Producer};
+use crate::wait::WaitStrategy;
+
+impl<W: WaitStrategy> Buf for Consumer<u8, W> {
+    fn remaining(&self) -> usize {
+        self.rb.occupied()
+    }
+
+    /// The readable bytes up to the end of the buffer, the rest follows
+    /// once they are consumed.
+    fn chunk(&self) -> &[u8] {
+        let [(first, len), _] = self.occupied_regions();
+        // Published by the producer, which does not write to them until
+        // `advance`; borrowing `self` keeps the consumer from it.
+        unsafe { slice::from_raw_parts(first, len) }
+    }
+
+    fn advance(&mut self, cnt: usize) {
+        self.advance_read(cnt);
+    }
+}
+
+unsafe impl<W: WaitStrategy> BufMut for Producer<u8, W> {
+    /// The vacant bytes, minus the ones pending from `push_batched`.
+    fn remaining_mut(&self) -> usize {
+        self.rb.vacant() - self.pending
+    }
+
+    unsafe fn advance_mut(&mut self, cnt: usize) {
+        self.advance_write(cnt);
+    }
+
+    /// The vacant bytes up to the end of the buffer. Publishes the bytes
+    /// pending from `push_batched` first.
+    fn chunk_mut(&mut self) -> &mut UninitSlice {
+        let [(first, len), _] = self.vacant_regions();
+        // The vacant slots are owned by this producer, and `UninitSlice`
+        // only lets them be written.
+        unsafe { UninitSlice::from_raw_parts_mut(first, len) }
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use crate::spsc_lockfree::ByteRingBuffer;
+    use bytes::{Buf, BufMut};
+    use std::thread;
+
+    #[test]
+    fn put_and_get_around_the_wrap() {
+        let (mut tx, mut rx) = ByteRingBuffer::new_split(8);
+        assert_eq!(tx.remaining_mut(), 8);
+        tx.put_slice(b"abcdef");
+        assert_eq!(rx.remaining(), 6);
+        assert_eq!(rx.get_u16(), u16::from_be_bytes(*b"ab"));
+        rx.advance(2);
+        // 4 bytes from 6 wrap around to 0..2.
+        tx.put_u32(0x0102_0304);
+        assert_eq!(rx.chunk(), b"ef\x01\x02");
+        assert_eq!(rx.copy_to_bytes(6).as_ref(), b"ef\x01\x02\x03\x04");
+        assert!(!rx.has_remaining());
+    }
+
+    #[test]
+    fn batched_bytes_are_not_vacant() {
+        let (mut tx, rx) = ByteRingBuffer::new_split(4);
+        tx.push_batched(1).unwrap();
+        assert_eq!(tx.remaining_mut(), 3);
+        assert_eq!(tx.chunk_mut().len(), 3);
+        assert_eq!(rx.remaining(), 1);
+    }
+
+    #[test]
+    fn frames_between_threads() {
+        let (mut tx, mut rx) = ByteRingBuffer::new_split(16);
+        let writer = thread::spawn(move || {
+            for i in 0..200u32 {
+                while tx.remaining_mut() < 4 {
+                    thread::yield_now();
+                }
+                tx.put_u32_le(i);
+            }
+        });
+        for i in 0..200u32 {
+            while rx.remaining() < 4 {
+                thread::yield_now();
+            }
+            assert_eq!(rx.get_u32_le(), i);
+        }
+        writer.join().unwrap();
+    }
+}
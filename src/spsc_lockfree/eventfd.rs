@@ -0,0 +1,110 @@
+//! Readiness notification through a Linux `eventfd`, so the consumer can
+//! sit in an epoll/mio event loop next to sockets.
+//! The producer signals the eventfd when its push makes the ring go from
+//! empty to non-empty. The consumer, once the fd is readable, must call
+//! `Consumer::clear_event` and then pop until the ring is empty before
+//! waiting on the fd again: while the ring is not empty, no new event is
+//! raised.
+
+use std::io;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+
+use super::{Consumer, SPSCRingBuffer};
+
+#[derive(Debug)]
+pub(super) struct EventFd(OwnedFd);
+
+impl EventFd {
+    fn new() -> io::Result<Self> {
+        let fd = unsafe { libc::eventfd(0, libc::EFD_NONBLOCK | libc::EFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(EventFd(unsafe { OwnedFd::from_raw_fd(fd) }))
+    }
+
+    /// Makes the fd readable.
+    pub(super) fn signal(&self) {
+        let one: u64 = 1;
+        // Can only fail if the counter would overflow, it is readable then.
+        unsafe { libc::write(self.0.as_raw_fd(), &one as *const u64 as *const libc::c_void, 8) };
+    }
+
+    /// Resets the counter, the fd is not readable anymore.
+    fn clear(&self) {
+        let mut count: u64 = 0;
+        // EAGAIN means it was not signaled, nothing to clear.
+        unsafe { libc::read(self.0.as_raw_fd(), &mut count as *mut u64 as *mut libc::c_void, 8) };
+    }
+}
+
+impl<T> SPSCRingBuffer<T> {
+    /// Creates a ring buffer whose consumer can be waited on through an
+    /// eventfd, see `Consumer::event_fd`.
+    pub fn with_eventfd(capacity: usize) -> io::Result<Self> {
+        let mut rb = Self::new(capacity);
+        rb.event = Some(EventFd::new()?);
+        Ok(rb)
+    }
+}
+
+impl<T, W> Consumer<T, W> {
+    /// Returns the eventfd signaled when the ring becomes non-empty (or the
+    /// producer is dropped), if the ring was created by `with_eventfd`.
+    pub fn event_fd(&self) -> Option<BorrowedFd<'_>> {
+        self.rb.event.as_ref().map(|e| e.0.as_fd())
+    }
+
+    /// Acknowledges the event, call it before draining the ring.
+    pub fn clear_event(&self) {
+        if let Some(event) = &self.rb.event {
+            event.clear();
+        }
+    }
+}
+
+/// # Panics
+/// If the ring was not created by `SPSCRingBuffer::with_eventfd`.
+impl<T, W> AsRawFd for Consumer<T, W> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.event_fd().expect("ring buffer created without an eventfd").as_raw_fd()
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+
+    fn readable(fd: RawFd) -> bool {
+        let mut pfd = libc::pollfd { fd, events: libc::POLLIN, revents: 0 };
+        unsafe { libc::poll(&mut pfd, 1, 0) == 1 }
+    }
+
+    #[test]
+    fn signals_empty_to_non_empty() {
+        let (mut tx, mut rx) = SPSCRingBuffer::<u64>::with_eventfd(8).unwrap().split();
+        let fd = rx.as_raw_fd();
+        assert!(!readable(fd));
+        assert!(tx.push(1).is_ok());
+        assert!(readable(fd));
+        rx.clear_event();
+        assert!(!readable(fd));
+        // Not a transition, the consumer has not drained the ring yet.
+        assert!(tx.push(2).is_ok());
+        assert!(!readable(fd));
+        assert_eq!(rx.pop().unwrap(), 1);
+        assert_eq!(rx.pop().unwrap(), 2);
+        assert!(rx.pop().is_err());
+        assert!(tx.push(3).is_ok());
+        assert!(readable(fd));
+        rx.clear_event();
+        drop(tx);
+        assert!(readable(fd));
+    }
+
+    #[test]
+    fn no_eventfd() {
+        let (_tx, rx) = SPSCRingBuffer::<u64>::new_split(8);
+        assert!(rx.event_fd().is_none());
+    }
+}
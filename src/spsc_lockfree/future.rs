@@ -0,0 +1,258 @@
+//! Futures bridging the lock-free SPSC halves to async tasks.
+//! A task that finds the ring full (or empty) registers its waker in the
+//! ring, and the opposite half wakes it after the next `pop` (or `push`),
+//! chunk commit, or when it is dropped.
+//! The `Consumer` is also a `futures_core::Stream` of the popped values,
+//! and the `Producer` a `futures_sink::Sink` pushing them.
+
+use futures_core::Stream;
+use futures_sink::Sink;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::Ordering;
+use std::task::{Context, Poll};
+
+use super::{Consumer, Producer};
+use crate::error::{Disconnected, PopError, PushError};
+use crate::wait::WaitStrategy;
+
+impl<T, W: WaitStrategy> Producer<T, W> {
+    /// Polls for a vacant slot. Once this returns `Ready(Ok(()))`, the next
+    /// `push` is guaranteed to succeed.
+    pub fn poll_vacant(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Disconnected>> {
+        // `push` flushes, the pending values must not be counted as vacant.
+        self.flush();
+        if let Poll::Ready(result) = self.try_vacant() {
+            return Poll::Ready(result);
+        }
+        self.rb.producer_waker.register(cx.waker());
+        // The consumer may have popped before the waker was registered.
+        self.try_vacant()
+    }
+
+    fn try_vacant(&self) -> Poll<Result<(), Disconnected>> {
+        if self.is_disconnected() {
+            Poll::Ready(Err(Disconnected))
+        } else if self.rb.vacant() > 0 {
+            Poll::Ready(Ok(()))
+        } else {
+            Poll::Pending
+        }
+    }
+
+    /// Pushes a value, waiting asynchronously for a vacant slot.
+    /// Fails only with `Disconnected`.
+    pub fn push_async(&mut self, value: T) -> PushFuture<'_, T, W> {
+        PushFuture { producer: self, value: Some(value) }
+    }
+}
+
+impl<T, W: WaitStrategy> Consumer<T, W> {
+    /// Polls for the oldest value. Fails only with `Disconnected`.
+    pub fn poll_pop(&mut self, cx: &mut Context<'_>) -> Poll<Result<T, PopError>> {
+        match self.pop() {
+            Err(PopError::Empty) => {}
+            result => return Poll::Ready(result),
+        }
+        self.rb.consumer_waker.register(cx.waker());
+        // The producer may have pushed before the waker was registered.
+        match self.pop() {
+            Err(PopError::Empty) => Poll::Pending,
+            result => Poll::Ready(result),
+        }
+    }
+
+    /// Pops the oldest value, waiting asynchronously for one to be pushed.
+    /// Fails only with `Disconnected`.
+    pub fn pop_async(&mut self) -> PopFuture<'_, T, W> {
+        PopFuture { consumer: self }
+    }
+}
+
+// The halves never pin their wait strategy.
+impl<T, W> Unpin for Producer<T, W> {}
+impl<T, W> Unpin for Consumer<T, W> {}
+
+/// Yields the popped values, and ends once the `Producer` is dropped and
+/// the ring is drained.
+impl<T, W: WaitStrategy> Stream for Consumer<T, W> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        match self.get_mut().poll_pop(cx) {
+            Poll::Ready(Ok(value)) => Poll::Ready(Some(value)),
+            Poll::Ready(Err(_)) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len(), None)
+    }
+}
+
+/// `poll_ready` waits for a vacant slot. Pushed values are visible to the
+/// consumer right away, so flushing is a no-op. Closing disconnects the
+/// consumer, which ends its stream once the ring is drained.
+impl<T, W: WaitStrategy> Sink<T> for Producer<T, W> {
+    type Error = Disconnected;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().poll_vacant(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+        // `poll_ready` found a vacant slot, so only `Disconnected` is left.
+        self.get_mut().push(item).map(|_| ()).map_err(|_| Disconnected)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.rb.disconnected.store(true, Ordering::Release);
+        self.rb.wake_consumer();
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Future returned by `Producer::push_async`.
+pub struct PushFuture<'a, T, W> {
+    producer: &'a mut Producer<T, W>,
+    value: Option<T>,
+}
+
+// The value is never pinned, it is moved into the ring.
+impl<T, W> Unpin for PushFuture<'_, T, W> {}
+
+impl<T, W: WaitStrategy> Future for PushFuture<'_, T, W> {
+    type Output = Result<usize, PushError<T>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.producer.poll_vacant(cx) {
+            // `push` hands the value back if the consumer is gone.
+            Poll::Ready(_) => {
+                let value = self.value.take().expect("PushFuture polled after completion");
+                Poll::Ready(self.producer.push(value))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Future returned by `Consumer::pop_async`.
+pub struct PopFuture<'a, T, W> {
+    consumer: &'a mut Consumer<T, W>,
+}
+
+impl<T, W: WaitStrategy> Future for PopFuture<'_, T, W> {
+    type Output = Result<T, PopError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.consumer.poll_pop(cx)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+pub(crate) mod tests {
+    use super::*;
+    use crate::spsc_lockfree::SPSCRingBuffer;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::{Wake, Waker};
+    use std::thread::{self, Thread};
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    /// Minimal executor: polls the future on the current thread and parks
+    /// between polls.
+    pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = std::pin::pin!(future);
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            thread::park();
+        }
+    }
+
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn pop_wakes_on_push() {
+        let (mut tx, mut rx) = SPSCRingBuffer::<u64>::new_split(4);
+        let count = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(count.clone());
+        let mut cx = Context::from_waker(&waker);
+        assert!(rx.poll_pop(&mut cx).is_pending());
+        assert!(tx.push(5).is_ok());
+        assert_eq!(count.0.load(Ordering::SeqCst), 1);
+        assert!(matches!(rx.poll_pop(&mut cx), Poll::Ready(Ok(5))));
+        assert!(rx.poll_pop(&mut cx).is_pending());
+        drop(tx);
+        assert_eq!(count.0.load(Ordering::SeqCst), 2);
+        assert_eq!(rx.poll_pop(&mut cx), Poll::Ready(Err(PopError::Disconnected)));
+    }
+
+    #[test]
+    fn async_across_threads() {
+        let (mut tx, mut rx) = SPSCRingBuffer::new_split(4);
+        let consumer = thread::spawn(move || {
+            block_on(async {
+                let mut sum = 0;
+                while let Ok(v) = rx.pop_async().await {
+                    sum += v;
+                }
+                sum
+            })
+        });
+        block_on(async {
+            for i in 0..1000u64 {
+                tx.push_async(i).await.unwrap();
+            }
+        });
+        drop(tx);
+        assert_eq!(consumer.join().unwrap(), 999 * 1000 / 2);
+    }
+
+    #[test]
+    fn stream() {
+        use futures::StreamExt;
+
+        let (mut tx, rx) = SPSCRingBuffer::<u64>::new_split(4);
+        let consumer = thread::spawn(move || {
+            block_on(rx.filter(|v| std::future::ready(v % 2 == 0)).map(|v| v * 10).collect::<Vec<_>>())
+        });
+        for i in 0..10 {
+            assert!(tx.push_blocking(i).is_ok());
+        }
+        drop(tx);
+        assert_eq!(consumer.join().unwrap(), vec![0, 20, 40, 60, 80]);
+    }
+
+    #[test]
+    fn sink() {
+        use futures::{stream, StreamExt};
+
+        let (tx, rx) = SPSCRingBuffer::<u64>::new_split(4);
+        let consumer = thread::spawn(move || block_on(rx.collect::<Vec<_>>()));
+        // `forward` closes the sink at the end of the stream.
+        block_on(stream::iter(0..100).map(Ok).forward(tx)).unwrap();
+        assert_eq!(consumer.join().unwrap(), (0..100).collect::<Vec<_>>());
+    }
+}
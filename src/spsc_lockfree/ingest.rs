@@ -0,0 +1,124 @@
+//! Socket ingestion: the producer of a byte ring as the RX buffer of a
+//! hand-rolled event loop. Once a nonblocking socket is readable, `ingest`
+//! reads what is available straight into the vacant bytes and reports why
+//! it stopped, without waiting and without an intermediate buffer.
+
+use std::io::{self, Read};
+use std::net::UdpSocket;
+
+use super::Producer;
+use crate::wait::WaitStrategy;
+
+/// Outcome of `Producer::ingest` and `Producer::ingest_datagram`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ingest {
+    /// Bytes read into the ring, and published.
+    Read(usize),
+    /// Nothing to read for now: wait for the socket to be readable again.
+    WouldBlock,
+    /// The peer closed the stream.
+    Eof,
+    /// No vacant byte, nothing was read: let the consumer catch up.
+    Full,
+}
+
+impl<W: WaitStrategy> Producer<u8, W> {
+    /// Reads the bytes available from a nonblocking `source`, such as a
+    /// `TcpStream`, with a single `read_vectored` into the vacant bytes.
+    /// Fails with `BrokenPipe` once the consumer is dropped, or with
+    /// `source`'s error; interrupted reads are retried.
+    pub fn ingest<R: Read + ?Sized>(&mut self, source: &mut R) -> io::Result<Ingest> {
+        match self.ingest_with(|slices| source.read_vectored(slices))? {
+            Ingest::Read(0) => Ok(Ingest::Eof),
+            ingest => Ok(ingest),
+        }
+    }
+
+    /// Receives one datagram into the vacant bytes, up to the wrap point:
+    /// a longer datagram is truncated, as by `UdpSocket::recv` into a short
+    /// buffer. An empty datagram is `Read(0)`. Same errors as `ingest`.
+    pub fn ingest_datagram(&mut self, socket: &UdpSocket) -> io::Result<Ingest> {
+        self.ingest_with(|[first, _]| socket.recv(first))
+    }
+
+    fn ingest_with(&mut self, mut read: impl FnMut(&mut [io::IoSliceMut<'_>; 2]) -> io::Result<usize>) -> io::Result<Ingest> {
+        if self.is_disconnected() {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        if self.rb.vacant() == 0 {
+            return Ok(Ingest::Full);
+        }
+        loop {
+            let n = match read(&mut self.vacant_io_slices()) {
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(Ingest::WouldBlock),
+                Err(e) => return Err(e),
+            };
+            // The slices are initialized, and the first `n` bytes were
+            // read into them.
+            unsafe { self.advance_write(n) };
+            return Ok(Ingest::Read(n));
+        }
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use crate::spsc_lockfree::ByteRingBuffer;
+    use std::io::Write;
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    /// Ingests until something else than `WouldBlock` comes up.
+    fn ingest_ready(tx: &mut Producer<u8>, mut ingest: impl FnMut(&mut Producer<u8>) -> io::Result<Ingest>) -> Ingest {
+        loop {
+            match ingest(tx).unwrap() {
+                Ingest::WouldBlock => thread::yield_now(),
+                other => return other,
+            }
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri leaks in readv on its emulated sockets")]
+    fn tcp_stream() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut server, _) = listener.accept().unwrap();
+        server.set_nonblocking(true).unwrap();
+        let (mut tx, mut rx) = ByteRingBuffer::new_split(4);
+        assert_eq!(tx.ingest(&mut server).unwrap(), Ingest::WouldBlock);
+
+        client.write_all(b"abcdef").unwrap();
+        assert_eq!(ingest_ready(&mut tx, |tx| tx.ingest(&mut server)), Ingest::Read(4));
+        assert_eq!(tx.ingest(&mut server).unwrap(), Ingest::Full);
+        let mut out = [0; 6];
+        rx.read_exact(&mut out[..4]).unwrap();
+        assert_eq!(ingest_ready(&mut tx, |tx| tx.ingest(&mut server)), Ingest::Read(2));
+        rx.read_exact(&mut out[4..]).unwrap();
+        assert_eq!(&out, b"abcdef");
+        drop(client);
+        assert_eq!(ingest_ready(&mut tx, |tx| tx.ingest(&mut server)), Ingest::Eof);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri only emulates TCP sockets")]
+    fn udp_datagrams() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_nonblocking(true).unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let (mut tx, mut rx) = ByteRingBuffer::new_split(8);
+        assert_eq!(tx.ingest_datagram(&socket).unwrap(), Ingest::WouldBlock);
+        sender.send_to(b"ping", socket.local_addr().unwrap()).unwrap();
+        sender.send_to(b"truncated", socket.local_addr().unwrap()).unwrap();
+        assert_eq!(ingest_ready(&mut tx, |tx| tx.ingest_datagram(&socket)), Ingest::Read(4));
+        assert_eq!(ingest_ready(&mut tx, |tx| tx.ingest_datagram(&socket)), Ingest::Read(4));
+        let mut out = [0; 8];
+        rx.read_exact(&mut out).unwrap();
+        assert_eq!(&out, b"pingtrun");
+        drop(rx);
+        assert_eq!(tx.ingest_datagram(&socket).unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+    }
+}
@@ -0,0 +1,273 @@
+//! Byte rings: the halves of a `ByteRingBuffer` are a pipe between two
+//! threads, the producer implementing `io::Write` and the consumer
+//! `io::Read`, so the ring can sit between a parser and a socket without
+//! glue code. As with a pipe, `write` waits (with the producer's wait
+//! strategy) for room for at least one byte and `read` for at least one
+//! byte; `read` returns 0 at the end of the stream, once the producer is
+//! dropped and the ring drained, and `write` fails with `BrokenPipe` once
+//! the consumer is dropped.
+//! The vacant and readable bytes are also exposed in place, as the two
+//! `IoSliceMut`/`IoSlice` on either side of the wrap point, so a single
+//! `readv`/`writev` can fill or drain the whole ring, which is what
+//! `Producer::read_from` does with any reader, and `Consumer::write_to`
+//! with any writer.
+
+use core::slice;
+use std::io::{self, IoSlice, IoSliceMut, Read, Write};
+
+use super::{Consumer, Producer, SPSCRingBuffer};
+use crate::error::Disconnected;
+use crate::sync::Ordering;
+use crate::wait::WaitStrategy;
+
+/// A ring of bytes, see the `io` impls of its `Producer` and `Consumer`.
+pub type ByteRingBuffer = SPSCRingBuffer<u8>;
+
+impl<W> Producer<u8, W> {
+    /// Returns the vacant bytes as two slices, the second one non-empty
+    /// when they wrap around, e.g. for a `readv` straight into the ring.
+    /// Nothing is visible to the consumer until `advance_write`, which the
+    /// slices being fully initialized makes sound for as many bytes as
+    /// were written to them.
+    pub fn vacant_io_slices(&mut self) -> [IoSliceMut<'_>; 2] {
+        self.flush();
+        let start = self.rb.write.load(Ordering::Relaxed);
+        // The vacant slots are owned by this producer.
+        let (first, second) = unsafe { self.rb.slots(start, self.rb.vacant()) };
+        // Before the first wrap, the slots from `start` on were never
+        // written: zero them, so they can be viewed as bytes. The others
+        // keep the bytes of earlier laps, popping does not clear them.
+        if start < self.rb.capacity() {
+            for byte in first.iter_mut() {
+                byte.write(0);
+            }
+        }
+        // Initialized, see above.
+        let (first, second) = unsafe {
+            (
+                slice::from_raw_parts_mut(first.as_mut_ptr() as *mut u8, first.len()),
+                slice::from_raw_parts_mut(second.as_mut_ptr() as *mut u8, second.len()),
+            )
+        };
+        [IoSliceMut::new(first), IoSliceMut::new(second)]
+    }
+}
+
+impl<W: WaitStrategy> Producer<u8, W> {
+    /// Waits for at least one vacant byte. Fails with `BrokenPipe` once the
+    /// consumer is dropped.
+    pub(super) fn wait_vacant(&mut self) -> io::Result<()> {
+        self.flush();
+        let mut step = 0;
+        loop {
+            if self.is_disconnected() {
+                return Err(io::ErrorKind::BrokenPipe.into());
+            }
+            if self.rb.vacant() > 0 {
+                return Ok(());
+            }
+            self.wait.wait(step, None);
+            step = step.saturating_add(1);
+        }
+    }
+
+    /// Reads from `reader` straight into the vacant bytes, with a single
+    /// `read_vectored` over both sides of the wrap point, and publishes
+    /// what it read. Waits for at least one vacant byte first, so 0 is the
+    /// end of `reader`'s stream. Fails with `BrokenPipe` once the consumer
+    /// is dropped, or with `reader`'s error.
+    pub fn read_from<R: Read + ?Sized>(&mut self, reader: &mut R) -> io::Result<usize> {
+        self.wait_vacant()?;
+        let n = reader.read_vectored(&mut self.vacant_io_slices())?;
+        // The slices are initialized, and `reader` wrote the first `n`
+        // bytes (`advance_write` panics if it claims more than fit).
+        unsafe { self.advance_write(n) };
+        Ok(n)
+    }
+}
+
+impl<W: WaitStrategy> Write for Producer<u8, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_vectored(&[IoSlice::new(buf)])
+    }
+
+    /// Copies as many bytes of `bufs`, in order, as fit in the ring.
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        if bufs.iter().all(|buf| buf.is_empty()) {
+            return Ok(0);
+        }
+        self.wait_vacant()?;
+        let mut written = 0;
+        for buf in bufs {
+            let n = self.push_slice(buf).map_err(|Disconnected| io::Error::from(io::ErrorKind::BrokenPipe))?;
+            written += n;
+            if n < buf.len() {
+                break;
+            }
+        }
+        Ok(written)
+    }
+
+    /// Publishes the bytes pending from `push_batched`, the others are
+    /// visible as soon as written.
+    fn flush(&mut self) -> io::Result<()> {
+        Producer::flush(self);
+        Ok(())
+    }
+}
+
+impl<W: WaitStrategy> Consumer<u8, W> {
+    /// Returns the readable bytes as two slices, the second one non-empty
+    /// when they wrap around, e.g. for a `writev` straight from the ring.
+    /// Release them with `advance_read`.
+    pub fn occupied_io_slices(&self) -> [IoSlice<'_>; 2] {
+        let [(first, first_len), (second, second_len)] = self.occupied_regions();
+        // Published by the producer, which does not write to them until
+        // `advance_read`; borrowing `self` keeps the consumer from it, and
+        // the consumer is not `Sync`, so no other thread holds `self`.
+        unsafe {
+            [
+                IoSlice::new(slice::from_raw_parts(first, first_len)),
+                IoSlice::new(slice::from_raw_parts(second, second_len)),
+            ]
+        }
+    }
+
+    /// Waits for at least one readable byte. Returns false at the end of
+    /// the stream.
+    pub(super) fn wait_readable(&mut self) -> bool {
+        let mut step = 0;
+        loop {
+            if self.rb.occupied() > 0 {
+                return true;
+            }
+            if self.is_disconnected() {
+                // The producer may have pushed right before dropping.
+                return self.rb.occupied() > 0;
+            }
+            self.wait.wait(step, None);
+            step = step.saturating_add(1);
+        }
+    }
+
+    /// Writes the readable bytes straight to `writer`, with a single
+    /// `write_vectored` over both sides of the wrap point, and releases
+    /// what it wrote. Waits for at least one readable byte first, so 0 is
+    /// the end of the stream (or a writer accepting nothing). Fails with
+    /// `writer`'s error.
+    pub fn write_to<V: Write + ?Sized>(&mut self, writer: &mut V) -> io::Result<usize> {
+        if !self.wait_readable() {
+            return Ok(0);
+        }
+        let n = writer.write_vectored(&self.occupied_io_slices())?;
+        // `advance_read` panics if `writer` claims more than was readable.
+        self.advance_read(n);
+        Ok(n)
+    }
+}
+
+impl<W: WaitStrategy> Read for Consumer<u8, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.read_vectored(&mut [IoSliceMut::new(buf)])
+    }
+
+    /// Fills `bufs` in order with as many bytes as are readable.
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
+        if bufs.iter().all(|buf| buf.is_empty()) || !self.wait_readable() {
+            return Ok(0);
+        }
+        let mut read = 0;
+        for buf in bufs {
+            // Readable bytes are left, so it cannot fail.
+            let n = self.pop_slice(buf).unwrap_or(0);
+            read += n;
+            if n < buf.len() {
+                break;
+            }
+        }
+        Ok(read)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::thread;
+
+    #[test]
+    fn pipe_between_threads() {
+        let (mut tx, rx) = ByteRingBuffer::new_split(16);
+        let writer = thread::spawn(move || {
+            for i in 0..100 {
+                writeln!(tx, "line {i}").unwrap();
+            }
+        });
+        let lines: Vec<_> = BufReader::new(rx).lines().map(Result::unwrap).collect();
+        writer.join().unwrap();
+        assert_eq!(lines.len(), 100);
+        assert_eq!(lines[99], "line 99");
+    }
+
+    #[test]
+    fn broken_pipe() {
+        let (mut tx, rx) = ByteRingBuffer::new_split(4);
+        assert_eq!(tx.write(b"abcdef").unwrap(), 4);
+        drop(rx);
+        assert_eq!(tx.write(b"ef").unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn vectored() {
+        let (mut tx, mut rx) = ByteRingBuffer::new_split(8);
+        let n = tx.write_vectored(&[IoSlice::new(b"abc"), IoSlice::new(b""), IoSlice::new(b"defghij")]).unwrap();
+        assert_eq!(n, 8);
+        let (mut a, mut b) = ([0; 2], [0; 4]);
+        assert_eq!(rx.read_vectored(&mut [IoSliceMut::new(&mut a), IoSliceMut::new(&mut b)]).unwrap(), 6);
+        assert_eq!((&a, &b), (b"ab", b"cdef"));
+
+        assert_eq!(tx.vacant_io_slices().map(|s| s.len()), [6, 0]);
+        assert_eq!(tx.write(b"klmnop").unwrap(), 6);
+        // The readable bytes wrap around: 6..8, then 0..6.
+        let [first, second] = rx.occupied_io_slices();
+        assert_eq!((&*first, &*second), (&b"gh"[..], &b"klmnop"[..]));
+        rx.advance_read(8);
+        let [mut first, second] = tx.vacant_io_slices();
+        assert_eq!((first.len(), second.len()), (2, 6));
+        first.copy_from_slice(b"qr");
+        unsafe { tx.advance_write(2) };
+        assert_eq!(rx.read(&mut a).unwrap(), 2);
+        assert_eq!(&a, b"qr");
+    }
+
+    #[test]
+    fn read_from() {
+        let (mut tx, mut rx) = ByteRingBuffer::new_split(8);
+        assert_eq!(tx.write(b"abcde").unwrap(), 5);
+        let mut out = [0; 3];
+        rx.read_exact(&mut out).unwrap();
+        // Lands on both sides of the wrap point: 5..8, then 0..3.
+        let mut source: &[u8] = b"fghijk";
+        assert_eq!(tx.read_from(&mut source).unwrap(), 6);
+        rx.read_exact(&mut out).unwrap();
+        assert_eq!(tx.read_from(&mut source).unwrap(), 0);
+        drop(tx);
+        let mut rest = Vec::new();
+        rx.read_to_end(&mut rest).unwrap();
+        assert_eq!((&out, &rest[..]), (b"def", &b"ghijk"[..]));
+    }
+
+    #[test]
+    fn write_to() {
+        let (mut tx, mut rx) = ByteRingBuffer::new_split(8);
+        assert_eq!(tx.write(b"abcdef").unwrap(), 6);
+        let mut sink = Vec::new();
+        assert_eq!(rx.write_to(&mut sink).unwrap(), 6);
+        // Drains both sides of the wrap point: 6..8, then 0..3.
+        assert_eq!(tx.write(b"ghijk").unwrap(), 5);
+        assert_eq!(rx.write_to(&mut sink).unwrap(), 5);
+        drop(tx);
+        assert_eq!(rx.write_to(&mut sink).unwrap(), 0);
+        assert_eq!(sink, b"abcdefghijk");
+    }
+}
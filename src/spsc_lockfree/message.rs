@@ -0,0 +1,240 @@
+//! Message rings: variable-length byte messages, such as IPC datagrams,
+//! each stored in a byte ring as a 4-byte little-endian length followed by
+//! its bytes, padded to a multiple of 4. A message never wraps around: one
+//! that does not fit before the end of the buffer is preceded by a padding
+//! record skipping the rest of it, so `pop_msg` can always lend it as a
+//! single slice. This limits messages to about half the capacity, see
+//! `max_msg_len`.
+
+use alloc::vec::Vec;
+use core::mem::MaybeUninit;
+use core::ops::Deref;
+use core::ptr;
+
+use super::{Consumer, Producer, ReadChunk, SPSCRingBuffer};
+use crate::error::{ChunkError, MessageError, PopError};
+
+/// Length of the header of a record.
+const HEADER: usize = 4;
+/// Header of a padding record, which runs to the end of the buffer.
+const PADDING: u32 = u32::MAX;
+
+/// Bytes taken in the ring by a message of `len` bytes.
+fn record_len(len: usize) -> usize {
+    (HEADER + len).next_multiple_of(HEADER)
+}
+
+/// Writes the record of `msg` with `header` to `slots`, zeroing the bytes
+/// after `msg`.
+fn write_record(slots: &mut [MaybeUninit<u8>], header: u32, msg: &[u8]) {
+    let (head, rest) = slots.split_at_mut(HEADER);
+    let (body, padding) = rest.split_at_mut(msg.len());
+    for (slot, byte) in head.iter_mut().zip(header.to_le_bytes()) {
+        slot.write(byte);
+    }
+    // `MaybeUninit<u8>` has the layout of `u8`.
+    unsafe { ptr::copy_nonoverlapping(msg.as_ptr(), body.as_mut_ptr() as *mut u8, msg.len()) };
+    for slot in padding {
+        slot.write(0);
+    }
+}
+
+/// A ring of length-prefixed byte messages, to split into a
+/// `MessageProducer` and a `MessageConsumer`.
+pub struct MessageRingBuffer {
+    rb: SPSCRingBuffer<u8>,
+}
+
+impl MessageRingBuffer {
+    /// Creates a ring of `capacity` bytes, rounded up to the next power of
+    /// two, and at least 16.
+    pub fn new(capacity: usize) -> Self {
+        MessageRingBuffer { rb: SPSCRingBuffer::new(capacity.max(16)) }
+    }
+
+    /// Creates a ring and splits it into its two owned halves.
+    pub fn new_split(capacity: usize) -> (MessageProducer, MessageConsumer) {
+        Self::new(capacity).split()
+    }
+
+    /// Splits the ring into halves that can be moved to different threads.
+    /// Dropping one half disconnects the other.
+    pub fn split(self) -> (MessageProducer, MessageConsumer) {
+        let (producer, consumer) = self.rb.split();
+        (MessageProducer { producer }, MessageConsumer { consumer })
+    }
+}
+
+/// The pushing half of a split `MessageRingBuffer`.
+pub struct MessageProducer {
+    producer: Producer<u8>,
+}
+
+impl MessageProducer {
+    /// Pushes a copy of `msg`, published at once with its header. Fails
+    /// with `MessageError::Full` while there is not enough room for it, and
+    /// `TooLong` if there never will be.
+    pub fn push_msg(&mut self, msg: &[u8]) -> Result<(), MessageError> {
+        let max = self.max_msg_len();
+        if msg.len() > max {
+            return Err(MessageError::TooLong(max));
+        }
+        let record = record_len(msg.len());
+        let mut chunk = self.producer.write_chunk(record).map_err(from_chunk_error)?;
+        let tail = chunk.as_mut_slices().0.len();
+        if tail < record {
+            // Pad to the end of the buffer, the message goes at the start.
+            chunk = self.producer.write_chunk(tail + record).map_err(from_chunk_error)?;
+            let (first, second) = chunk.as_mut_slices();
+            write_record(first, PADDING, &[]);
+            write_record(second, msg.len() as u32, msg);
+        } else {
+            write_record(chunk.as_mut_slices().0, msg.len() as u32, msg);
+        }
+        // All the reserved bytes were written above.
+        let len = chunk.len();
+        unsafe { chunk.commit(len) };
+        Ok(())
+    }
+
+    /// Returns the length of the longest message, a bit under half the
+    /// capacity so that it fits without wrapping around wherever the
+    /// previous one ended.
+    pub fn max_msg_len(&self) -> usize {
+        self.producer.capacity() / 2 - HEADER
+    }
+
+    /// Returns true if the `MessageConsumer` was dropped.
+    pub fn is_disconnected(&self) -> bool {
+        self.producer.is_disconnected()
+    }
+}
+
+fn from_chunk_error(e: ChunkError) -> MessageError {
+    match e {
+        ChunkError::TooFewSlots(_) => MessageError::Full,
+        ChunkError::Disconnected => MessageError::Disconnected,
+    }
+}
+
+/// The popping half of a split `MessageRingBuffer`.
+pub struct MessageConsumer {
+    consumer: Consumer<u8>,
+}
+
+impl MessageConsumer {
+    /// Lends the oldest message in place; it is released when the
+    /// `Message` is dropped. Messages pushed before the producer was
+    /// dropped are still delivered, `Disconnected` is only returned once
+    /// the ring is drained.
+    pub fn pop_msg(&mut self) -> Result<Message<'_>, PopError> {
+        let len = loop {
+            let chunk = self.consumer.read_chunk()?;
+            match header(&chunk) {
+                // A padding record is always followed by a message,
+                // published with it.
+                Some(PADDING) => {
+                    let tail = chunk.as_slices().0.len();
+                    chunk.commit(tail);
+                }
+                Some(len) => break len as usize,
+                None => return Err(PopError::Empty),
+            }
+        };
+        // Only this half releases records, so the header read above is
+        // still the oldest one.
+        let chunk = self.consumer.read_chunk()?;
+        Ok(Message { chunk: Some(chunk), len })
+    }
+
+    /// Pops the oldest message into a `Vec`.
+    pub fn pop_msg_vec(&mut self) -> Result<Vec<u8>, PopError> {
+        self.pop_msg().map(|msg| msg.to_vec())
+    }
+
+    /// Returns true if the `MessageProducer` was dropped.
+    pub fn is_disconnected(&self) -> bool {
+        self.consumer.is_disconnected()
+    }
+}
+
+/// Returns the header of the oldest record, if any. Records start at
+/// multiples of 4, so a header never wraps around.
+fn header(chunk: &ReadChunk<'_, u8>) -> Option<u32> {
+    let head = chunk.as_slices().0.get(..HEADER)?;
+    Some(u32::from_le_bytes(head.try_into().unwrap()))
+}
+
+/// A message lent by `MessageConsumer::pop_msg`, released when dropped.
+pub struct Message<'a> {
+    /// Always set until dropped.
+    chunk: Option<ReadChunk<'a, u8>>,
+    len: usize,
+}
+
+impl Deref for Message<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        let chunk = self.chunk.as_ref().expect("the message was released");
+        &chunk.as_slices().0[HEADER..HEADER + self.len]
+    }
+}
+
+impl Drop for Message<'_> {
+    fn drop(&mut self) {
+        if let Some(chunk) = self.chunk.take() {
+            chunk.commit(record_len(self.len));
+        }
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn padding_at_the_wrap_point() {
+        let (mut tx, mut rx) = MessageRingBuffer::new_split(32);
+        assert_eq!(tx.max_msg_len(), 12);
+        assert_eq!(tx.push_msg(&[0; 13]), Err(MessageError::TooLong(12)));
+        tx.push_msg(b"hello").unwrap(); // 0..12
+        tx.push_msg(b"world!!!").unwrap(); // 12..24
+        assert_eq!(&*rx.pop_msg().unwrap(), b"hello");
+        // 8 bytes left before the end: padded, then at 0..16.
+        assert_eq!(tx.push_msg(b"wrapped"), Ok(()));
+        assert_eq!(tx.push_msg(b"full"), Err(MessageError::Full));
+        assert_eq!(rx.pop_msg_vec().unwrap(), b"world!!!");
+        assert_eq!(&*rx.pop_msg().unwrap(), b"wrapped");
+        assert_eq!(rx.pop_msg().err(), Some(PopError::Empty));
+        tx.push_msg(b"").unwrap();
+        drop(tx);
+        assert_eq!(rx.pop_msg_vec(), Ok(Vec::new()));
+        assert_eq!(rx.pop_msg().err(), Some(PopError::Disconnected));
+    }
+
+    #[test]
+    fn messages_between_threads() {
+        let (mut tx, mut rx) = MessageRingBuffer::new_split(64);
+        let producer = thread::spawn(move || {
+            for i in 0..500usize {
+                let msg = vec![i as u8; i % 29];
+                while let Err(e) = tx.push_msg(&msg) {
+                    assert_eq!(e, MessageError::Full);
+                    thread::yield_now();
+                }
+            }
+        });
+        for i in 0..500usize {
+            let msg = loop {
+                match rx.pop_msg() {
+                    Ok(msg) => break msg,
+                    Err(_) => thread::yield_now(),
+                }
+            };
+            assert_eq!(&*msg, &vec![i as u8; i % 29][..]);
+        }
+        producer.join().unwrap();
+    }
+}
@@ -0,0 +1,141 @@
+//! SPSC halves for the tokio runtime, woken through `tokio::sync::Notify`.
+//! There is exactly one waiter per `Notify`, and `notify_one` keeps a
+//! permit when nobody waits yet, so a wakeup sent between a failed attempt
+//! and the `.await` is never lost.
+
+use ::tokio::sync::Notify;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use super::{Consumer, Producer, SPSCRingBuffer};
+use crate::error::{PopError, PushError};
+
+#[derive(Default)]
+struct Notifiers {
+    /// Notified after a push, the consumer waits on it.
+    data: Notify,
+    /// Notified after a pop, the producer waits on it.
+    space: Notify,
+}
+
+impl<T> SPSCRingBuffer<T> {
+    /// Splits the ring buffer into async halves for tokio tasks.
+    pub fn tokio_split(self) -> (TokioProducer<T>, TokioConsumer<T>) {
+        let (producer, consumer) = self.split();
+        let notify = Arc::new(Notifiers::default());
+        (
+            TokioProducer { inner: producer, notify: notify.clone() },
+            TokioConsumer { inner: consumer, notify },
+        )
+    }
+}
+
+/// The pushing half returned by `SPSCRingBuffer::tokio_split`.
+pub struct TokioProducer<T> {
+    inner: Producer<T>,
+    notify: Arc<Notifiers>,
+}
+
+impl<T> TokioProducer<T> {
+    /// Pushes a value, waiting for a vacant slot. Fails only with
+    /// `Disconnected`, which hands the value back.
+    pub async fn push(&mut self, value: T) -> Result<usize, PushError<T>> {
+        loop {
+            if self.inner.is_disconnected() {
+                return Err(PushError::Disconnected(value));
+            }
+            if self.inner.rb.vacant() > 0 {
+                return self.try_push(value);
+            }
+            self.notify.space.notified().await;
+        }
+    }
+
+    /// Pushes a value without waiting, see `Producer::push`.
+    pub fn try_push(&mut self, value: T) -> Result<usize, PushError<T>> {
+        let slot = self.inner.push(value)?;
+        self.notify.data.notify_one();
+        Ok(slot)
+    }
+
+    pub fn is_disconnected(&self) -> bool {
+        self.inner.is_disconnected()
+    }
+}
+
+impl<T> Drop for TokioProducer<T> {
+    fn drop(&mut self) {
+        // `inner` only sets the flag after this runs, the consumer must see
+        // it when it wakes up.
+        self.inner.rb.disconnected.store(true, Ordering::Release);
+        self.notify.data.notify_one();
+    }
+}
+
+/// The popping half returned by `SPSCRingBuffer::tokio_split`.
+pub struct TokioConsumer<T> {
+    inner: Consumer<T>,
+    notify: Arc<Notifiers>,
+}
+
+impl<T> TokioConsumer<T> {
+    /// Pops the oldest value, waiting for one to be pushed. Fails only with
+    /// `Disconnected`.
+    pub async fn pop(&mut self) -> Result<T, PopError> {
+        loop {
+            match self.try_pop() {
+                Err(PopError::Empty) => self.notify.data.notified().await,
+                result => return result,
+            }
+        }
+    }
+
+    /// Pops a value without waiting, see `Consumer::pop`.
+    pub fn try_pop(&mut self) -> Result<T, PopError> {
+        let value = self.inner.pop()?;
+        self.notify.space.notify_one();
+        Ok(value)
+    }
+
+    pub fn is_disconnected(&self) -> bool {
+        self.inner.is_disconnected()
+    }
+}
+
+impl<T> Drop for TokioConsumer<T> {
+    fn drop(&mut self) {
+        self.inner.rb.disconnected.store(true, Ordering::Release);
+        self.notify.space.notify_one();
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn tokio_split() {
+        let (mut tx, mut rx) = SPSCRingBuffer::<u64>::new(4).tokio_split();
+        let producer = ::tokio::spawn(async move {
+            for i in 0..1000 {
+                tx.push(i).await.unwrap();
+            }
+        });
+        let mut sum = 0;
+        while let Ok(v) = rx.pop().await {
+            sum += v;
+        }
+        producer.await.unwrap();
+        assert_eq!(sum, 999 * 1000 / 2);
+    }
+
+    #[tokio::test]
+    async fn consumer_drop_wakes_producer() {
+        let (mut tx, rx) = SPSCRingBuffer::<u64>::new(1).tokio_split();
+        tx.push(1).await.unwrap();
+        let producer = ::tokio::spawn(async move { tx.push(2).await });
+        ::tokio::task::yield_now().await;
+        drop(rx);
+        assert_eq!(producer.await.unwrap(), Err(PushError::Disconnected(2)));
+    }
+}
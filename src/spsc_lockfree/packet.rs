@@ -0,0 +1,135 @@
+//! Packet rings: each slot is a fixed, MTU-sized buffer with the length
+//! and the source address of the datagram in it, so that `recv_into`
+//! receives straight into the next vacant slot, with no copy and no
+//! allocation, as packet capture and game-server netcode need. The
+//! consumer pops whole `Packet`s, or reads them in place with
+//! `read_chunk`.
+
+use core::ptr;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+
+use super::{Ingest, Producer, SPSCRingBuffer};
+use crate::error::ChunkError;
+use crate::sync::Ordering;
+use crate::wait::WaitStrategy;
+
+/// A datagram in a slot of a packet ring, of at most `MTU` bytes.
+#[derive(Clone, Copy)]
+pub struct Packet<const MTU: usize = 1500> {
+    len: usize,
+    addr: SocketAddr,
+    data: [u8; MTU],
+}
+
+impl<const MTU: usize> Packet<MTU> {
+    /// Returns the bytes of the datagram, truncated to `MTU`.
+    pub fn payload(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+
+    /// Returns the address the datagram came from.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// A ring of packets, see `Producer::recv_into`.
+pub type PacketRingBuffer<const MTU: usize = 1500> = SPSCRingBuffer<Packet<MTU>>;
+
+impl<const MTU: usize, W: WaitStrategy> Producer<Packet<MTU>, W> {
+    /// Receives one datagram straight into the next vacant slot, and
+    /// publishes it. A datagram longer than `MTU` is truncated, as by
+    /// `UdpSocket::recv_from` into a short buffer. Returns `Read` with the
+    /// packet's length, `WouldBlock` if the nonblocking `socket` has
+    /// nothing for now, or `Full`. Fails with `BrokenPipe` once the
+    /// consumer is dropped, or with `socket`'s error; interrupted receives
+    /// are retried.
+    pub fn recv_into(&mut self, socket: &UdpSocket) -> io::Result<Ingest> {
+        self.flush();
+        // Before the first wrap, the slot was never written.
+        let fresh = self.rb.write.load(Ordering::Relaxed) < self.rb.capacity();
+        let mut chunk = match self.write_chunk(1) {
+            Ok(chunk) => chunk,
+            Err(ChunkError::TooFewSlots(_)) => return Ok(Ingest::Full),
+            Err(ChunkError::Disconnected) => return Err(io::ErrorKind::BrokenPipe.into()),
+        };
+        let slot = chunk.as_mut_slices().0[0].as_mut_ptr();
+        // The slot is vacant and owned by this producer. Its buffer is
+        // zeroed before its first use so it can be viewed as bytes; after
+        // that it keeps the bytes of earlier laps, popping does not clear
+        // them.
+        let data = unsafe {
+            if fresh {
+                ptr::addr_of_mut!((*slot).data).write([0; MTU]);
+            }
+            &mut *ptr::addr_of_mut!((*slot).data)
+        };
+        let (len, addr) = loop {
+            match socket.recv_from(data) {
+                Ok(received) => break received,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(Ingest::WouldBlock),
+                Err(e) => return Err(e),
+            }
+        };
+        // All the fields are initialized now.
+        unsafe {
+            ptr::addr_of_mut!((*slot).len).write(len);
+            ptr::addr_of_mut!((*slot).addr).write(addr);
+            chunk.commit(1);
+        }
+        Ok(Ingest::Read(len))
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn recv_ready<const MTU: usize>(tx: &mut Producer<Packet<MTU>>, socket: &UdpSocket) -> Ingest {
+        loop {
+            match tx.recv_into(socket).unwrap() {
+                Ingest::WouldBlock => thread::yield_now(),
+                other => return other,
+            }
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri only emulates TCP sockets")]
+    fn datagrams_into_slots() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_nonblocking(true).unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let (mut tx, mut rx) = PacketRingBuffer::<8>::new_split(2);
+        assert_eq!(tx.recv_into(&socket).unwrap(), Ingest::WouldBlock);
+        for datagram in [&b"move"[..], b"jump", b"truncated", b""] {
+            sender.send_to(datagram, socket.local_addr().unwrap()).unwrap();
+        }
+        assert_eq!(recv_ready(&mut tx, &socket), Ingest::Read(4));
+        assert_eq!(recv_ready(&mut tx, &socket), Ingest::Read(4));
+        assert_eq!(tx.recv_into(&socket).unwrap(), Ingest::Full);
+
+        let packet = rx.pop().unwrap();
+        assert_eq!(packet.payload(), b"move");
+        assert_eq!(packet.addr(), sender.local_addr().unwrap());
+        // Into the slot of "move".
+        assert_eq!(recv_ready(&mut tx, &socket), Ingest::Read(8));
+        assert_eq!(rx.pop().unwrap().payload(), b"jump");
+        assert_eq!(rx.pop().unwrap().payload(), b"truncate");
+        assert_eq!(recv_ready(&mut tx, &socket), Ingest::Read(0));
+        assert!(rx.pop().unwrap().is_empty());
+        drop(rx);
+        assert_eq!(tx.recv_into(&socket).unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+    }
+}
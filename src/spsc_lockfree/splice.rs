@@ -0,0 +1,163 @@
+//! Linux `splice` fast paths between file descriptors and the byte ring,
+//! for proxy-style workloads. The kernel can only splice to or from a
+//! pipe, so each direction goes through a `SplicePipe`: `splice_from`
+//! splices `src` (a file, a socket or a pipe) into the pipe without
+//! copying, then `vmsplice`s the pipe into the vacant bytes, one copy in
+//! all and no read buffer; `splice_to` writes the readable bytes into the
+//! pipe and splices the pipe to `dst` (a socket, a file or a pipe) without
+//! copying. Unlike a `vmsplice` into the pipe, that write leaves no
+//! reference to the ring's pages in the kernel, so the producer can reuse
+//! the bytes as soon as they are released.
+//! Use one `SplicePipe` per direction: a pipe may hold bytes that were not
+//! delivered yet, see `SplicePipe::pending`.
+
+use std::io;
+use std::os::fd::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd};
+use std::ptr;
+
+use super::{Consumer, Producer};
+use crate::wait::WaitStrategy;
+
+/// The kernel pipe a splice goes through.
+#[derive(Debug)]
+pub struct SplicePipe {
+    read: OwnedFd,
+    write: OwnedFd,
+    pending: usize,
+}
+
+impl SplicePipe {
+    pub fn new() -> io::Result<Self> {
+        let mut fds = [0; 2];
+        cvt(unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC | libc::O_NONBLOCK) } as isize)?;
+        // Fresh descriptors, owned from now on.
+        let (read, write) = unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+        Ok(SplicePipe { read, write, pending: 0 })
+    }
+
+    /// Returns the number of bytes in the pipe, left by a previous splice
+    /// that could not deliver all of them; the next one delivers them
+    /// first.
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+}
+
+fn cvt(ret: isize) -> io::Result<usize> {
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(ret as usize)
+}
+
+/// Retries `f` while it is interrupted.
+fn retry(mut f: impl FnMut() -> isize) -> io::Result<usize> {
+    loop {
+        match cvt(f()) {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            result => return result,
+        }
+    }
+}
+
+impl<W: WaitStrategy> Producer<u8, W> {
+    /// Splices bytes from `src` into the vacant bytes, through `pipe`, and
+    /// publishes them. Waits for at least one vacant byte first, so 0 is
+    /// the end of `src`. Fails with `BrokenPipe` once the consumer is
+    /// dropped, or with the error of the splice, e.g. `WouldBlock` for a
+    /// nonblocking `src` with nothing to read.
+    pub fn splice_from(&mut self, src: BorrowedFd<'_>, pipe: &mut SplicePipe) -> io::Result<usize> {
+        self.wait_vacant()?;
+        if pipe.pending == 0 {
+            let (src, into, vacant) = (src.as_raw_fd(), pipe.write.as_raw_fd(), self.rb.vacant());
+            let flags = libc::SPLICE_F_MOVE;
+            pipe.pending = retry(|| unsafe { libc::splice(src, ptr::null_mut(), into, ptr::null_mut(), vacant, flags) })?;
+            if pipe.pending == 0 {
+                return Ok(0);
+            }
+        }
+        // No byte was written since the pipe was filled up to the vacant
+        // bytes of then, and the consumer only frees more.
+        let mut left = pipe.pending;
+        let iovecs = self.vacant_io_slices().map(|slice| {
+            let len = slice.len().min(left);
+            left -= len;
+            libc::iovec { iov_base: slice.as_ptr() as *mut libc::c_void, iov_len: len }
+        });
+        let from = pipe.read.as_raw_fd();
+        let n = retry(|| unsafe { libc::vmsplice(from, iovecs.as_ptr(), iovecs.len(), 0) })?;
+        pipe.pending -= n;
+        // The slices are initialized, and the kernel wrote the first `n`
+        // bytes.
+        unsafe { self.advance_write(n) };
+        Ok(n)
+    }
+}
+
+impl<W: WaitStrategy> Consumer<u8, W> {
+    /// Moves readable bytes to `dst`, through `pipe`, releasing them once
+    /// they are in the pipe. Waits for at least one readable byte first,
+    /// so 0 is the end of the stream. Returns the number of bytes spliced
+    /// to `dst`, fails with the error of the splice, e.g. `WouldBlock` for
+    /// a nonblocking `dst` that is full.
+    pub fn splice_to(&mut self, dst: BorrowedFd<'_>, pipe: &mut SplicePipe) -> io::Result<usize> {
+        if pipe.pending == 0 {
+            if !self.wait_readable() {
+                return Ok(0);
+            }
+            let into = pipe.write.as_raw_fd();
+            // `IoSlice` is ABI-compatible with `iovec`.
+            let n = {
+                let slices = self.occupied_io_slices();
+                retry(|| unsafe { libc::writev(into, slices.as_ptr() as *const libc::iovec, 2) })?
+            };
+            self.advance_read(n);
+            pipe.pending = n;
+        }
+        let (from, dst, pending) = (pipe.read.as_raw_fd(), dst.as_raw_fd(), pipe.pending);
+        let flags = libc::SPLICE_F_MOVE;
+        let n = retry(|| unsafe { libc::splice(from, ptr::null_mut(), dst, ptr::null_mut(), pending, flags) })?;
+        pipe.pending -= n;
+        Ok(n)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use crate::spsc_lockfree::ByteRingBuffer;
+    use std::fs::{self, File};
+    use std::io::Read;
+    use std::os::fd::AsFd;
+    use std::os::unix::net::UnixStream;
+
+    #[test]
+    fn file_to_ring_to_socket() {
+        let path = std::env::temp_dir().join(format!("ringbuf-splice-{}", std::process::id()));
+        fs::write(&path, b"spliced through the ring").unwrap();
+        let file = File::open(&path).unwrap();
+        let (mut client, server) = UnixStream::pair().unwrap();
+        let (mut tx, mut rx) = ByteRingBuffer::new_split(16);
+        let (mut inbound, mut outbound) = (SplicePipe::new().unwrap(), SplicePipe::new().unwrap());
+
+        let mut received = Vec::new();
+        loop {
+            let n = tx.splice_from(file.as_fd(), &mut inbound).unwrap();
+            if n == 0 {
+                break;
+            }
+            assert!(n <= 16);
+            while !rx.is_empty() {
+                let sent = rx.splice_to(server.as_fd(), &mut outbound).unwrap();
+                let mut buf = vec![0; sent];
+                client.read_exact(&mut buf).unwrap();
+                received.extend(buf);
+            }
+        }
+        assert_eq!(received, b"spliced through the ring");
+        assert_eq!((inbound.pending(), outbound.pending()), (0, 0));
+        drop(tx);
+        assert_eq!(rx.splice_to(server.as_fd(), &mut outbound).unwrap(), 0);
+        fs::remove_file(&path).unwrap();
+    }
+}
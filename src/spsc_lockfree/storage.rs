@@ -0,0 +1,317 @@
+//! Allocation of the slots of the lock-free SPSC ring, tuned through
+//! `StorageOptions` and `SPSCRingBuffer::with_storage`.
+
+use alloc::alloc::{alloc, dealloc, handle_alloc_error, Layout};
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::ops::{Deref, DerefMut};
+use core::ptr::{self, NonNull};
+use core::slice;
+
+/// Size of a cache line on the targets we care about.
+pub(super) const CACHE_LINE: usize = 64;
+
+/// Size of a (PMD) huge page on x86_64 and aarch64 with 4 KiB pages.
+#[cfg(all(feature = "huge-pages", target_os = "linux"))]
+const HUGE_PAGE: usize = 2 << 20;
+
+/// Alignment of the slots.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AlignedStorage {
+    /// The alignment of `T`, as in a `Vec<T>`.
+    #[default]
+    Natural,
+    /// Starts on a cache line and is padded to whole lines, so no slot
+    /// shares a line with unrelated heap neighbors.
+    CacheLine,
+}
+
+/// Pages backing the slots, to cut TLB misses on multi-megabyte rings.
+/// The mapping is rounded up to whole huge pages.
+#[cfg(all(feature = "huge-pages", target_os = "linux"))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HugePages {
+    /// Regular heap allocation.
+    #[default]
+    Off,
+    /// Anonymous mapping advised with `MADV_HUGEPAGE`, backed by transparent
+    /// huge pages when the kernel has some available.
+    Transparent,
+    /// `MAP_HUGETLB` mapping from the reserved pool (`vm.nr_hugepages`),
+    /// falls back to `Transparent` when the pool is exhausted.
+    Explicit,
+}
+
+/// How the slots of a ring are allocated.
+/// `StorageOptions::default()` allocates them as a `Vec` would.
+#[derive(Clone, Debug, Default)]
+pub struct StorageOptions {
+    aligned: AlignedStorage,
+    #[cfg(all(feature = "huge-pages", target_os = "linux"))]
+    huge_pages: HugePages,
+    #[cfg(all(feature = "numa", target_os = "linux"))]
+    numa_node: Option<u32>,
+}
+
+impl StorageOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the alignment of the slots.
+    pub fn aligned(mut self, aligned: AlignedStorage) -> Self {
+        self.aligned = aligned;
+        self
+    }
+
+    /// Sets the pages backing the slots.
+    #[cfg(all(feature = "huge-pages", target_os = "linux"))]
+    pub fn huge_pages(mut self, huge_pages: HugePages) -> Self {
+        self.huge_pages = huge_pages;
+        self
+    }
+
+    /// Prefers memory of the NUMA `node` for the slots, e.g. the node of the
+    /// consumer when the producer is pinned to another socket. The pages are
+    /// only allocated on first touch, so this holds whichever side touches
+    /// them first. It is a hint: other nodes are used when `node` is out of
+    /// memory, and it is ignored without NUMA support.
+    #[cfg(all(feature = "numa", target_os = "linux"))]
+    pub fn numa_node(mut self, node: u32) -> Self {
+        self.numa_node = Some(node);
+        self
+    }
+
+    /// True if the slots must be in a mapping of their own.
+    #[cfg(all(any(feature = "huge-pages", feature = "numa"), target_os = "linux"))]
+    fn mapped(&self) -> bool {
+        #[cfg(feature = "huge-pages")]
+        if self.huge_pages != HugePages::Off {
+            return true;
+        }
+        #[cfg(feature = "numa")]
+        if self.numa_node.is_some() {
+            return true;
+        }
+        false
+    }
+
+    fn layout<T>(&self, len: usize) -> Layout {
+        let layout = Layout::array::<T>(len).expect("ring buffer too large");
+        match self.aligned {
+            AlignedStorage::Natural => layout,
+            AlignedStorage::CacheLine => layout
+                .align_to(CACHE_LINE)
+                .expect("ring buffer too large")
+                .pad_to_align(),
+        }
+    }
+}
+
+/// The slots of a ring: a boxed slice with a custom layout.
+pub(crate) struct Storage<T> {
+    ptr: NonNull<UnsafeCell<MaybeUninit<T>>>,
+    len: usize,
+    layout: Layout,
+    /// Length of the mapping holding the slots, 0 if they are on the heap.
+    #[cfg(all(any(feature = "huge-pages", feature = "numa"), target_os = "linux"))]
+    mapped: usize,
+}
+
+// Same as `Box<[T]>`.
+unsafe impl<T: Send> Send for Storage<T> {}
+unsafe impl<T: Sync> Sync for Storage<T> {}
+
+impl<T> Storage<T> {
+    pub(crate) fn new(len: usize, options: &StorageOptions) -> Self {
+        let layout = options.layout::<T>(len);
+        if layout.size() == 0 {
+            // Never read nor freed, it only has to be aligned.
+            let ptr = ptr::without_provenance_mut(layout.align());
+            return Self::with_ptr(NonNull::new(ptr).unwrap(), len, layout);
+        }
+        #[cfg(all(any(feature = "huge-pages", feature = "numa"), target_os = "linux"))]
+        if options.mapped() {
+            return Self::map(len, layout, options);
+        }
+        let ptr = unsafe { alloc(layout) } as *mut UnsafeCell<MaybeUninit<T>>;
+        let Some(ptr) = NonNull::new(ptr) else { handle_alloc_error(layout) };
+        Self::with_ptr(ptr, len, layout)
+    }
+
+    fn with_ptr(ptr: NonNull<UnsafeCell<MaybeUninit<T>>>, len: usize, layout: Layout) -> Self {
+        Storage {
+            ptr,
+            len,
+            layout,
+            #[cfg(all(any(feature = "huge-pages", feature = "numa"), target_os = "linux"))]
+            mapped: 0,
+        }
+    }
+
+    /// Maps whole pages for the slots, huge ones if asked to. Pages are
+    /// always aligned far beyond `AlignedStorage::CacheLine`.
+    #[cfg(all(any(feature = "huge-pages", feature = "numa"), target_os = "linux"))]
+    fn map(len: usize, layout: Layout, options: &StorageOptions) -> Self {
+        let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        assert!(layout.align() <= page, "slots too aligned for a mapping");
+        #[cfg(feature = "huge-pages")]
+        let page = if options.huge_pages == HugePages::Off { page } else { HUGE_PAGE };
+        let size = layout.size().next_multiple_of(page);
+        let prot = libc::PROT_READ | libc::PROT_WRITE;
+        let flags = libc::MAP_PRIVATE | libc::MAP_ANONYMOUS;
+        let mut ptr = libc::MAP_FAILED;
+        #[cfg(feature = "huge-pages")]
+        if options.huge_pages == HugePages::Explicit {
+            ptr = unsafe { libc::mmap(ptr::null_mut(), size, prot, flags | libc::MAP_HUGETLB, -1, 0) };
+        }
+        if ptr == libc::MAP_FAILED {
+            ptr = unsafe { libc::mmap(ptr::null_mut(), size, prot, flags, -1, 0) };
+            if ptr == libc::MAP_FAILED {
+                handle_alloc_error(layout);
+            }
+            // Only a hint, the ring works the same on regular pages.
+            #[cfg(feature = "huge-pages")]
+            if options.huge_pages != HugePages::Off {
+                unsafe { libc::madvise(ptr, size, libc::MADV_HUGEPAGE) };
+            }
+        }
+        // Nothing was touched yet, so no page was allocated on another node.
+        #[cfg(feature = "numa")]
+        if let Some(node) = options.numa_node {
+            prefer_node(ptr, size, node);
+        }
+        let ptr = NonNull::new(ptr as *mut UnsafeCell<MaybeUninit<T>>).unwrap();
+        Storage { ptr, len, layout, mapped: size }
+    }
+
+    /// Panics if the slots are in a mapping of their own (huge pages, NUMA
+    /// node), which `into_raw` cannot give up.
+    pub(crate) fn assert_not_mapped(&self) {
+        #[cfg(all(any(feature = "huge-pages", feature = "numa"), target_os = "linux"))]
+        assert_eq!(self.mapped, 0, "a ring in a mapping of its own cannot be taken apart");
+    }
+
+    /// Gives up ownership of the allocation, see `from_raw`.
+    /// # Panics
+    /// If the slots are in a mapping of their own, see `assert_not_mapped`.
+    pub(crate) fn into_raw(self) -> (*mut T, usize, usize) {
+        self.assert_not_mapped();
+        let parts = (self.ptr.as_ptr() as *mut T, self.len, self.layout.align());
+        core::mem::forget(self);
+        parts
+    }
+
+    /// # Safety
+    /// The parts must come from `into_raw` on a storage of the same `T`.
+    pub(crate) unsafe fn from_raw(ptr: *mut T, len: usize, align: usize) -> Self {
+        let aligned = if align > core::mem::align_of::<T>() {
+            AlignedStorage::CacheLine
+        } else {
+            AlignedStorage::Natural
+        };
+        let layout = StorageOptions::new().aligned(aligned).layout::<T>(len);
+        debug_assert_eq!(layout.align(), align);
+        Self::with_ptr(NonNull::new_unchecked(ptr as *mut UnsafeCell<MaybeUninit<T>>), len, layout)
+    }
+}
+
+impl<T> Deref for Storage<T> {
+    type Target = [UnsafeCell<MaybeUninit<T>>];
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl<T> DerefMut for Storage<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl<T> Drop for Storage<T> {
+    /// Only frees the memory, the ring drops the values it holds.
+    fn drop(&mut self) {
+        #[cfg(all(any(feature = "huge-pages", feature = "numa"), target_os = "linux"))]
+        if self.mapped != 0 {
+            unsafe { libc::munmap(self.ptr.as_ptr() as *mut libc::c_void, self.mapped) };
+            return;
+        }
+        if self.layout.size() != 0 {
+            unsafe { dealloc(self.ptr.as_ptr() as *mut u8, self.layout) };
+        }
+    }
+}
+
+/// Sets the `MPOL_PREFERRED` policy of `node` on the mapping, as libnuma's
+/// `numa_tonode_memory` does.
+#[cfg(all(feature = "numa", target_os = "linux"))]
+fn prefer_node(ptr: *mut libc::c_void, size: usize, node: u32) {
+    const BITS: usize = libc::c_ulong::BITS as usize;
+    let mut mask = [0 as libc::c_ulong; 1024 / BITS];
+    let node = node as usize;
+    if node >= mask.len() * BITS {
+        log::warn!("NUMA node {node} out of range, ring left unbound");
+        return;
+    }
+    mask[node / BITS] |= 1 << (node % BITS);
+    // The kernel ignores the last bit of `maxnode`.
+    let maxnode = mask.len() * BITS + 1;
+    let ret = unsafe { libc::syscall(libc::SYS_mbind, ptr, size, libc::MPOL_PREFERRED, mask.as_ptr(), maxnode, 0) };
+    if ret != 0 {
+        log::warn!("mbind to NUMA node {node} failed: {}", std::io::Error::last_os_error());
+    }
+}
+
+#[cfg(all(test, not(loom), any(feature = "huge-pages", feature = "numa"), target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::spsc_lockfree::SPSCRingBuffer;
+
+    #[test]
+    #[cfg(feature = "huge-pages")]
+    fn huge_pages() {
+        // No pool is reserved on most machines, `Explicit` then falls back.
+        for huge_pages in [HugePages::Transparent, HugePages::Explicit] {
+            let options = StorageOptions::new().huge_pages(huge_pages);
+            let rb = SPSCRingBuffer::<u64>::with_storage(1 << 20, options);
+            assert_eq!(rb.capacity(), 1 << 20);
+            for i in 0..(1 << 20) {
+                assert!(rb.push(i).is_ok());
+            }
+            assert!(rb.push(0).is_err());
+            assert_eq!(rb.pop().unwrap().1, 0);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "numa")]
+    fn numa_node() {
+        // Node 0 always exists, the policy is only a hint anyway.
+        for node in [0, 1 << 20] {
+            let rb = SPSCRingBuffer::<u64>::with_storage(1024, StorageOptions::new().numa_node(node));
+            assert!(rb.push(1).is_ok());
+            assert_eq!(rb.pop().unwrap().1, 1);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "huge-pages")]
+    #[should_panic(expected = "cannot be taken apart")]
+    fn huge_pages_into_raw_parts() {
+        let options = StorageOptions::new().huge_pages(HugePages::Transparent);
+        SPSCRingBuffer::<u8>::with_storage(8, options).into_raw_parts();
+    }
+
+    #[test]
+    #[cfg(feature = "huge-pages")]
+    #[should_panic(expected = "cannot be taken apart")]
+    fn huge_pages_into_raw_parts_with_values() {
+        // The ring is dropped while unwinding, queued values included.
+        let options = StorageOptions::new().huge_pages(HugePages::Transparent);
+        let rb = SPSCRingBuffer::<String>::with_storage(8, options);
+        assert!(rb.push("queued".to_string()).is_ok());
+        rb.into_raw_parts();
+    }
+}
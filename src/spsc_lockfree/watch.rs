@@ -0,0 +1,132 @@
+//! Latest-value channel for configuration or state propagation, built on an
+//! overwriting ring holding a single value: every `send` replaces the value
+//! the receiver has not seen yet. Values carry a version, incremented by
+//! each `send`, so the receiver can tell whether its copy is stale.
+
+use crate::sync::{Arc, Ordering};
+
+use super::SPSCRingBuffer;
+
+/// Creates a watch channel holding `initial` as version 0.
+pub fn watch<T>(initial: T) -> (WatchSender<T>, WatchReceiver<T>) {
+    let rb = Arc::new(SPSCRingBuffer::new_overwriting(1));
+    (
+        WatchSender { rb: rb.clone(), version: 0 },
+        WatchReceiver { rb, current: (0, initial) },
+    )
+}
+
+/// The sending half returned by `watch`.
+pub struct WatchSender<T> {
+    rb: Arc<SPSCRingBuffer<(u64, T)>>,
+    version: u64,
+}
+
+impl<T> WatchSender<T> {
+    /// Publishes a new value, replacing the unseen one if any.
+    /// Returns its version.
+    pub fn send(&mut self, value: T) -> u64 {
+        self.version += 1;
+        self.rb.force_push((self.version, value));
+        self.version
+    }
+
+    /// True once the receiver is dropped.
+    pub fn is_closed(&self) -> bool {
+        self.rb.disconnected.load(Ordering::Acquire)
+    }
+}
+
+impl<T> Drop for WatchSender<T> {
+    fn drop(&mut self) {
+        self.rb.disconnected.store(true, Ordering::Release);
+    }
+}
+
+/// The receiving half returned by `watch`, it keeps the latest value seen.
+pub struct WatchReceiver<T> {
+    rb: Arc<SPSCRingBuffer<(u64, T)>>,
+    current: (u64, T),
+}
+
+impl<T> WatchReceiver<T> {
+    /// True if a value newer than `current` was sent.
+    pub fn has_changed(&self) -> bool {
+        !self.rb.is_empty()
+    }
+
+    /// Returns the most recent value, after taking the newest sent one.
+    pub fn latest(&mut self) -> &T {
+        if let Some((_, value)) = self.rb.pop() {
+            self.current = value;
+        }
+        &self.current.1
+    }
+
+    /// Returns the value seen last, without looking for a newer one.
+    pub fn current(&self) -> &T {
+        &self.current.1
+    }
+
+    /// The version of the value returned by `current`.
+    pub fn version(&self) -> u64 {
+        self.current.0
+    }
+
+    /// True once the sender is dropped. The last value sent stays readable.
+    pub fn is_closed(&self) -> bool {
+        self.rb.disconnected.load(Ordering::Acquire)
+    }
+}
+
+impl<T> Drop for WatchReceiver<T> {
+    fn drop(&mut self) {
+        self.rb.disconnected.store(true, Ordering::Release);
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_latest() {
+        let (mut tx, mut rx) = watch("a".to_string());
+        assert!(!rx.has_changed());
+        assert_eq!(rx.latest(), "a");
+        assert_eq!(tx.send("b".to_string()), 1);
+        assert_eq!(tx.send("c".to_string()), 2);
+        assert!(rx.has_changed());
+        assert_eq!(rx.current(), "a");
+        assert_eq!(rx.latest(), "c");
+        assert_eq!(rx.version(), 2);
+        assert!(!rx.has_changed());
+        drop(tx);
+        assert!(rx.is_closed());
+        assert_eq!(rx.latest(), "c");
+    }
+
+    #[test]
+    fn versions_across_threads() {
+        let (mut tx, mut rx) = watch(0u64);
+        let sender = std::thread::spawn(move || {
+            for i in 1..=10_000 {
+                tx.send(i);
+            }
+        });
+        let mut last = 0;
+        loop {
+            let closed = rx.is_closed();
+            let value = *rx.latest();
+            // The version always matches its value, and never goes back.
+            assert_eq!(value, rx.version());
+            assert!(value >= last);
+            last = value;
+            if closed && !rx.has_changed() {
+                break;
+            }
+        }
+        sender.join().unwrap();
+        assert_eq!(last, 10_000);
+    }
+}
@@ -0,0 +1,71 @@
+//! Occupancy statistics of the rings, enabled by the `stats` feature.
+
+use crate::spsc_lockfree::CachePadded;
+use crate::sync::{AtomicUsize, Ordering};
+
+/// Counters of a ring at some point in time, see `SPSCRingBuffer::stats`.
+/// The counters wrap around on overflow.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StatsSnapshot {
+    /// Values published by the producer.
+    pub pushes: usize,
+    /// Values consumed, evicted values excluded.
+    pub pops: usize,
+    /// Pushes rejected because the ring was full.
+    pub full: usize,
+    /// Pops rejected because the ring was empty.
+    pub empty: usize,
+    /// Largest number of queued values seen by the producer.
+    pub high_water: usize,
+}
+
+/// Counters updated with relaxed atomics, each half writing its own cache
+/// line.
+#[derive(Debug, Default)]
+pub(crate) struct Stats {
+    producer: CachePadded<ProducerCounters>,
+    consumer: CachePadded<ConsumerCounters>,
+}
+
+#[derive(Debug, Default)]
+struct ProducerCounters {
+    pushes: AtomicUsize,
+    full: AtomicUsize,
+    high_water: AtomicUsize,
+}
+
+#[derive(Debug, Default)]
+struct ConsumerCounters {
+    pops: AtomicUsize,
+    empty: AtomicUsize,
+}
+
+impl Stats {
+    /// Counts `n` values published, leaving at most `len` queued.
+    pub(crate) fn pushed(&self, n: usize, len: usize) {
+        self.producer.pushes.fetch_add(n, Ordering::Relaxed);
+        self.producer.high_water.fetch_max(len, Ordering::Relaxed);
+    }
+
+    pub(crate) fn popped(&self, n: usize) {
+        self.consumer.pops.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub(crate) fn full(&self) {
+        self.producer.full.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn empty(&self) {
+        self.consumer.empty.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            pushes: self.producer.pushes.load(Ordering::Relaxed),
+            pops: self.consumer.pops.load(Ordering::Relaxed),
+            full: self.producer.full.load(Ordering::Relaxed),
+            empty: self.consumer.empty.load(Ordering::Relaxed),
+            high_water: self.producer.high_water.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -0,0 +1,68 @@
+//! Synchronization primitives used by the lock-free rings. Built with
+//! `--cfg loom` they come from `loom`, which model-checks the tests in the
+//! `loom_tests` modules over all the interleavings and memory orderings
+//! allowed by the atomics:
+//!
+//! RUSTFLAGS="--cfg loom" cargo test --release --lib loom_tests
+
+// Not every item is used by every feature combination.
+#[cfg(loom)]
+#[allow(unused_imports)]
+pub(crate) use loom::sync::atomic::{fence, AtomicBool, AtomicU16, AtomicU32, AtomicUsize, Ordering};
+#[cfg(all(loom, feature = "alloc"))]
+pub(crate) use loom::sync::Arc;
+
+#[cfg(all(not(loom), not(feature = "portable-atomic")))]
+#[allow(unused_imports)]
+pub(crate) use core::sync::atomic::{fence, AtomicBool, AtomicU16, AtomicU32, AtomicUsize, Ordering};
+#[cfg(all(not(loom), not(feature = "portable-atomic"), feature = "alloc"))]
+pub(crate) use alloc::sync::Arc;
+
+// For targets without native atomic read-modify-write operations.
+#[cfg(all(not(loom), feature = "portable-atomic"))]
+#[allow(unused_imports)]
+pub(crate) use portable_atomic::{fence, AtomicBool, AtomicU16, AtomicU32, AtomicUsize, Ordering};
+#[cfg(all(not(loom), feature = "portable-atomic", feature = "alloc"))]
+pub(crate) use portable_atomic_util::Arc;
+
+/// Hint for the body of a spin loop. Under loom it yields to the other
+/// threads of the model, which could never make progress otherwise.
+#[cfg(loom)]
+pub(crate) use loom::hint::spin_loop;
+#[cfg(not(loom))]
+pub(crate) use core::hint::spin_loop;
+
+/// Reports the accesses to the slots of a ring to loom, which flags the
+/// ones not ordered by the atomics as data races. The slots themselves stay
+/// plain `UnsafeCell`s so their layout does not change under loom.
+/// Without loom this is a zero-sized no-op.
+#[cfg(feature = "alloc")]
+#[derive(Debug)]
+pub(crate) struct SlotTracker {
+    #[cfg(loom)]
+    slots: Vec<loom::cell::UnsafeCell<()>>,
+}
+
+#[cfg(feature = "alloc")]
+impl SlotTracker {
+    pub(crate) fn new(_capacity: usize) -> Self {
+        SlotTracker {
+            #[cfg(loom)]
+            slots: (0.._capacity).map(|_| loom::cell::UnsafeCell::new(())).collect(),
+        }
+    }
+
+    /// Called when the value in `slot` is read (or moved out).
+    #[inline(always)]
+    pub(crate) fn read(&self, _slot: usize) {
+        #[cfg(loom)]
+        self.slots[_slot].with(|_| ());
+    }
+
+    /// Called when a value is written into `slot`.
+    #[inline(always)]
+    pub(crate) fn write(&self, _slot: usize) {
+        #[cfg(loom)]
+        self.slots[_slot].with_mut(|_| ());
+    }
+}
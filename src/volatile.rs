@@ -0,0 +1,132 @@
+//! Rings over memory shared with a peripheral, such as the RAM of a DMA
+//! engine, a coprocessor's mailbox or a hardware FIFO mapped into the
+//! address space, where the other side is not a thread and the compiler
+//! must not elide, merge or reorder the accesses. Slots and indices are
+//! only accessed with `read_volatile`/`write_volatile`, and fences order
+//! the slot accesses against the index updates.
+//! The layout is the caller's: a slot array of a power-of-two length, and
+//! two free-running `u32` indices wherever the peripheral expects them.
+//! Each side only writes its own index: the CPU is the producer of a TX
+//! ring and the consumer of an RX ring.
+
+use core::ptr::NonNull;
+// Orders accesses to device memory, which loom does not model.
+use core::sync::atomic::{fence, Ordering};
+
+use crate::error::PushError;
+
+/// A view of a ring in shared memory, see the module documentation.
+pub struct VolatileRing<T> {
+    slots: NonNull<T>,
+    read: NonNull<u32>,
+    write: NonNull<u32>,
+    mask: u32,
+}
+
+// Only `Copy` values cross the shared memory.
+unsafe impl<T: Send> Send for VolatileRing<T> {}
+
+impl<T: Copy> VolatileRing<T> {
+    /// Creates a view of the ring of `capacity` slots at `slots`, with its
+    /// indices at `read` and `write`. An empty ring has equal indices.
+    /// # Safety
+    /// All the pointers must be valid and aligned for volatile accesses
+    /// for as long as the view lives, with `capacity` slots at `slots`.
+    /// Only one view may push, and only one may pop.
+    /// # Panics
+    /// If `capacity` is not a power of two, or does not fit in a `u32`.
+    pub unsafe fn new(slots: NonNull<T>, capacity: usize, read: NonNull<u32>, write: NonNull<u32>) -> Self {
+        assert!(capacity.is_power_of_two() && capacity <= 1 << 31, "capacity must be a power of two up to 2^31");
+        VolatileRing { slots, read, write, mask: capacity as u32 - 1 }
+    }
+
+    /// Pushes a value, or hands it back in `PushError::Full`.
+    pub fn try_push(&mut self, value: T) -> Result<(), PushError<T>> {
+        let write = unsafe { self.write.as_ptr().read_volatile() };
+        let read = unsafe { self.read.as_ptr().read_volatile() };
+        if write.wrapping_sub(read) > self.mask {
+            return Err(PushError::Full(value));
+        }
+        // The consumer is done reading the slot before it moved `read`.
+        fence(Ordering::Acquire);
+        unsafe { self.slot(write).write_volatile(value) };
+        // The value lands before the index announcing it.
+        fence(Ordering::Release);
+        unsafe { self.write.as_ptr().write_volatile(write.wrapping_add(1)) };
+        Ok(())
+    }
+
+    /// Pops the oldest value, if any.
+    pub fn pop(&mut self) -> Option<T> {
+        let read = unsafe { self.read.as_ptr().read_volatile() };
+        if unsafe { self.write.as_ptr().read_volatile() } == read {
+            return None;
+        }
+        // The value is read after the index announcing it.
+        fence(Ordering::Acquire);
+        let value = unsafe { self.slot(read).read_volatile() };
+        // Read before the slot is handed back.
+        fence(Ordering::Release);
+        unsafe { self.read.as_ptr().write_volatile(read.wrapping_add(1)) };
+        Some(value)
+    }
+
+    /// Returns the number of values queued, which the other side may
+    /// change at any time.
+    pub fn len(&self) -> usize {
+        let read = unsafe { self.read.as_ptr().read_volatile() };
+        let write = unsafe { self.write.as_ptr().read_volatile() };
+        write.wrapping_sub(read) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.mask as usize + 1
+    }
+
+    /// Returns the slot of the index `i`, within the slots by `new`.
+    fn slot(&self, i: u32) -> *mut T {
+        unsafe { self.slots.as_ptr().add((i & self.mask) as usize) }
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn producer_and_consumer_views() {
+        let mut slots = [0u16; 4];
+        let (mut read, mut write) = (0u32, 0u32);
+        let (slots, read, write) = (NonNull::from(&mut slots).cast(), NonNull::from(&mut read), NonNull::from(&mut write));
+        // Two views of the same memory, as the CPU and the device would
+        // have.
+        let mut tx = unsafe { VolatileRing::<u16>::new(slots, 4, read, write) };
+        let mut rx = unsafe { VolatileRing::<u16>::new(slots, 4, read, write) };
+        for i in 0..4 {
+            assert!(tx.try_push(i).is_ok());
+        }
+        assert_eq!(tx.try_push(4), Err(PushError::Full(4)));
+        assert_eq!(rx.len(), 4);
+        assert_eq!(rx.pop(), Some(0));
+        assert_eq!(rx.pop(), Some(1));
+        // Wraps around the slots.
+        assert!(tx.try_push(4).is_ok());
+        let popped: Vec<_> = core::iter::from_fn(|| rx.pop()).collect();
+        assert_eq!(popped, [2, 3, 4]);
+        assert!(tx.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "capacity must be a power of two")]
+    fn capacity_power_of_two() {
+        let mut slots = [0u8; 3];
+        let (mut read, mut write) = (0u32, 0u32);
+        let _ = unsafe {
+            VolatileRing::new(NonNull::from(&mut slots).cast::<u8>(), 3, NonNull::from(&mut read), NonNull::from(&mut write))
+        };
+    }
+}
@@ -0,0 +1,200 @@
+//! Wait strategies used by the blocking operations of the ring buffers.
+//! A blocking `push`/`pop` retries the non-blocking operation in a loop and
+//! calls `WaitStrategy::wait` between attempts. The strategy decides how to
+//! spend that time: burning CPU for the lowest latency, or giving the core
+//! back to the OS to save power.
+//! Without the `std` feature there is no scheduler to yield to nor clock:
+//! only the spinning strategies exist and deadlines are never set.
+//! On `wasm32-unknown-unknown` std has neither either: parking traps on a
+//! browser's main thread and reading the clock panics, so stick to the
+//! spinning strategies and the calls without a timeout there.
+
+#[cfg(feature = "std")]
+use std::thread;
+#[cfg(feature = "std")]
+use std::time::Duration;
+#[cfg(feature = "std")]
+pub(crate) use std::time::Instant;
+
+/// Stand-in for `std::time::Instant` without std: it has no values, since
+/// there is no clock to set a deadline from.
+#[cfg(not(feature = "std"))]
+#[derive(Debug, Clone, Copy)]
+pub enum Instant {}
+
+/// True once `deadline` has passed, never without a deadline.
+#[cfg(all(feature = "alloc", feature = "std"))]
+pub(crate) fn expired(deadline: Option<Instant>) -> bool {
+    deadline.is_some_and(|d| Instant::now() >= d)
+}
+
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+pub(crate) fn expired(deadline: Option<Instant>) -> bool {
+    match deadline {
+        None => false,
+        Some(never) => match never {},
+    }
+}
+
+/// How a blocked producer or consumer waits before its next attempt.
+pub trait WaitStrategy {
+    /// Called after the `step`-th failed attempt (starting at 0) of the
+    /// current operation. Must not sleep past `deadline` when there is one.
+    fn wait(&self, step: u32, deadline: Option<Instant>);
+}
+
+/// Spins on the CPU, never gives the core away.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BusySpin;
+
+impl WaitStrategy for BusySpin {
+    fn wait(&self, _step: u32, _deadline: Option<Instant>) {
+        core::hint::spin_loop();
+    }
+}
+
+/// Spins for `spins` attempts, then yields the thread to the scheduler
+/// (keeps spinning without std).
+#[derive(Debug, Clone, Copy)]
+pub struct SpinThenYield {
+    pub spins: u32,
+}
+
+impl Default for SpinThenYield {
+    fn default() -> Self {
+        SpinThenYield { spins: 64 }
+    }
+}
+
+impl WaitStrategy for SpinThenYield {
+    fn wait(&self, step: u32, _deadline: Option<Instant>) {
+        if step < self.spins || cfg!(not(feature = "std")) {
+            core::hint::spin_loop();
+        } else {
+            #[cfg(feature = "std")]
+            thread::yield_now();
+        }
+    }
+}
+
+/// Parks the thread for a duration doubling on each attempt, from 1µs up
+/// to `max_park`. The other side does not need to unpark us, the latency
+/// is bounded by `max_park` instead.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy)]
+pub struct Park {
+    pub max_park: Duration,
+}
+
+#[cfg(feature = "std")]
+impl Default for Park {
+    fn default() -> Self {
+        Park { max_park: Duration::from_millis(1) }
+    }
+}
+
+#[cfg(feature = "std")]
+impl WaitStrategy for Park {
+    fn wait(&self, step: u32, deadline: Option<Instant>) {
+        let mut park = Duration::from_micros(1 << step.min(20)).min(self.max_park);
+        if let Some(deadline) = deadline {
+            park = park.min(deadline.saturating_duration_since(Instant::now()));
+        }
+        thread::park_timeout(park);
+    }
+}
+
+/// Spins, then yields, then parks: low latency for short waits without
+/// burning a core during long ones.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy)]
+pub struct Hybrid {
+    pub spins: u32,
+    pub yields: u32,
+    pub park: Park,
+}
+
+#[cfg(feature = "std")]
+impl Default for Hybrid {
+    fn default() -> Self {
+        Hybrid { spins: 64, yields: 64, park: Park::default() }
+    }
+}
+
+#[cfg(feature = "std")]
+impl WaitStrategy for Hybrid {
+    fn wait(&self, step: u32, deadline: Option<Instant>) {
+        if step < self.spins {
+            core::hint::spin_loop();
+        } else if step < self.spins + self.yields {
+            thread::yield_now();
+        } else {
+            self.park.wait(step - self.spins - self.yields, deadline);
+        }
+    }
+}
+
+/// Exponential backoff between the retries of a contended CAS, such as
+/// the producers claiming slots of a multi-producer ring: spins for 1, 2,
+/// 4... up to 64 iterations, then yields, then parks for a duration
+/// doubling from 1µs up to 1ms (keeps spinning without std, and yielding
+/// on `wasm32-unknown-unknown`, see the module documentation). Each failed
+/// CAS means another thread made progress, backing off lets it finish
+/// instead of invalidating the contended cache line again right away.
+#[cfg(feature = "mpmc")]
+#[derive(Debug, Default)]
+pub(crate) struct Backoff {
+    step: u32,
+}
+
+#[cfg(feature = "mpmc")]
+impl Backoff {
+    const SPIN_LIMIT: u32 = 6;
+    const YIELD_LIMIT: u32 = 10;
+
+    pub(crate) fn snooze(&mut self) {
+        // Under loom spinning makes no progress: each retry yields to the
+        // other threads of the model once instead.
+        if cfg!(loom) {
+            crate::sync::spin_loop();
+            return;
+        }
+        if self.step <= Self::SPIN_LIMIT || cfg!(not(feature = "std")) {
+            for _ in 0..1 << self.step.min(Self::SPIN_LIMIT) {
+                core::hint::spin_loop();
+            }
+        } else if self.step <= Self::YIELD_LIMIT || cfg!(all(target_family = "wasm", target_os = "unknown")) {
+            #[cfg(feature = "std")]
+            thread::yield_now();
+        } else {
+            #[cfg(feature = "std")]
+            thread::park_timeout(Duration::from_micros(1 << (self.step - Self::YIELD_LIMIT).min(10)));
+        }
+        self.step = self.step.saturating_add(1);
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn park_respects_deadline() {
+        let park = Park { max_park: Duration::from_secs(10) };
+        let start = Instant::now();
+        park.wait(30, Some(start + Duration::from_millis(5)));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[cfg(feature = "mpmc")]
+    #[test]
+    fn backoff_parks_briefly() {
+        let mut backoff = Backoff::default();
+        let start = Instant::now();
+        for _ in 0..30 {
+            backoff.snooze();
+        }
+        // 20 parks of at most 1ms each.
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
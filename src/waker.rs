@@ -0,0 +1,143 @@
+//! Slots holding the `Waker`s of the tasks blocked on one side of a ring.
+//! Registering takes a lock, but waking is a fence plus a relaxed load
+//! while nobody is registered, so the non-async hot paths stay cheap.
+
+use std::collections::VecDeque;
+use std::mem;
+use std::sync::atomic::{fence, AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::task::Waker;
+
+#[derive(Debug, Default)]
+pub(crate) struct WakerSlot {
+    waker: Mutex<Option<Waker>>,
+    registered: AtomicBool,
+}
+
+impl WakerSlot {
+    /// Stores the waker of the current task. The caller must check the ring
+    /// again after this: the fence pairs with the one in `wake` so either the
+    /// waker sees the new state of the ring, or `wake` sees the waker.
+    pub(crate) fn register(&self, waker: &Waker) {
+        let mut slot = self.waker.lock().unwrap();
+        match &*slot {
+            Some(w) if w.will_wake(waker) => {}
+            _ => *slot = Some(waker.clone()),
+        }
+        self.registered.store(true, Ordering::Relaxed);
+        drop(slot);
+        fence(Ordering::SeqCst);
+    }
+
+    /// Wakes the registered task, if any. Must be called after the ring
+    /// state change the task may be waiting for was published.
+    pub(crate) fn wake(&self) {
+        fence(Ordering::SeqCst);
+        if self.registered.load(Ordering::Relaxed) && self.registered.swap(false, Ordering::Acquire) {
+            if let Some(waker) = self.waker.lock().unwrap().take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/// Wakers of the tasks blocked on the shared side of a ring, such as the
+/// senders of an MPSC channel, woken one at a time in FIFO order. Each
+/// future keeps the key of its entry, so that a future dropped after being
+/// woken can pass the wakeup on instead of losing it.
+#[cfg_attr(not(feature = "mpsc"), allow(dead_code))]
+#[derive(Debug, Default)]
+pub(crate) struct WakerList {
+    waiters: Mutex<Waiters>,
+    /// Number of entries in `waiters`.
+    registered: AtomicUsize,
+}
+
+#[cfg_attr(not(feature = "mpsc"), allow(dead_code))]
+#[derive(Debug, Default)]
+struct Waiters {
+    next_key: usize,
+    queue: VecDeque<(usize, Waker)>,
+}
+
+#[cfg_attr(not(feature = "mpsc"), allow(dead_code))]
+impl WakerList {
+    /// Stores the waker of the current task under `key`, which is assigned
+    /// on the first call. Same caveat as `WakerSlot::register`.
+    pub(crate) fn register(&self, key: &mut Option<usize>, waker: &Waker) {
+        let mut waiters = self.waiters.lock().unwrap();
+        let entry = key.and_then(|key| waiters.queue.iter().position(|(k, _)| *k == key));
+        match entry {
+            Some(i) => {
+                if !waiters.queue[i].1.will_wake(waker) {
+                    waiters.queue[i].1 = waker.clone();
+                }
+            }
+            // First registration, or woken since the last one.
+            None => {
+                let k = *key.get_or_insert_with(|| {
+                    waiters.next_key = waiters.next_key.wrapping_add(1);
+                    waiters.next_key
+                });
+                waiters.queue.push_back((k, waker.clone()));
+                self.registered.store(waiters.queue.len(), Ordering::Relaxed);
+            }
+        }
+        drop(waiters);
+        fence(Ordering::SeqCst);
+    }
+
+    /// Wakes the oldest registered task, if any. Must be called after the
+    /// ring state change the tasks may be waiting for was published.
+    pub(crate) fn wake_one(&self) {
+        fence(Ordering::SeqCst);
+        if self.registered.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        let mut waiters = self.waiters.lock().unwrap();
+        let woken = waiters.queue.pop_front();
+        self.registered.store(waiters.queue.len(), Ordering::Relaxed);
+        drop(waiters);
+        if let Some((_, waker)) = woken {
+            waker.wake();
+        }
+    }
+
+    /// Wakes all the registered tasks, e.g. on disconnection.
+    pub(crate) fn wake_all(&self) {
+        fence(Ordering::SeqCst);
+        if self.registered.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        let woken = mem::take(&mut self.waiters.lock().unwrap().queue);
+        self.registered.store(0, Ordering::Relaxed);
+        for (_, waker) in woken {
+            waker.wake();
+        }
+    }
+
+    /// Removes the entry of a future that completed.
+    pub(crate) fn remove(&self, key: usize) {
+        self.unregister(key);
+    }
+
+    /// Removes the entry of a future dropped before completing. If it was
+    /// already woken, the wakeup goes to the next task, as whatever it was
+    /// woken for may still be there.
+    pub(crate) fn cancel(&self, key: usize) {
+        if !self.unregister(key) {
+            self.wake_one();
+        }
+    }
+
+    /// Returns false if there was no entry for `key`.
+    fn unregister(&self, key: usize) -> bool {
+        let mut waiters = self.waiters.lock().unwrap();
+        let Some(i) = waiters.queue.iter().position(|(k, _)| *k == key) else {
+            return false;
+        };
+        waiters.queue.remove(i);
+        self.registered.store(waiters.queue.len(), Ordering::Relaxed);
+        true
+    }
+}